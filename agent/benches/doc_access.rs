@@ -0,0 +1,187 @@
+//! Compares the targeted document access used by `DocWebAgent` and the editor side
+//! of the agent with hydrating and reconciling the whole `LspAgent`, on a document
+//! holding a large workspace.
+//!
+//! Run with `cargo bench -p agent --bench doc_access`.
+
+use agent::{
+    AuditEntry, DocWebAgent, DocumentContent, LspAgent, NoStorage, StoredValue, TextDocument,
+    document_agent,
+};
+use automerge_repo::{DocHandle, Repo, RepoHandle};
+use autosurgeon::{Text, hydrate, reconcile};
+use criterion::{Criterion, criterion_group, criterion_main};
+use tokio::runtime::Runtime;
+use traits::{TextChange, TextPosition, TextRange, WebAgent};
+
+const DOCUMENTS: usize = 50;
+const APPS: usize = 10;
+const DOCUMENT_BYTES: usize = 20_000;
+
+fn workspace() -> LspAgent {
+    let mut agent = LspAgent::default();
+    for i in 0..DOCUMENTS {
+        agent.text_documents.documents.insert(
+            format!("file:///src/file_{}.rs", i),
+            TextDocument {
+                text: Text::with_value("fn main() {}\n".repeat(DOCUMENT_BYTES / 13)),
+                ..Default::default()
+            },
+        );
+    }
+    for i in 0..APPS {
+        agent.webviews.documents.insert(
+            format!("app-{}", i),
+            DocumentContent {
+                text: "<div>app</div>\n".repeat(DOCUMENT_BYTES / 15),
+            },
+        );
+    }
+    agent.stored_values.insert(
+        "counter".to_string(),
+        StoredValue {
+            value: "0".to_string(),
+            description: "a counter".to_string(),
+        },
+    );
+    agent
+}
+
+fn populated_repo(runtime: &Runtime) -> (RepoHandle, DocHandle) {
+    runtime.block_on(async {
+        let repo_handle = Repo::new(None, Box::new(NoStorage)).run();
+        let doc_handle = repo_handle.new_document();
+        doc_handle.with_doc_mut(|doc| {
+            let mut tx = doc.transaction();
+            reconcile(&mut tx, workspace()).unwrap();
+            tx.commit();
+        });
+        (repo_handle, doc_handle)
+    })
+}
+
+fn populated_doc(runtime: &Runtime) -> DocHandle {
+    populated_repo(runtime).1
+}
+
+/// What every operation did before targeted access: hydrate everything, change one
+/// field, reconcile everything.
+fn full_round_trip(doc_handle: &DocHandle, update: impl FnOnce(&mut LspAgent)) {
+    doc_handle.with_doc_mut(|doc| {
+        let mut agent: LspAgent = hydrate(doc).unwrap();
+        update(&mut agent);
+        let mut tx = doc.transaction();
+        reconcile(&mut tx, &agent).unwrap();
+        tx.commit();
+    });
+}
+
+fn audit_entry(action: &str, key: &str) -> AuditEntry {
+    AuditEntry {
+        app_id: "app-0".to_string(),
+        action: action.to_string(),
+        target: key.to_string(),
+        ..Default::default()
+    }
+}
+
+fn read_value(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("read_value");
+
+    let doc_handle = populated_doc(&runtime);
+    let web_agent = DocWebAgent::new(doc_handle);
+    group.bench_function("targeted", |b| {
+        b.to_async(&runtime)
+            .iter(|| web_agent.read_value("app-0".to_string(), "counter".to_string()))
+    });
+
+    let doc_handle = populated_doc(&runtime);
+    group.bench_function("full", |b| {
+        b.iter(|| {
+            full_round_trip(&doc_handle, |agent| {
+                let _ = agent.stored_values.get("counter").map(|v| v.value.clone());
+                agent.audit_log.push(audit_entry("read_value", "counter"));
+            })
+        })
+    });
+    group.finish();
+}
+
+fn store_value(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("store_value");
+
+    let doc_handle = populated_doc(&runtime);
+    let web_agent = DocWebAgent::new(doc_handle);
+    group.bench_function("targeted", |b| {
+        b.to_async(&runtime).iter(|| {
+            web_agent.store_value(
+                "app-0".to_string(),
+                "counter".to_string(),
+                "1".to_string(),
+                "a counter".to_string(),
+            )
+        })
+    });
+
+    let doc_handle = populated_doc(&runtime);
+    group.bench_function("full", |b| {
+        b.iter(|| {
+            full_round_trip(&doc_handle, |agent| {
+                agent.stored_values.insert(
+                    "counter".to_string(),
+                    StoredValue {
+                        value: "1".to_string(),
+                        description: "a counter".to_string(),
+                    },
+                );
+                agent.audit_log.push(audit_entry("store_value", "counter"));
+            })
+        })
+    });
+    group.finish();
+}
+
+/// A keystroke in an open document.
+fn did_change(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let mut group = c.benchmark_group("did_change");
+    let uri = "file:///src/file_0.rs";
+    let start = TextPosition {
+        line: 0,
+        character: 0,
+    };
+
+    let (repo_handle, doc_handle) = populated_repo(&runtime);
+    let workspace_agent = document_agent(repo_handle, doc_handle);
+    let mut version = 0;
+    group.bench_function("targeted", |b| {
+        b.to_async(&runtime).iter(|| {
+            version += 1;
+            workspace_agent.did_change(
+                uri.to_string(),
+                version,
+                vec![TextChange {
+                    range: Some(TextRange { start, end: start }),
+                    text: "x".to_string(),
+                }],
+            )
+        })
+    });
+
+    let doc_handle = populated_doc(&runtime);
+    group.bench_function("full", |b| {
+        b.iter(|| {
+            full_round_trip(&doc_handle, |agent| {
+                let document = agent.text_documents.documents.get_mut(uri).unwrap();
+                document.text.splice(0, 0, "x");
+                document.version += 1;
+            })
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, read_value, store_value, did_change);
+criterion_main!(benches);
@@ -0,0 +1,128 @@
+use crate::app_html::clean_html;
+use boa_ast::scope::Scope;
+use boa_interner::Interner;
+use boa_parser::{Parser, Source};
+
+/// Script types browsers run; JSON data blocks and templates are not checked.
+fn is_javascript(script_type: Option<&str>) -> bool {
+    matches!(
+        script_type
+            .map(|t| t.trim().to_ascii_lowercase())
+            .as_deref(),
+        None | Some("") | Some("text/javascript") | Some("application/javascript") | Some("module")
+    )
+}
+
+fn script_type(attributes: &str) -> Option<&str> {
+    let lower = attributes.to_ascii_lowercase();
+    let start = lower.find("type")?;
+    let rest = attributes[start + "type".len()..]
+        .trim_start()
+        .strip_prefix('=')?;
+    let rest = rest.trim_start();
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let value = &rest[1..];
+    value.find(quote).map(|end| &value[..end])
+}
+
+/// The syntax error in `source`, with the offending line, if any.
+fn syntax_error(source: &str, module: bool) -> Option<String> {
+    let mut parser = Parser::new(Source::from_bytes(source));
+    let mut interner = Interner::default();
+    let scope = Scope::new_global();
+    let error = if module {
+        parser.parse_module(&scope, &mut interner).err()
+    } else {
+        parser.parse_script(&scope, &mut interner).err()
+    }?;
+    let message = error.to_string();
+    let line = message
+        .split("line ")
+        .nth(1)
+        .and_then(|rest| rest.split(|c: char| !c.is_ascii_digit()).next())
+        .and_then(|line| line.parse::<usize>().ok())
+        .and_then(|line| source.lines().nth(line.saturating_sub(1)))
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    Some(match line {
+        Some(line) => format!("{} (`{}`)", message, line),
+        None => message,
+    })
+}
+
+/// Problems that would keep a generated app from loading: a missing doctype,
+/// markup cut off before it ends, or scripts that do not parse. Empty if none
+/// were found.
+pub fn check(content: &str) -> Vec<String> {
+    let html = clean_html(content);
+    let lower = html.to_ascii_lowercase();
+    let mut problems = Vec::new();
+    if !lower.starts_with("<!doctype html") {
+        problems.push("The app does not start with <!DOCTYPE html>.".to_string());
+    }
+    if lower.contains("<html") && !lower.contains("</html>") {
+        problems.push("The app has no closing </html> tag; it may be cut off.".to_string());
+    }
+
+    let mut index = 0;
+    let mut from = 0;
+    while let Some(found) = lower[from..].find("<script") {
+        let start = from + found;
+        index += 1;
+        let Some(body_start) = lower[start..].find('>').map(|end| start + end + 1) else {
+            problems.push(format!(
+                "Script {} has an unterminated <script> tag.",
+                index
+            ));
+            break;
+        };
+        let Some(body_end) = lower[body_start..]
+            .find("</script")
+            .map(|end| body_start + end)
+        else {
+            problems.push(format!("Script {} is not closed with </script>.", index));
+            break;
+        };
+        let attributes = &html[start + "<script".len()..body_start - 1];
+        let kind = script_type(attributes);
+        if is_javascript(kind)
+            && let Some(error) = syntax_error(
+                &html[body_start..body_end],
+                kind.is_some_and(|t| t.trim().eq_ignore_ascii_case("module")),
+            )
+        {
+            problems.push(format!("Script {} has a syntax error: {}", index, error));
+        }
+        from = body_end;
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let valid = "```html\n<!DOCTYPE html>\n<html><body><script>const a = 1;</script>\
+                     <script type=\"module\">export const b = 2;</script>\
+                     <script type=\"application/json\">{\"not\": js}</script></body></html>\n```";
+        assert!(check(valid).is_empty(), "{:?}", check(valid));
+
+        let broken = "<html><body><script>\nconst a = 1;\nif (a {\n</script></body></html>";
+        let problems = check(broken);
+        assert_eq!(problems.len(), 2, "{:?}", problems);
+        assert_eq!(problems[0], "The app does not start with <!DOCTYPE html>.");
+        assert!(problems[1].starts_with("Script 1 has a syntax error:"));
+        assert!(problems[1].ends_with("(`if (a {`)"), "{}", problems[1]);
+
+        let cut_off = "<!DOCTYPE html><html><body><script>let x = 1;";
+        assert_eq!(
+            check(cut_off),
+            vec![
+                "The app has no closing </html> tag; it may be cut off.".to_string(),
+                "Script 1 is not closed with </script>.".to_string(),
+            ]
+        );
+    }
+}
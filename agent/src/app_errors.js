@@ -0,0 +1,33 @@
+// Forwards an app's console errors, uncaught exceptions and unhandled
+// rejections to the agent through wry://log. Runtimes inject it before the
+// app's own scripts run.
+(() => {
+    const send = (message) => {
+        fetch('wry://log', { method: 'POST', body: message }).catch(() => {});
+    };
+    const describe = (value) => {
+        if (value instanceof Error) {
+            return `${value.name}: ${value.message}`;
+        }
+        if (value !== null && typeof value === 'object') {
+            try {
+                return JSON.stringify(value);
+            } catch (e) {}
+        }
+        return String(value);
+    };
+
+    const error = console.error.bind(console);
+    console.error = (...args) => {
+        error(...args);
+        send(`console.error: ${args.map(describe).join(' ')}`);
+    };
+    window.addEventListener('error', (event) => {
+        const message = event.error ? describe(event.error) : event.message;
+        const where = event.lineno ? ` at line ${event.lineno}:${event.colno}` : '';
+        send(`${message}${where}`);
+    });
+    window.addEventListener('unhandledrejection', (event) => {
+        send(`Unhandled promise rejection: ${describe(event.reason)}`);
+    });
+})();
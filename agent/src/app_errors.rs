@@ -0,0 +1,51 @@
+/// Script the web runtimes inject into every app so that its errors reach the
+/// agent through `wry://log`.
+pub const REPORTER_SCRIPT: &str = include_str!("app_errors.js");
+
+/// Errors kept per app; an app failing in a loop would otherwise flood the doc.
+const MAX_APP_ERRORS: usize = 10;
+const MAX_ERROR_CHARS: usize = 1000;
+
+/// Appends `message` to an app's errors. A repeated error moves to the end
+/// instead of being kept twice, and only the most recent ones are kept.
+pub(crate) fn recorded(mut errors: Vec<String>, message: &str) -> Vec<String> {
+    let message: String = message.trim().chars().take(MAX_ERROR_CHARS).collect();
+    if message.is_empty() {
+        return errors;
+    }
+    errors.retain(|error| error != &message);
+    errors.push(message);
+    let excess = errors.len().saturating_sub(MAX_APP_ERRORS);
+    errors.drain(..excess);
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recorded() {
+        let errors = recorded(Vec::new(), " TypeError: a is undefined at line 42:7\n");
+        assert_eq!(errors, vec!["TypeError: a is undefined at line 42:7"]);
+
+        let errors = recorded(errors, "console.error: failed");
+        let errors = recorded(errors, "TypeError: a is undefined at line 42:7");
+        assert_eq!(
+            errors,
+            vec![
+                "console.error: failed",
+                "TypeError: a is undefined at line 42:7"
+            ]
+        );
+        assert_eq!(recorded(errors.clone(), "  "), errors);
+
+        let errors = (0..15).fold(Vec::new(), |errors, i| recorded(errors, &i.to_string()));
+        assert_eq!(errors.len(), MAX_APP_ERRORS);
+        assert_eq!(errors[0], "5");
+        assert_eq!(
+            recorded(Vec::new(), &"x".repeat(5000))[0].len(),
+            MAX_ERROR_CHARS
+        );
+    }
+}
@@ -0,0 +1,46 @@
+/// Strips the markdown code fence the model sometimes wraps an app in, as
+/// every web runtime does before loading it.
+pub fn clean_html(content: &str) -> &str {
+    let content = content.trim();
+    let content = content
+        .strip_prefix("```html")
+        .or_else(|| content.strip_prefix("```"))
+        .unwrap_or(content);
+    content.strip_suffix("```").unwrap_or(content).trim()
+}
+
+/// Returns the contents of the app's `<title>` element, if it has a non-empty one.
+pub fn title(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let open = lower.find("<title")?;
+    let start = open + lower[open..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    let title = html[start..end].trim();
+    (!title.is_empty()).then(|| title.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_html() {
+        assert_eq!(clean_html("```html\n<p>hi</p>\n```"), "<p>hi</p>");
+        assert_eq!(clean_html("```\n<p>hi</p>```"), "<p>hi</p>");
+        assert_eq!(clean_html(" <p>hi</p>\n"), "<p>hi</p>");
+    }
+
+    #[test]
+    fn test_title() {
+        assert_eq!(
+            title("<html><head><TITLE> Todo </TITLE></head></html>").as_deref(),
+            Some("Todo")
+        );
+        assert_eq!(
+            title("```html\n<title>Fenced</title>\n```").as_deref(),
+            Some("Fenced")
+        );
+        assert_eq!(title("<html><title></title></html>"), None);
+        assert_eq!(title("<html></html>"), None);
+    }
+}
@@ -0,0 +1,242 @@
+use crate::doc_paths::{self, Readable, Writable};
+use crate::{AuditEntry, saved_apps};
+use std::io::Write;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Workspace-relative file that older audit entries are moved to.
+pub(crate) const ARCHIVE_FILE: &str = ".lsp_agent/audit.jsonl";
+
+/// Entries kept in the shared document. Beyond this, the older half is moved to
+/// the archive file so a busy app cannot grow the document without bound.
+const MAX_AUDIT_ENTRIES: usize = 1000;
+
+/// Appends an entry to the audit log. Entries are never modified or removed:
+/// they only move to the archive file, and stay in the document when there is
+/// no workspace folder to archive into or the archive cannot be written.
+pub(crate) fn record<D: Writable>(
+    doc: &mut D,
+    app_id: &str,
+    action: &str,
+    target: &str,
+    request_bytes: usize,
+    response_bytes: usize,
+) {
+    let entry = AuditEntry {
+        timestamp_ms: now_ms(),
+        app_id: app_id.to_string(),
+        action: action.to_string(),
+        target: target.to_string(),
+        request_bytes: request_bytes as u64,
+        response_bytes: response_bytes as u64,
+    };
+    append(doc, entry, MAX_AUDIT_ENTRIES);
+}
+
+fn append<D: Writable>(doc: &mut D, entry: AuditEntry, max_entries: usize) {
+    let len = doc_paths::push(doc, &[], "audit_log", entry);
+    if len <= max_entries {
+        return;
+    }
+    let Ok(root) = saved_apps::workspace_root(doc) else {
+        return;
+    };
+    let entries: Vec<AuditEntry> = doc_paths::get_list(doc, &[], "audit_log");
+    let moved = len - max_entries / 2;
+    match archive(&root, &entries[..moved]) {
+        Ok(()) => doc_paths::drain_front(doc, &[], "audit_log", moved),
+        Err(e) => eprintln!("[LSP Agent] Failed to archive audit log entries: {}", e),
+    }
+}
+
+fn archive(root: &Path, entries: &[AuditEntry]) -> std::io::Result<()> {
+    let path = root.join(ARCHIVE_FILE);
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut lines = String::new();
+    for entry in entries {
+        lines.push_str(&serde_json::to_string(entry)?);
+        lines.push('\n');
+    }
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(lines.as_bytes())
+}
+
+/// The whole audit log, oldest entry first: the archived entries followed by
+/// the ones still in the document.
+pub(crate) fn entries<D: Readable>(doc: &D) -> Vec<AuditEntry> {
+    let mut entries: Vec<AuditEntry> = saved_apps::workspace_root(doc)
+        .ok()
+        .and_then(|root| std::fs::read_to_string(root.join(ARCHIVE_FILE)).ok())
+        .map(|archived| {
+            archived
+                .lines()
+                .filter_map(|line| serde_json::from_str(line).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    entries.extend(doc_paths::get_list::<_, AuditEntry>(doc, &[], "audit_log"));
+    entries
+}
+
+/// Renders the audit log as a markdown table, oldest entry first.
+pub(crate) fn render(entries: &[AuditEntry]) -> String {
+    let mut out = String::from("# LSP Agent Audit Log\n\n");
+    if entries.is_empty() {
+        out.push_str("No app actions recorded yet.\n");
+        return out;
+    }
+
+    out.push_str("| Time (UTC) | App | Action | Target | Request bytes | Response bytes |\n");
+    out.push_str("|---|---|---|---|---|---|\n");
+    for entry in entries {
+        out.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {} |\n",
+            format_timestamp(entry.timestamp_ms),
+            entry.app_id,
+            entry.action,
+            escape_cell(&entry.target),
+            entry.request_bytes,
+            entry.response_bytes
+        ));
+    }
+    out
+}
+
+fn escape_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Formats milliseconds since the Unix epoch as `YYYY-MM-DD HH:MM:SS`.
+fn format_timestamp(timestamp_ms: u64) -> String {
+    let secs = timestamp_ms / 1000;
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+
+    // Civil-from-days conversion (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        (rem % 3600) / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LspAgent;
+
+    #[test]
+    fn test_format_timestamp() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        // 2024-02-29 12:34:56 UTC
+        assert_eq!(format_timestamp(1_709_210_096_000), "2024-02-29 12:34:56");
+    }
+
+    #[test]
+    fn test_record_appends() {
+        let mut doc = automerge::AutoCommit::new();
+        autosurgeon::reconcile(&mut doc, LspAgent::default()).unwrap();
+        record(&mut doc, "app1", "read_document", "file:///a.rs", 0, 42);
+        record(&mut doc, "app1", "store_value", "notes", 7, 0);
+
+        let agent: LspAgent = autosurgeon::hydrate(&doc).unwrap();
+
+        assert_eq!(agent.audit_log.len(), 2);
+        assert_eq!(agent.audit_log[0].action, "read_document");
+        assert_eq!(agent.audit_log[0].response_bytes, 42);
+        assert_eq!(agent.audit_log[1].target, "notes");
+        assert_eq!(agent.audit_log[1].request_bytes, 7);
+    }
+
+    fn entry(target: usize) -> AuditEntry {
+        AuditEntry {
+            timestamp_ms: target as u64,
+            app_id: "app1".to_string(),
+            action: "read_value".to_string(),
+            target: target.to_string(),
+            request_bytes: 0,
+            response_bytes: 0,
+        }
+    }
+
+    fn targets(entries: &[AuditEntry]) -> Vec<String> {
+        entries.iter().map(|entry| entry.target.clone()).collect()
+    }
+
+    #[test]
+    fn test_append_archives_older_entries() {
+        let workspace = tempfile::TempDir::new().unwrap();
+        let mut doc = automerge::AutoCommit::new();
+        let agent = LspAgent {
+            workspace_folders: vec![workspace.path().display().to_string()],
+            ..Default::default()
+        };
+        autosurgeon::reconcile(&mut doc, agent).unwrap();
+        for i in 0..7 {
+            append(&mut doc, entry(i), 4);
+        }
+
+        let agent: LspAgent = autosurgeon::hydrate(&doc).unwrap();
+
+        assert_eq!(targets(&agent.audit_log), vec!["3", "4", "5", "6"]);
+        let archived = std::fs::read_to_string(workspace.path().join(ARCHIVE_FILE)).unwrap();
+        assert_eq!(archived.lines().count(), 3);
+        assert_eq!(
+            targets(&entries(&doc)),
+            vec!["0", "1", "2", "3", "4", "5", "6"]
+        );
+    }
+
+    #[test]
+    fn test_append_keeps_entries_without_workspace() {
+        let mut doc = automerge::AutoCommit::new();
+        autosurgeon::reconcile(&mut doc, LspAgent::default()).unwrap();
+        for i in 0..7 {
+            append(&mut doc, entry(i), 4);
+        }
+
+        assert_eq!(entries(&doc).len(), 7);
+    }
+
+    #[test]
+    fn test_render() {
+        assert!(render(&[]).contains("No app actions recorded yet."));
+
+        let entries = vec![AuditEntry {
+            timestamp_ms: 0,
+            app_id: "app1".to_string(),
+            action: "store_value".to_string(),
+            target: "a|b".to_string(),
+            request_bytes: 3,
+            response_bytes: 0,
+        }];
+        let rendered = render(&entries);
+        assert!(rendered.contains("| 1970-01-01 00:00:00 | app1 | store_value | a\\|b | 3 | 0 |"));
+    }
+}
@@ -0,0 +1,233 @@
+use crate::doc_paths::{self, Readable, Writable};
+use crate::{DocumentContent, SessionBundle, StoredValue, library, sessions};
+use automerge::AutoCommit;
+use autosurgeon::{hydrate, reconcile};
+use std::collections::HashMap;
+use traits::ImportedSession;
+use uuid::Uuid;
+
+const WEBVIEWS: &[&str] = &["webviews", "documents"];
+
+/// Builds the bundle for `name` and returns it as raw Automerge bytes.
+///
+/// Only the stored values that the session's apps wrote or read are included;
+/// values of other sessions stay private.
+pub(crate) fn export<D: Readable>(doc: &D, name: &str) -> Result<Vec<u8>, String> {
+    if name != sessions::DEFAULT_SESSION && !sessions::exists(doc, name) {
+        return Err(format!("Session '{}' does not exist.", name));
+    }
+    let session = sessions::session(doc, name);
+    let apps = session
+        .apps
+        .iter()
+        .filter_map(|id| {
+            doc_paths::get::<_, DocumentContent>(doc, WEBVIEWS, id)
+                .map(|content| (id.clone(), content))
+        })
+        .collect();
+    let bundle = SessionBundle {
+        name: name.to_string(),
+        stored_values: used_values(doc, &session.apps),
+        session,
+        apps,
+    };
+
+    let mut bundle_doc = AutoCommit::new();
+    reconcile(&mut bundle_doc, &bundle).map_err(|e| format!("Failed to encode session: {}", e))?;
+    Ok(bundle_doc.save())
+}
+
+/// Adds the session in `bytes` to `doc` and makes it active.
+///
+/// The session is renamed if its name is taken. Its apps are untrusted, so they
+/// only go to the library under fresh ids, for the user to relaunch. Bundled stored values whose key is
+/// already taken are skipped and reported, so an import never overwrites data.
+pub(crate) fn import<D: Writable>(doc: &mut D, bytes: &[u8]) -> Result<ImportedSession, String> {
+    let bundle_doc = AutoCommit::load(bytes).map_err(|e| format!("Not a session bundle: {}", e))?;
+    let bundle: SessionBundle =
+        hydrate(&bundle_doc).map_err(|e| format!("Not a session bundle: {}", e))?;
+
+    let name = unused_name(doc, &bundle.name);
+    let mut session = bundle.session;
+    for content in bundle.apps.into_values() {
+        let id = format!("app-{}", Uuid::new_v4());
+        library::record_launch(doc, &id, "Imported with a session bundle", &content.text);
+    }
+    session.apps.clear();
+
+    let mut skipped_values = Vec::new();
+    for (key, value) in bundle.stored_values {
+        if doc_paths::contains(doc, &["stored_values"], &key) {
+            skipped_values.push(key);
+        } else {
+            doc_paths::put(doc, &["stored_values"], &key, value);
+        }
+    }
+    skipped_values.sort();
+
+    sessions::put(doc, &name, &session);
+    doc_paths::put(doc, &[], "active_session", name.as_str());
+    Ok(ImportedSession {
+        name,
+        skipped_values,
+    })
+}
+
+/// Remembers that `app_id` wrote or read the stored value under `key`.
+pub(crate) fn note_value_use<D: Writable>(doc: &mut D, app_id: &str, key: &str) {
+    let keys: Vec<String> = doc_paths::get_list(doc, &["app_values"], app_id);
+    if !keys.iter().any(|used| used == key) {
+        doc_paths::push(doc, &["app_values"], app_id, key.to_string());
+    }
+}
+
+/// The stored values that any of `apps` wrote or read.
+fn used_values<D: Readable>(doc: &D, apps: &[String]) -> HashMap<String, StoredValue> {
+    apps.iter()
+        .flat_map(|app_id| doc_paths::get_list::<_, String>(doc, &["app_values"], app_id))
+        .filter_map(|key| {
+            doc_paths::get::<_, StoredValue>(doc, &["stored_values"], &key)
+                .map(|value| (key, value))
+        })
+        .collect()
+}
+
+fn unused_name<D: Readable>(doc: &D, name: &str) -> String {
+    let name = match name.trim() {
+        "" => sessions::DEFAULT_SESSION,
+        trimmed => trimmed,
+    };
+    if !sessions::exists(doc, name) {
+        return name.to_string();
+    }
+    (2..)
+        .map(|n| format!("{}-{}", name, n))
+        .find(|candidate| !sessions::exists(doc, candidate))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConversationFragment, LspAgent, Session};
+
+    fn doc_of(agent: &LspAgent) -> AutoCommit {
+        let mut doc = AutoCommit::new();
+        reconcile(&mut doc, agent).unwrap();
+        doc
+    }
+
+    fn agent_with_session() -> LspAgent {
+        let mut agent = LspAgent::default();
+        agent.sessions.insert(
+            "work".to_string(),
+            Session {
+                conversation_history: vec![ConversationFragment::User("make a todo app".into())],
+                apps: vec!["app-1".to_string()],
+                ..Default::default()
+            },
+        );
+        agent.webviews.documents.insert(
+            "app-1".to_string(),
+            DocumentContent {
+                text: "<html>todo</html>".to_string(),
+            },
+        );
+        agent.stored_values.insert(
+            "todos".to_string(),
+            StoredValue {
+                value: "[]".to_string(),
+                description: "todo items".to_string(),
+            },
+        );
+        agent.stored_values.insert(
+            "secret".to_string(),
+            StoredValue {
+                value: "hunter2".to_string(),
+                description: "another session's value".to_string(),
+            },
+        );
+        agent
+            .app_values
+            .insert("app-1".to_string(), vec!["todos".to_string()]);
+        agent
+            .app_values
+            .insert("app-2".to_string(), vec!["secret".to_string()]);
+        agent
+    }
+
+    #[test]
+    fn test_export_import_roundtrip() {
+        let source = agent_with_session();
+        let bytes = export(&doc_of(&source), "work").unwrap();
+
+        let mut target_doc = doc_of(&LspAgent::default());
+        let imported = import(&mut target_doc, &bytes).unwrap();
+        let target: LspAgent = hydrate(&target_doc).unwrap();
+
+        assert_eq!(imported.name, "work");
+        assert!(imported.skipped_values.is_empty());
+        assert_eq!(target.active_session, "work");
+        let session = &target.sessions["work"];
+        assert_eq!(
+            session.conversation_history,
+            source.sessions["work"].conversation_history
+        );
+        assert!(session.apps.is_empty());
+        assert!(target.webviews.documents.is_empty());
+        assert!(target.responses.is_empty());
+        let library = library::list(&target_doc);
+        assert_eq!(library.len(), 1);
+        assert_ne!(library[0].id, "app-1");
+        assert_eq!(
+            library::relaunchable(&target_doc, &library[0].id).unwrap(),
+            "<html>todo</html>"
+        );
+        assert_eq!(target.stored_values["todos"].value, "[]");
+        assert!(!target.stored_values.contains_key("secret"));
+    }
+
+    #[test]
+    fn test_import_keeps_existing_stored_values() {
+        let bytes = export(&doc_of(&agent_with_session()), "work").unwrap();
+
+        let mut target = LspAgent::default();
+        target.stored_values.insert(
+            "todos".to_string(),
+            StoredValue {
+                value: "[\"mine\"]".to_string(),
+                description: "todo items".to_string(),
+            },
+        );
+        let mut target_doc = doc_of(&target);
+        let imported = import(&mut target_doc, &bytes).unwrap();
+        let target: LspAgent = hydrate(&target_doc).unwrap();
+
+        assert_eq!(imported.skipped_values, vec!["todos".to_string()]);
+        assert_eq!(target.stored_values["todos"].value, "[\"mine\"]");
+    }
+
+    #[test]
+    fn test_import_renames_existing_session() {
+        let mut doc = doc_of(&agent_with_session());
+        let bytes = export(&doc, "work").unwrap();
+
+        assert_eq!(import(&mut doc, &bytes).unwrap().name, "work-2");
+        assert_eq!(import(&mut doc, &bytes).unwrap().name, "work-3");
+        assert_eq!(
+            sessions::session(&doc, "work").apps,
+            vec!["app-1".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_export_unknown_session_and_bad_bundle() {
+        let mut doc = doc_of(&LspAgent::default());
+        assert!(export(&doc, "missing").is_err());
+        assert!(export(&doc, sessions::DEFAULT_SESSION).is_ok());
+
+        assert!(import(&mut doc, b"not automerge").is_err());
+        let agent: LspAgent = hydrate(&doc).unwrap();
+        assert!(agent.sessions.is_empty());
+    }
+}
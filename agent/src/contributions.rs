@@ -0,0 +1,178 @@
+use crate::{AppContributions, EditPosition};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use traits::{CodeLens, TextPosition, TextRange};
+
+/// Contributions of every app, keyed by app id and then document URI.
+pub(crate) type ByApp = HashMap<String, HashMap<String, AppContributions>>;
+
+/// Code lenses as shown in the editor, per document URI.
+pub(crate) type Lenses = BTreeMap<String, Vec<CodeLens>>;
+
+/// Separates the hover texts of different apps in one hover.
+const HOVER_SEPARATOR: &str = "\n\n---\n\n";
+
+/// Body of a `wry://contributions` request.
+#[derive(Deserialize)]
+struct ContributionsBody {
+    uri: String,
+    #[serde(flatten)]
+    contributions: AppContributions,
+}
+
+/// Parses a `wry://contributions` body into the document URI and what the app
+/// contributes to it.
+pub(crate) fn parse_body(body: &str) -> Result<(String, AppContributions), String> {
+    let body: ContributionsBody =
+        serde_json::from_str(body).map_err(|e| format!("Invalid contributions: {}", e))?;
+    let contributions = body.contributions;
+    if contributions
+        .hovers
+        .iter()
+        .any(|hover| hover.range.start > hover.range.end)
+        || contributions
+            .code_lenses
+            .iter()
+            .any(|lens| lens.range.start > lens.range.end)
+    {
+        return Err("Invalid contributions: a range ends before it starts".to_string());
+    }
+    if contributions
+        .code_lenses
+        .iter()
+        .any(|lens| lens.title.trim().is_empty())
+    {
+        return Err("Invalid contributions: code lenses need a title".to_string());
+    }
+    Ok((body.uri, contributions))
+}
+
+fn sorted_app_ids(by_app: &ByApp) -> Vec<&String> {
+    let mut app_ids: Vec<&String> = by_app.keys().collect();
+    app_ids.sort();
+    app_ids
+}
+
+/// Joins the hover texts that apps placed over `position` in `uri`, ordered by
+/// app id.
+pub(crate) fn hover(by_app: &ByApp, uri: &str, position: EditPosition) -> Option<String> {
+    let contents: Vec<&str> = sorted_app_ids(by_app)
+        .into_iter()
+        .filter_map(|app_id| by_app[app_id].get(uri))
+        .flat_map(|contributions| &contributions.hovers)
+        .filter(|hover| hover.range.start <= position && position <= hover.range.end)
+        .map(|hover| hover.contents.as_str())
+        .collect();
+    (!contents.is_empty()).then(|| contents.join(HOVER_SEPARATOR))
+}
+
+fn text_position(position: EditPosition) -> TextPosition {
+    TextPosition {
+        line: position.line,
+        character: position.character,
+    }
+}
+
+/// Merges the code lenses of all apps per document, ordered by app id.
+pub(crate) fn lenses(by_app: &ByApp) -> Lenses {
+    let mut lenses = Lenses::new();
+    for app_id in sorted_app_ids(by_app) {
+        for (uri, contributions) in &by_app[app_id] {
+            let entry = lenses.entry(uri.clone()).or_default();
+            entry.extend(contributions.code_lenses.iter().map(|lens| CodeLens {
+                range: TextRange {
+                    start: text_position(lens.range.start),
+                    end: text_position(lens.range.end),
+                },
+                title: lens.title.clone(),
+            }));
+        }
+    }
+    lenses.retain(|_, lenses| !lenses.is_empty());
+    lenses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contributions(body: &str) -> AppContributions {
+        parse_body(body).unwrap().1
+    }
+
+    #[test]
+    fn test_parse_body() {
+        let (uri, parsed) = parse_body(
+            r#"{"uri": "file:///a.md", "hovers": [
+                {"range": {"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 3}},
+                 "contents": "**API**: application programming interface"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(uri, "file:///a.md");
+        assert_eq!(parsed.hovers.len(), 1);
+        assert!(parsed.code_lenses.is_empty());
+
+        assert!(
+            parse_body(
+                r#"{"uri": "file:///a.md", "code_lenses": [
+                {"range": {"start": {"line": 2, "character": 0}, "end": {"line": 1, "character": 0}},
+                 "title": "TODO"}
+            ]}"#
+            )
+            .is_err()
+        );
+        assert!(
+            parse_body(
+                r#"{"uri": "file:///a.md", "code_lenses": [
+                {"range": {"start": {"line": 1, "character": 0}, "end": {"line": 1, "character": 0}},
+                 "title": " "}
+            ]}"#
+            )
+            .is_err()
+        );
+        assert!(parse_body("{}").is_err());
+    }
+
+    #[test]
+    fn test_hover_and_lenses() {
+        let mut by_app = ByApp::new();
+        by_app.insert(
+            "app-b".to_string(),
+            HashMap::from([(
+                "file:///a.md".to_string(),
+                contributions(
+                    r#"{"uri": "file:///a.md",
+                    "hovers": [{"range": {"start": {"line": 1, "character": 0}, "end": {"line": 1, "character": 3}}, "contents": "from b"}],
+                    "code_lenses": [{"range": {"start": {"line": 4, "character": 0}, "end": {"line": 4, "character": 0}}, "title": "2 TODOs"}]}"#,
+                ),
+            )]),
+        );
+        by_app.insert(
+            "app-a".to_string(),
+            HashMap::from([(
+                "file:///a.md".to_string(),
+                contributions(
+                    r#"{"uri": "file:///a.md",
+                    "hovers": [{"range": {"start": {"line": 0, "character": 0}, "end": {"line": 2, "character": 0}}, "contents": "from a"}]}"#,
+                ),
+            )]),
+        );
+
+        let at = |line, character| EditPosition { line, character };
+        assert_eq!(
+            hover(&by_app, "file:///a.md", at(1, 2)).as_deref(),
+            Some("from a\n\n---\n\nfrom b")
+        );
+        assert_eq!(
+            hover(&by_app, "file:///a.md", at(1, 5)).as_deref(),
+            Some("from a")
+        );
+        assert_eq!(hover(&by_app, "file:///a.md", at(3, 0)), None);
+        assert_eq!(hover(&by_app, "file:///b.md", at(1, 2)), None);
+
+        let lenses = lenses(&by_app);
+        assert_eq!(lenses.len(), 1);
+        assert_eq!(lenses["file:///a.md"][0].title, "2 TODOs");
+    }
+}
@@ -0,0 +1,168 @@
+use crate::{AppDiagnostic, EditPosition};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use traits::{Diagnostic, DiagnosticSeverity, TextPosition, TextRange};
+
+/// Diagnostics as published to the editor, per document URI.
+pub(crate) type Published = BTreeMap<String, Vec<Diagnostic>>;
+
+/// Body of a `wry://diagnostics` request.
+#[derive(Deserialize)]
+struct DiagnosticsBody {
+    uri: String,
+    diagnostics: Vec<AppDiagnostic>,
+}
+
+fn severity(name: &str) -> Option<DiagnosticSeverity> {
+    match name {
+        "error" => Some(DiagnosticSeverity::Error),
+        "warning" => Some(DiagnosticSeverity::Warning),
+        "information" => Some(DiagnosticSeverity::Information),
+        "hint" => Some(DiagnosticSeverity::Hint),
+        _ => None,
+    }
+}
+
+/// Parses a `wry://diagnostics` body into the document URI and its diagnostics.
+pub(crate) fn parse_body(body: &str) -> Result<(String, Vec<AppDiagnostic>), String> {
+    let body: DiagnosticsBody =
+        serde_json::from_str(body).map_err(|e| format!("Invalid diagnostics: {}", e))?;
+    if let Some(bad) = body
+        .diagnostics
+        .iter()
+        .find(|diagnostic| severity(&diagnostic.severity).is_none())
+    {
+        return Err(format!(
+            "Invalid diagnostics: unknown severity '{}'",
+            bad.severity
+        ));
+    }
+    Ok((body.uri, body.diagnostics))
+}
+
+fn text_position(position: EditPosition) -> TextPosition {
+    TextPosition {
+        line: position.line,
+        character: position.character,
+    }
+}
+
+/// Merges the diagnostics of all apps per document, ordered by app id so that
+/// the result is stable across calls.
+pub(crate) fn merged(by_app: &HashMap<String, HashMap<String, Vec<AppDiagnostic>>>) -> Published {
+    let mut app_ids: Vec<&String> = by_app.keys().collect();
+    app_ids.sort();
+    let mut published = Published::new();
+    for app_id in app_ids {
+        for (uri, diagnostics) in &by_app[app_id] {
+            let entry = published.entry(uri.clone()).or_default();
+            entry.extend(diagnostics.iter().filter_map(|diagnostic| {
+                Some(Diagnostic {
+                    range: TextRange {
+                        start: text_position(diagnostic.range.start),
+                        end: text_position(diagnostic.range.end),
+                    },
+                    severity: severity(&diagnostic.severity)?,
+                    message: diagnostic.message.clone(),
+                    source: diagnostic.source.clone(),
+                })
+            }));
+        }
+    }
+    published.retain(|_, diagnostics| !diagnostics.is_empty());
+    published
+}
+
+/// Documents whose diagnostics differ between `before` and `after`, with what to
+/// publish for them now (empty to clear).
+pub(crate) fn changes(before: &Published, after: &Published) -> Vec<(String, Vec<Diagnostic>)> {
+    let cleared = before
+        .keys()
+        .filter(|uri| !after.contains_key(*uri))
+        .map(|uri| (uri.clone(), Vec::new()));
+    let updated = after
+        .iter()
+        .filter(|(uri, diagnostics)| before.get(*uri) != Some(diagnostics))
+        .map(|(uri, diagnostics)| (uri.clone(), diagnostics.clone()));
+    cleared.chain(updated).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::EditRange;
+
+    fn diagnostic(message: &str) -> AppDiagnostic {
+        let at = |character| EditPosition { line: 0, character };
+        AppDiagnostic {
+            range: EditRange {
+                start: at(0),
+                end: at(3),
+            },
+            severity: "warning".to_string(),
+            message: message.to_string(),
+            source: "Linter".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_body() {
+        let (uri, diagnostics) = parse_body(
+            r#"{"uri": "file:///a.rs", "diagnostics": [
+                {"range": {"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 3}},
+                 "severity": "error", "message": "bad"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(uri, "file:///a.rs");
+        assert_eq!(diagnostics[0].message, "bad");
+
+        assert!(
+            parse_body(
+                r#"{"uri": "file:///a.rs", "diagnostics": [
+                {"range": {"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 3}},
+                 "severity": "fatal", "message": "bad"}
+            ]}"#
+            )
+            .is_err()
+        );
+        assert!(parse_body("{}").is_err());
+    }
+
+    #[test]
+    fn test_merged_and_changes() {
+        let mut by_app = HashMap::new();
+        by_app.insert(
+            "app-b".to_string(),
+            HashMap::from([("file:///a.rs".to_string(), vec![diagnostic("from b")])]),
+        );
+        by_app.insert(
+            "app-a".to_string(),
+            HashMap::from([
+                ("file:///a.rs".to_string(), vec![diagnostic("from a")]),
+                ("file:///b.rs".to_string(), vec![]),
+            ]),
+        );
+
+        let published = merged(&by_app);
+        assert_eq!(published.len(), 1);
+        let messages: Vec<&str> = published["file:///a.rs"]
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect();
+        assert_eq!(messages, vec!["from a", "from b"]);
+        assert_eq!(
+            published["file:///a.rs"][0].severity,
+            DiagnosticSeverity::Warning
+        );
+
+        assert_eq!(changes(&Published::new(), &published).len(), 1);
+        assert!(changes(&published, &published).is_empty());
+
+        // Closing both apps clears the document.
+        assert_eq!(
+            changes(&published, &Published::new()),
+            vec![("file:///a.rs".to_string(), vec![])]
+        );
+    }
+}
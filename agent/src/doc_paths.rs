@@ -0,0 +1,228 @@
+//! Reads and writes of single values in the shared document, addressed by their
+//! path of map keys from the root. Hydrating and reconciling the whole `LspAgent`
+//! walks every open document and app, so hot paths use these instead.
+
+use automerge::transaction::Transactable;
+use automerge::{ObjId, ObjType, ROOT, ReadDoc};
+use autosurgeon::{Hydrate, Reconcile, hydrate_prop, reconcile_insert, reconcile_prop};
+
+/// Documents that can be read both directly and through autosurgeon.
+pub(crate) trait Readable: ReadDoc + autosurgeon::ReadDoc {}
+impl<D: ReadDoc + autosurgeon::ReadDoc> Readable for D {}
+
+/// Documents (or transactions) that can also be written.
+pub(crate) trait Writable: Readable + Transactable {}
+impl<D: Readable + Transactable> Writable for D {}
+
+fn child<D: ReadDoc>(
+    doc: &D,
+    obj: &ObjId,
+    key: &str,
+) -> Option<(automerge::Value<'static>, ObjId)> {
+    doc.get(obj, key)
+        .ok()
+        .flatten()
+        .map(|(value, id)| (value.into_owned(), id))
+}
+
+fn length<D: ReadDoc>(doc: &D, obj: &ObjId) -> usize {
+    doc.length(obj)
+}
+
+/// Resolves a path of map keys to the object it names.
+fn object<D: ReadDoc>(doc: &D, path: &[&str]) -> Option<ObjId> {
+    let mut obj = ROOT;
+    for key in path {
+        match child(doc, &obj, key)? {
+            (automerge::Value::Object(_), id) => obj = id,
+            _ => return None,
+        }
+    }
+    Some(obj)
+}
+
+/// Like [`object`], creating the maps along the path that do not exist yet.
+fn object_or_create<D: Writable>(doc: &mut D, path: &[&str]) -> ObjId {
+    let mut obj = ROOT;
+    for key in path {
+        obj = match child(doc, &obj, key) {
+            Some((automerge::Value::Object(_), id)) => id,
+            _ => doc.put_object(&obj, *key, ObjType::Map).unwrap(),
+        };
+    }
+    obj
+}
+
+fn list_or_create<D: Writable>(doc: &mut D, path: &[&str], key: &str) -> ObjId {
+    let parent = object_or_create(doc, path);
+    match child(doc, &parent, key) {
+        Some((automerge::Value::Object(ObjType::List), id)) => id,
+        _ => doc.put_object(&parent, key, ObjType::List).unwrap(),
+    }
+}
+
+fn map_keys<D: ReadDoc>(doc: &D, obj: &ObjId) -> Vec<String> {
+    doc.keys(obj).collect()
+}
+
+/// Resolves the list under `key` in the map at `path`.
+fn list<D: ReadDoc>(doc: &D, path: &[&str], key: &str) -> Option<ObjId> {
+    match child(doc, &object(doc, path)?, key)? {
+        (automerge::Value::Object(ObjType::List), id) => Some(id),
+        _ => None,
+    }
+}
+
+/// Keys of the map at `path`, without reading their values; empty if it is missing.
+pub(crate) fn keys<D: Readable>(doc: &D, path: &[&str]) -> Vec<String> {
+    object(doc, path)
+        .map(|obj| map_keys(doc, &obj))
+        .unwrap_or_default()
+}
+
+/// Whether the map at `path` has a value under `key`.
+pub(crate) fn contains<D: Readable>(doc: &D, path: &[&str], key: &str) -> bool {
+    object(doc, path).is_some_and(|obj| child(doc, &obj, key).is_some())
+}
+
+/// Length of the list under `key` in the map at `path`; a missing list is empty.
+pub(crate) fn len<D: Readable>(doc: &D, path: &[&str], key: &str) -> usize {
+    list(doc, path, key).map_or(0, |list| length(doc, &list))
+}
+
+/// Hydrates the value under `key` in the map at `path`, or `None` if it is missing.
+pub(crate) fn get<D: Readable, T: Hydrate>(doc: &D, path: &[&str], key: &str) -> Option<T> {
+    let obj = object(doc, path)?;
+    child(doc, &obj, key)?;
+    hydrate_prop(doc, &obj, key).ok()
+}
+
+/// Hydrates the list under `key` in the map at `path`; a missing list is empty.
+pub(crate) fn get_list<D: Readable, T: Hydrate>(doc: &D, path: &[&str], key: &str) -> Vec<T> {
+    get(doc, path, key).unwrap_or_default()
+}
+
+/// Reconciles `value` into the map at `path` under `key`.
+pub(crate) fn put<D: Writable, T: Reconcile>(doc: &mut D, path: &[&str], key: &str, value: T) {
+    let obj = object_or_create(doc, path);
+    reconcile_prop(doc, &obj, key, value).unwrap();
+}
+
+pub(crate) fn delete<D: Writable>(doc: &mut D, path: &[&str], key: &str) {
+    if let Some(obj) = object(doc, path)
+        && child(doc, &obj, key).is_some()
+    {
+        doc.delete(&obj, key).unwrap();
+    }
+}
+
+/// Appends `value` to the list under `key` in the map at `path` and returns
+/// the list's new length.
+pub(crate) fn push<D: Writable, T: Reconcile>(
+    doc: &mut D,
+    path: &[&str],
+    key: &str,
+    value: T,
+) -> usize {
+    let list = list_or_create(doc, path, key);
+    let len = length(doc, &list);
+    reconcile_insert(doc, list, len, value).unwrap();
+    len + 1
+}
+
+/// Removes the first `count` elements of the list under `key` in the map at `path`.
+pub(crate) fn drain_front<D: Writable>(doc: &mut D, path: &[&str], key: &str, count: usize) {
+    let Some(list) = list(doc, path, key) else {
+        return;
+    };
+    for _ in 0..count.min(length(doc, &list)) {
+        doc.delete(&list, 0).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AgentRequest, LspAgent, StoredValue};
+    use automerge::AutoCommit;
+    use autosurgeon::{hydrate, reconcile};
+
+    fn doc() -> AutoCommit {
+        let mut doc = AutoCommit::new();
+        reconcile(&mut doc, LspAgent::default()).unwrap();
+        doc
+    }
+
+    #[test]
+    fn test_put_get_delete() {
+        let mut doc = doc();
+        let value = StoredValue {
+            value: "42".to_string(),
+            description: "answer".to_string(),
+        };
+
+        assert_eq!(get::<_, StoredValue>(&doc, &["stored_values"], "a"), None);
+        put(&mut doc, &["stored_values"], "a", &value);
+        assert_eq!(get(&doc, &["stored_values"], "a"), Some(value.clone()));
+        assert!(contains(&doc, &["stored_values"], "a"));
+        assert_eq!(keys(&doc, &["stored_values"]), vec!["a".to_string()]);
+
+        let agent: LspAgent = hydrate(&doc).unwrap();
+        assert_eq!(agent.stored_values["a"], value);
+
+        delete(&mut doc, &["stored_values"], "a");
+        delete(&mut doc, &["stored_values"], "missing");
+        assert_eq!(get::<_, StoredValue>(&doc, &["stored_values"], "a"), None);
+    }
+
+    #[test]
+    fn test_push_and_drain_front() {
+        let mut doc = doc();
+        for i in 0..3 {
+            push(
+                &mut doc,
+                &[],
+                "requests",
+                AgentRequest::Inference {
+                    request_id: i.to_string(),
+                    content: i.to_string(),
+                    app_id: "app".to_string(),
+                },
+            );
+        }
+
+        let requests: Vec<AgentRequest> = get_list(&doc, &[], "requests");
+        assert_eq!(requests.len(), 3);
+        assert_eq!(len(&doc, &[], "requests"), 3);
+
+        drain_front(&mut doc, &[], "requests", 2);
+        let agent: LspAgent = hydrate(&doc).unwrap();
+        assert_eq!(
+            agent.requests,
+            vec![AgentRequest::Inference {
+                request_id: "2".to_string(),
+                content: "2".to_string(),
+                app_id: "app".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_put_creates_missing_maps() {
+        let mut doc = AutoCommit::new();
+        put(
+            &mut doc,
+            &["text_documents", "documents"],
+            "file:///a.rs",
+            "x",
+        );
+        assert_eq!(
+            get::<_, String>(&doc, &["text_documents", "documents"], "file:///a.rs"),
+            Some("x".to_string())
+        );
+        assert!(get_list::<_, AgentRequest>(&doc, &[], "requests").is_empty());
+        assert_eq!(len(&doc, &[], "requests"), 0);
+        assert!(!contains(&doc, &["stored_values"], "a"));
+        assert!(keys(&doc, &["stored_values"]).is_empty());
+    }
+}
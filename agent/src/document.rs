@@ -1,6 +1,7 @@
 use automerge_repo::{DocumentId, Storage, StorageError};
-use autosurgeon::{Hydrate, Reconcile};
+use autosurgeon::{Hydrate, Reconcile, Text};
 use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 pub struct NoStorage;
@@ -36,20 +37,81 @@ impl Storage for NoStorage {
 
 #[derive(Debug, Clone, Reconcile, Hydrate, PartialEq)]
 pub enum AgentRequest {
-    Inference { content: String, app_id: String },
+    Inference {
+        request_id: String,
+        content: String,
+        app_id: String,
+    },
+    ReadWorkspaceFile {
+        request_id: String,
+        app_id: String,
+        path: String,
+    },
+    ListDirectory {
+        request_id: String,
+        app_id: String,
+        path: String,
+    },
+    ProposeEdit {
+        request_id: String,
+        app_id: String,
+        uri: String,
+        edits: Vec<ProposedEdit>,
+    },
+}
+
+/// A position as LSP sends it: zero-based line and UTF-16 offset within the line.
+#[derive(
+    Debug, Clone, Copy, Reconcile, Hydrate, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize,
+)]
+pub struct EditPosition {
+    pub line: u32,
+    pub character: u32,
+}
+
+#[derive(Debug, Clone, Copy, Reconcile, Hydrate, PartialEq, Deserialize)]
+pub struct EditRange {
+    pub start: EditPosition,
+    pub end: EditPosition,
+}
+
+/// Body of the `wry://store_value` protocol.
+#[derive(Debug, Deserialize)]
+pub struct StoreValueBody {
+    pub key: String,
+    pub value: String,
+    pub description: String,
+}
+
+/// An edit proposed by an app. Without a range it replaces the whole document.
+#[derive(Debug, Clone, Reconcile, Hydrate, PartialEq, Deserialize)]
+pub struct ProposedEdit {
+    #[serde(default)]
+    pub range: Option<EditRange>,
+    pub text: String,
 }
 
 #[derive(Debug, Clone, Reconcile, Hydrate, PartialEq)]
 pub enum AgentResponse {
     Chat(String),
-    Inference { app_id: String, content: String },
-    WebApp { id: String, content: String },
+    WebApp {
+        id: String,
+        content: String,
+    },
+    /// Answer to the request with the same `request_id`; `status` is an HTTP status.
+    Reply {
+        request_id: String,
+        status: u16,
+        content: String,
+    },
 }
 
 #[derive(Debug, Clone, Reconcile, Hydrate, PartialEq)]
 pub enum ConversationFragment {
     Assistant(String),
     User(String),
+    /// Model-written summary standing in for older fragments that were compacted.
+    Summary(String),
 }
 
 #[derive(Debug, Clone, Reconcile, Hydrate, PartialEq, Default)]
@@ -73,22 +135,165 @@ pub struct DocumentManager {
     pub active_document: Option<Uri>,
 }
 
+/// An editor document. The text is an Automerge text object so that incremental
+/// changes are spliced in place instead of replacing the whole string.
+///
+/// The other fields describe the document without its contents and are kept up
+/// to date on every change.
+#[derive(Debug, Clone, Reconcile, Hydrate, PartialEq, Default)]
+pub struct TextDocument {
+    pub text: Text,
+    pub language_id: String,
+    /// Version number sent by the editor, increasing with every change.
+    pub version: i32,
+    pub line_count: u64,
+    pub byte_size: u64,
+    /// Changed since it was opened or last saved.
+    pub dirty: bool,
+    /// Not backed by a file yet (an `untitled:` URI).
+    pub untitled: bool,
+}
+
+/// A diagnostic published by an app. `severity` is one of `error`, `warning`,
+/// `information` and `hint`.
+#[derive(Debug, Clone, Reconcile, Hydrate, PartialEq, Deserialize)]
+pub struct AppDiagnostic {
+    pub range: EditRange,
+    pub severity: String,
+    pub message: String,
+    #[serde(default)]
+    pub source: String,
+}
+
+/// Markdown an app shows when the user hovers over `range`.
+#[derive(Debug, Clone, Reconcile, Hydrate, PartialEq, Deserialize)]
+pub struct AppHover {
+    pub range: EditRange,
+    pub contents: String,
+}
+
+/// A line of text an app shows above `range`.
+#[derive(Debug, Clone, Reconcile, Hydrate, PartialEq, Deserialize)]
+pub struct AppCodeLens {
+    pub range: EditRange,
+    pub title: String,
+}
+
+/// What one app contributes to the editor for one document.
+#[derive(Debug, Clone, Reconcile, Hydrate, PartialEq, Deserialize, Default)]
+pub struct AppContributions {
+    #[serde(default)]
+    pub hovers: Vec<AppHover>,
+    #[serde(default)]
+    pub code_lenses: Vec<AppCodeLens>,
+}
+
+/// A selection in an editor. `active` is where the cursor is; for an empty
+/// selection it equals `anchor`.
+#[derive(Debug, Clone, Copy, Reconcile, Hydrate, PartialEq)]
+pub struct Selection {
+    pub anchor: EditPosition,
+    pub active: EditPosition,
+}
+
+/// The selection an app was launched on from an editor code action.
+#[derive(Debug, Clone, Reconcile, Hydrate, PartialEq)]
+pub struct LaunchContext {
+    pub uri: String,
+    pub selection: Selection,
+}
+
+#[derive(Debug, Clone, Reconcile, Hydrate, PartialEq, Default)]
+pub struct TextDocumentManager {
+    pub documents: HashMap<String, TextDocument>,
+    pub active_document: Option<Uri>,
+    /// Selections of the editors showing each open document, primary selection first.
+    pub selections: HashMap<String, Vec<Selection>>,
+}
+
 #[derive(Debug, Clone, Reconcile, Hydrate, PartialEq, Default)]
 pub struct StoredValue {
     pub value: String,
     pub description: String,
 }
 
+/// One privileged call made by an app through the `WebAgent` interface.
+///
+/// Byte counts are measured from the app's perspective: `request_bytes` is what
+/// the app handed over (prompt, stored value), `response_bytes` what it got back.
+#[derive(Debug, Clone, Reconcile, Hydrate, PartialEq, Default, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp_ms: u64,
+    pub app_id: String,
+    pub action: String,
+    pub target: String,
+    pub request_bytes: u64,
+    pub response_bytes: u64,
+}
+
+/// A named conversation with its own history and the apps launched from it.
+#[derive(Debug, Clone, Reconcile, Hydrate, PartialEq, Default)]
+pub struct Session {
+    pub conversation_history: Vec<ConversationFragment>,
+    /// Raw fragments removed from `conversation_history` by compaction, oldest first.
+    pub archived_history: Vec<ConversationFragment>,
+    pub apps: Vec<String>,
+}
+
+/// An app kept in the library so it can be reopened after it was closed.
+#[derive(Debug, Clone, Reconcile, Hydrate, PartialEq, Default)]
+pub struct LibraryApp {
+    pub title: String,
+    /// The chat message the app was generated for.
+    pub prompt: String,
+    pub html: String,
+    pub last_used_ms: u64,
+}
+
+/// Portable snapshot of one session, saved as its own Automerge document.
+///
+/// `apps` holds the HTML of the session's running apps, keyed by the ids used in
+/// `session.apps`.
+#[derive(Debug, Clone, Reconcile, Hydrate, PartialEq, Default)]
+pub struct SessionBundle {
+    pub name: String,
+    pub session: Session,
+    pub apps: HashMap<String, DocumentContent>,
+    pub stored_values: HashMap<String, StoredValue>,
+}
+
 #[derive(Debug, Clone, Reconcile, Hydrate, PartialEq, Default)]
 pub struct LspAgent {
     pub requests: Vec<AgentRequest>,
     pub responses: Vec<AgentResponse>,
-    pub text_documents: DocumentManager,
+    pub text_documents: TextDocumentManager,
     pub webviews: DocumentManager,
     pub should_exit: bool,
     pub active_model: Option<String>,
-    pub conversation_history: Vec<ConversationFragment>,
+    pub sessions: HashMap<String, Session>,
+    /// Session used by chat requests that do not name one. Empty means the default session.
+    pub active_session: String,
     pub stored_values: HashMap<String, StoredValue>,
+    pub audit_log: Vec<AuditEntry>,
+    /// File system paths of the editor's workspace folders, first one is the root.
+    pub workspace_folders: Vec<String>,
+    /// Glob patterns, relative to a workspace folder, of the files apps may read.
+    pub exposed_files: Vec<String>,
+    /// Every app launched so far, keyed by app id; running apps are also in `webviews`.
+    pub app_library: HashMap<String, LibraryApp>,
+    /// Diagnostics published by running apps, keyed by app id and then document URI.
+    pub diagnostics: HashMap<String, HashMap<String, Vec<AppDiagnostic>>>,
+    /// Hovers and code lenses placed by running apps, keyed by app id and then
+    /// document URI.
+    pub contributions: HashMap<String, HashMap<String, AppContributions>>,
+    /// Selections that apps were launched on, keyed by app id.
+    pub launch_contexts: HashMap<String, LaunchContext>,
+    /// Errors reported by running apps through `wry://log`, keyed by app id,
+    /// oldest first.
+    pub app_errors: HashMap<String, Vec<String>>,
+    /// Keys of the stored values each app wrote or read, keyed by app id; what
+    /// exporting a session takes along.
+    pub app_values: HashMap<String, Vec<String>>,
 }
 
 impl std::fmt::Display for Id {
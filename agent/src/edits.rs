@@ -0,0 +1,181 @@
+use crate::{EditPosition, EditRange, ProposedEdit};
+use serde::Deserialize;
+use traits::{EditProposal, TextEdit, TextPosition, TextRange};
+
+/// Body of a `wry://propose_edit` request.
+#[derive(Deserialize)]
+struct ProposeEditBody {
+    uri: String,
+    edits: Vec<ProposedEdit>,
+}
+
+/// Parses a `wry://propose_edit` body into the target URI and its edits.
+pub(crate) fn parse_body(body: &str) -> Result<(String, Vec<ProposedEdit>), String> {
+    let body: ProposeEditBody =
+        serde_json::from_str(body).map_err(|e| format!("Invalid edit proposal: {}", e))?;
+    if body.uri.trim().is_empty() {
+        return Err("Invalid edit proposal: missing uri".to_string());
+    }
+    if body.edits.is_empty() {
+        return Err("Invalid edit proposal: no edits".to_string());
+    }
+    Ok((body.uri, body.edits))
+}
+
+/// Position just past the last character of `text`.
+fn end_position(text: &str) -> TextPosition {
+    let (line, last) = match text.rsplit_once('\n') {
+        Some((before, last)) => (before.matches('\n').count() + 1, last),
+        None => (0, text),
+    };
+    TextPosition {
+        line: line as u32,
+        character: last.encode_utf16().count() as u32,
+    }
+}
+
+fn text_position(position: EditPosition) -> TextPosition {
+    TextPosition {
+        line: position.line,
+        character: position.character,
+    }
+}
+
+fn text_range(range: EditRange) -> TextRange {
+    TextRange {
+        start: text_position(range.start),
+        end: text_position(range.end),
+    }
+}
+
+/// Turns queued edits into a proposal for the editor. Edits without a range
+/// replace the whole document, which must then be open so its extent is known.
+pub(crate) fn proposal(
+    uri: String,
+    label: String,
+    edits: Vec<ProposedEdit>,
+    current_text: Option<&str>,
+    version: Option<i32>,
+) -> Result<EditProposal, String> {
+    let edits = edits
+        .into_iter()
+        .map(|edit| {
+            let range = match edit.range {
+                Some(range) => text_range(range),
+                None => {
+                    let text = current_text.ok_or_else(|| {
+                        format!("'{}' is not open; edits to it must give a range.", uri)
+                    })?;
+                    TextRange {
+                        start: TextPosition {
+                            line: 0,
+                            character: 0,
+                        },
+                        end: end_position(text),
+                    }
+                }
+            };
+            Ok(TextEdit {
+                range,
+                text: edit.text,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    Ok(EditProposal {
+        uri,
+        label,
+        edits,
+        version,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_body() {
+        let (uri, edits) = parse_body(
+            r#"{"uri": "file:///a.md", "edits": [
+                {"range": {"start": {"line": 1, "character": 0}, "end": {"line": 2, "character": 4}}, "text": "new"},
+                {"text": "all"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(uri, "file:///a.md");
+        assert_eq!(edits[0].range.unwrap().end.character, 4);
+        assert_eq!(edits[1].range, None);
+
+        assert!(parse_body("not json").is_err());
+        assert!(parse_body(r#"{"uri": "file:///a.md", "edits": []}"#).is_err());
+        assert!(parse_body(r#"{"uri": " ", "edits": [{"text": "x"}]}"#).is_err());
+    }
+
+    #[test]
+    fn test_end_position() {
+        assert_eq!(
+            end_position(""),
+            TextPosition {
+                line: 0,
+                character: 0
+            }
+        );
+        assert_eq!(
+            end_position("a😀"),
+            TextPosition {
+                line: 0,
+                character: 3
+            }
+        );
+        assert_eq!(
+            end_position("a\nbc\n"),
+            TextPosition {
+                line: 2,
+                character: 0
+            }
+        );
+        assert_eq!(
+            end_position("a\nbc"),
+            TextPosition {
+                line: 1,
+                character: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_proposal_resolves_whole_document_edits() {
+        let edits = vec![ProposedEdit {
+            range: None,
+            text: "replaced".to_string(),
+        }];
+
+        let resolved = proposal(
+            "file:///a.md".to_string(),
+            "label".to_string(),
+            edits.clone(),
+            Some("one\ntwo"),
+            Some(4),
+        )
+        .unwrap();
+        assert_eq!(resolved.version, Some(4));
+        assert_eq!(
+            resolved.edits[0].range.end,
+            TextPosition {
+                line: 1,
+                character: 3
+            }
+        );
+
+        assert!(
+            proposal(
+                "file:///a.md".to_string(),
+                "label".to_string(),
+                edits,
+                None,
+                None
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,370 @@
+use crate::{ConversationFragment, Session};
+use traits::ChatTurn;
+
+pub(crate) const DEFAULT_COMPACT_CHARS: usize = 40_000;
+
+/// Number of most recent fragments that are always kept verbatim.
+const KEEP_RECENT: usize = 6;
+
+fn fragment_chars(fragment: &ConversationFragment) -> usize {
+    match fragment {
+        ConversationFragment::Assistant(content)
+        | ConversationFragment::User(content)
+        | ConversationFragment::Summary(content) => content.chars().count(),
+    }
+}
+
+/// Returns how many leading fragments should be folded into a summary, or
+/// `None` when the history fits in `budget` characters (`0` disables compaction).
+pub(crate) fn compaction_split(history: &[ConversationFragment], budget: usize) -> Option<usize> {
+    if budget == 0 || history.len() <= KEEP_RECENT {
+        return None;
+    }
+    let total: usize = history.iter().map(fragment_chars).sum();
+    if total <= budget {
+        return None;
+    }
+
+    let split = history.len() - KEEP_RECENT;
+    // Re-summarizing a lone summary would not shrink anything.
+    if split == 1 && matches!(history[0], ConversationFragment::Summary(_)) {
+        return None;
+    }
+    Some(split)
+}
+
+/// Replaces `summarized` at the start of the conversation with a single summary
+/// fragment and moves the raw fragments to `archived_history`.
+///
+/// Returns false without touching the session if the history no longer starts with
+/// `summarized` (it was changed while the summary was being produced).
+pub(crate) fn apply_compaction(
+    session: &mut Session,
+    summarized: &[ConversationFragment],
+    summary: String,
+) -> bool {
+    if !session.conversation_history.starts_with(summarized) {
+        return false;
+    }
+
+    let removed: Vec<ConversationFragment> = session
+        .conversation_history
+        .drain(..summarized.len())
+        .collect();
+    session.archived_history.extend(
+        removed
+            .into_iter()
+            .filter(|fragment| !matches!(fragment, ConversationFragment::Summary(_))),
+    );
+    session
+        .conversation_history
+        .insert(0, ConversationFragment::Summary(summary));
+    true
+}
+
+/// A user prompt as the editor shows it, with the reply displayed under it.
+struct EditorTurn<'a> {
+    prompt: &'a str,
+    reply: Option<String>,
+}
+
+fn editor_turns(turns: &[ChatTurn]) -> Vec<EditorTurn<'_>> {
+    let mut result: Vec<EditorTurn<'_>> = Vec::new();
+    for turn in turns {
+        match turn {
+            ChatTurn::User(prompt) => result.push(EditorTurn {
+                prompt,
+                reply: None,
+            }),
+            ChatTurn::Assistant(reply) => {
+                if let Some(last) = result.last_mut() {
+                    match &mut last.reply {
+                        Some(existing) => {
+                            existing.push('\n');
+                            existing.push_str(reply);
+                        }
+                        None => last.reply = Some(reply.clone()),
+                    }
+                }
+            }
+        }
+    }
+    result
+}
+
+/// Splits history into the fragments before the first user message (summary,
+/// notes) and one group per user message with the fragments that followed it.
+fn history_turns(
+    history: &[ConversationFragment],
+) -> (&[ConversationFragment], Vec<&[ConversationFragment]>) {
+    let starts: Vec<usize> = history
+        .iter()
+        .enumerate()
+        .filter(|(_, fragment)| matches!(fragment, ConversationFragment::User(_)))
+        .map(|(index, _)| index)
+        .collect();
+    let Some(&first) = starts.first() else {
+        return (history, Vec::new());
+    };
+
+    let mut turns = Vec::with_capacity(starts.len());
+    for (i, &start) in starts.iter().enumerate() {
+        let end = starts.get(i + 1).copied().unwrap_or(history.len());
+        turns.push(&history[start..end]);
+    }
+    (&history[..first], turns)
+}
+
+fn user_prompt(fragment: &ConversationFragment) -> Option<&str> {
+    match fragment {
+        ConversationFragment::User(prompt) => Some(prompt),
+        _ => None,
+    }
+}
+
+/// Rebuilds a session's history so it matches the turns the editor displays.
+///
+/// Turns are matched in order by their user prompt. Server turns the editor no
+/// longer shows are dropped, together with the tool notes recorded during them;
+/// editor turns the server does not know (e.g. from before a restart) are added
+/// from the editor's text. Leading turns already folded into the summary are
+/// recognised through `archived_history` and left alone.
+pub(crate) fn reconcile_with_editor(
+    session: &Session,
+    turns: &[ChatTurn],
+) -> Vec<ConversationFragment> {
+    let editor = editor_turns(turns);
+
+    let archived: Vec<&str> = session
+        .archived_history
+        .iter()
+        .filter_map(user_prompt)
+        .collect();
+    let mut archived_cursor = 0;
+    let mut first_live = 0;
+    while let Some(turn) = editor.get(first_live) {
+        match archived[archived_cursor..]
+            .iter()
+            .position(|prompt| *prompt == turn.prompt)
+        {
+            Some(offset) => {
+                archived_cursor += offset + 1;
+                first_live += 1;
+            }
+            None => break,
+        }
+    }
+
+    let (prefix, server_turns) = history_turns(&session.conversation_history);
+    let mut result = prefix.to_vec();
+    let mut cursor = 0;
+    for turn in &editor[first_live..] {
+        let found = server_turns[cursor..]
+            .iter()
+            .position(|fragments| user_prompt(&fragments[0]) == Some(turn.prompt));
+        match found {
+            Some(offset) => {
+                result.extend_from_slice(server_turns[cursor + offset]);
+                cursor += offset + 1;
+            }
+            None => {
+                result.push(ConversationFragment::User(turn.prompt.to_string()));
+                if let Some(reply) = &turn.reply {
+                    result.push(ConversationFragment::Assistant(reply.clone()));
+                }
+            }
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turns(count: usize, size: usize) -> Vec<ConversationFragment> {
+        (0..count)
+            .map(|i| {
+                if i % 2 == 0 {
+                    ConversationFragment::User(format!("{}{}", i, "u".repeat(size)))
+                } else {
+                    ConversationFragment::Assistant(format!("{}{}", i, "a".repeat(size)))
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_compaction_split_under_budget() {
+        assert_eq!(compaction_split(&turns(10, 10), 1000), None);
+        assert_eq!(compaction_split(&turns(10, 1000), 0), None);
+        assert_eq!(compaction_split(&turns(KEEP_RECENT, 1000), 10), None);
+    }
+
+    #[test]
+    fn test_compaction_split_over_budget() {
+        assert_eq!(
+            compaction_split(&turns(10, 1000), 1000),
+            Some(10 - KEEP_RECENT)
+        );
+
+        let mut history = vec![ConversationFragment::Summary("s".repeat(5000))];
+        history.extend(turns(KEEP_RECENT, 10));
+        assert_eq!(compaction_split(&history, 100), None);
+    }
+
+    #[test]
+    fn test_apply_compaction_archives_raw_fragments() {
+        let mut history = vec![ConversationFragment::Summary("old".to_string())];
+        history.extend(turns(8, 1));
+        let mut session = Session {
+            conversation_history: history,
+            ..Default::default()
+        };
+        let summarized = session.conversation_history[..3].to_vec();
+
+        assert!(apply_compaction(
+            &mut session,
+            &summarized,
+            "new".to_string()
+        ));
+        assert_eq!(
+            session.conversation_history[0],
+            ConversationFragment::Summary("new".to_string())
+        );
+        assert_eq!(session.conversation_history.len(), 9 - 3 + 1);
+        // The earlier summary is derived data and is not archived.
+        assert_eq!(session.archived_history, summarized[1..].to_vec());
+    }
+
+    #[test]
+    fn test_apply_compaction_rejects_stale_history() {
+        let mut session = Session {
+            conversation_history: turns(8, 1),
+            ..Default::default()
+        };
+        let summarized = turns(3, 2);
+
+        assert!(!apply_compaction(
+            &mut session,
+            &summarized,
+            "new".to_string()
+        ));
+        assert_eq!(session.conversation_history, turns(8, 1));
+        assert!(session.archived_history.is_empty());
+    }
+
+    fn user(text: &str) -> ConversationFragment {
+        ConversationFragment::User(text.to_string())
+    }
+
+    fn assistant(text: &str) -> ConversationFragment {
+        ConversationFragment::Assistant(text.to_string())
+    }
+
+    fn editor(pairs: &[(&str, &str)]) -> Vec<ChatTurn> {
+        pairs
+            .iter()
+            .flat_map(|(prompt, reply)| {
+                vec![
+                    ChatTurn::User(prompt.to_string()),
+                    ChatTurn::Assistant(reply.to_string()),
+                ]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reconcile_with_editor_unchanged() {
+        let session = Session {
+            conversation_history: vec![
+                user("one"),
+                assistant("Assistant requested info on open documents."),
+                assistant("reply one"),
+                user("two"),
+                assistant("reply two"),
+            ],
+            ..Default::default()
+        };
+        let turns = editor(&[("one", "reply one"), ("two", "reply two")]);
+
+        assert_eq!(
+            reconcile_with_editor(&session, &turns),
+            session.conversation_history
+        );
+    }
+
+    #[test]
+    fn test_reconcile_with_editor_drops_deleted_turns() {
+        let session = Session {
+            conversation_history: vec![
+                user("one"),
+                assistant("reply one"),
+                user("two"),
+                assistant("App closed: app-1"),
+                user("three"),
+                assistant("reply three"),
+            ],
+            ..Default::default()
+        };
+        let turns = editor(&[("one", "reply one"), ("three", "reply three")]);
+
+        assert_eq!(
+            reconcile_with_editor(&session, &turns),
+            vec![
+                user("one"),
+                assistant("reply one"),
+                user("three"),
+                assistant("reply three"),
+            ]
+        );
+        assert!(reconcile_with_editor(&session, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_reconcile_with_editor_adds_unknown_turns() {
+        let session = Session {
+            conversation_history: vec![user("two"), assistant("reply two")],
+            ..Default::default()
+        };
+        let turns = editor(&[("one", "from editor"), ("two", "reply two")]);
+
+        assert_eq!(
+            reconcile_with_editor(&session, &turns),
+            vec![
+                user("one"),
+                assistant("from editor"),
+                user("two"),
+                assistant("reply two"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reconcile_with_editor_skips_summarized_turns() {
+        let session = Session {
+            conversation_history: vec![
+                ConversationFragment::Summary("talked about one and two".to_string()),
+                user("three"),
+                assistant("reply three"),
+            ],
+            archived_history: vec![
+                user("one"),
+                assistant("reply one"),
+                user("two"),
+                assistant("reply two"),
+            ],
+            ..Default::default()
+        };
+        let turns = editor(&[
+            ("one", "reply one"),
+            ("two", "reply two"),
+            ("three", "reply three"),
+        ]);
+
+        assert_eq!(
+            reconcile_with_editor(&session, &turns),
+            session.conversation_history
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Base URL of an OpenAI-compatible API, e.g. `https://api.openai.com/v1`.
+const URL_VAR: &str = "LSP_AGENT_INFERENCE_URL";
+const API_KEY_VAR: &str = "LSP_AGENT_INFERENCE_API_KEY";
+const MODEL_VAR: &str = "LSP_AGENT_INFERENCE_MODEL";
+const DEFAULT_MODEL: &str = "gpt-5-mini";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// Long enough for a slow model to write a whole app.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Serialize)]
+struct ChatMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct CompletionRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage<'a>>,
+}
+
+#[derive(Deserialize)]
+struct CompletionMessage {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CompletionChoice {
+    message: CompletionMessage,
+}
+
+#[derive(Deserialize)]
+struct CompletionResponse {
+    choices: Vec<CompletionChoice>,
+}
+
+/// Inference through an OpenAI-compatible chat completions endpoint, for
+/// front-ends whose editor cannot run inference itself.
+#[derive(Clone)]
+pub struct HttpInference {
+    client: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+    model: String,
+}
+
+impl HttpInference {
+    pub fn new(url: String, api_key: Option<String>, model: String) -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .connect_timeout(CONNECT_TIMEOUT)
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("failed to build the inference HTTP client"),
+            url: url.trim_end_matches('/').to_string(),
+            api_key,
+            model,
+        }
+    }
+
+    /// Reads `LSP_AGENT_INFERENCE_URL`, `LSP_AGENT_INFERENCE_API_KEY` and
+    /// `LSP_AGENT_INFERENCE_MODEL`. Returns `None` when no URL is set.
+    pub fn from_env() -> Option<Self> {
+        let url = std::env::var(URL_VAR)
+            .ok()
+            .filter(|url| !url.trim().is_empty())?;
+        let api_key = std::env::var(API_KEY_VAR)
+            .ok()
+            .filter(|key| !key.is_empty());
+        let model = std::env::var(MODEL_VAR).unwrap_or_else(|_| DEFAULT_MODEL.to_string());
+        Some(Self::new(url, api_key, model))
+    }
+
+    /// Sends `request` as a single user message. `model` overrides the configured
+    /// model unless it is `auto`, the editor's name for "no preference".
+    pub async fn complete(&self, request: String, model: Option<String>) -> Result<String, String> {
+        let model = model
+            .as_deref()
+            .map(str::trim)
+            .filter(|model| !model.is_empty() && !model.eq_ignore_ascii_case("auto"))
+            .unwrap_or(&self.model);
+        let body = CompletionRequest {
+            model,
+            messages: vec![ChatMessage {
+                role: "user",
+                content: &request,
+            }],
+        };
+        let mut http_request = self
+            .client
+            .post(format!("{}/chat/completions", self.url))
+            .json(&body);
+        if let Some(api_key) = &self.api_key {
+            http_request = http_request.bearer_auth(api_key);
+        }
+        let response = http_request
+            .send()
+            .await
+            .map_err(|e| format!("Inference request failed: {}", e))?;
+        let status = response.status();
+        if !status.is_success() {
+            let detail = response.text().await.unwrap_or_default();
+            return Err(format!("Inference request failed ({}): {}", status, detail));
+        }
+        let completion: CompletionResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Invalid inference response: {}", e))?;
+        completion
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| "Inference response had no content".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use tokio::net::TcpListener;
+
+    /// Serves a completions endpoint that echoes the model and message it was sent.
+    async fn serve() -> String {
+        let app = axum::Router::new().route(
+            "/v1/chat/completions",
+            post(
+                |headers: HeaderMap, body: axum::Json<serde_json::Value>| async move {
+                    if headers.get("authorization").and_then(|v| v.to_str().ok())
+                        != Some("Bearer secret")
+                    {
+                        return Err(StatusCode::UNAUTHORIZED);
+                    }
+                    let reply = format!(
+                        "{}: {}",
+                        body["model"].as_str().unwrap_or_default(),
+                        body["messages"][0]["content"].as_str().unwrap_or_default()
+                    );
+                    Ok(axum::Json(serde_json::json!({
+                        "choices": [{"message": {"role": "assistant", "content": reply}}]
+                    })))
+                },
+            ),
+        );
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/v1/", addr)
+    }
+
+    #[tokio::test]
+    async fn test_complete() {
+        let url = serve().await;
+        let inference = HttpInference::new(
+            url.clone(),
+            Some("secret".to_string()),
+            "default-model".to_string(),
+        );
+
+        assert_eq!(
+            inference.complete("hi".to_string(), None).await.unwrap(),
+            "default-model: hi"
+        );
+        assert_eq!(
+            inference
+                .complete("hi".to_string(), Some("auto".to_string()))
+                .await
+                .unwrap(),
+            "default-model: hi"
+        );
+        assert_eq!(
+            inference
+                .complete("hi".to_string(), Some("other".to_string()))
+                .await
+                .unwrap(),
+            "other: hi"
+        );
+
+        let unauthorized = HttpInference::new(url, None, "default-model".to_string());
+        let error = unauthorized
+            .complete("hi".to_string(), None)
+            .await
+            .unwrap_err();
+        assert!(error.contains("401"));
+    }
+}
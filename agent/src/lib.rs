@@ -1,22 +1,51 @@
+mod app_check;
+pub mod app_errors;
+pub mod app_html;
+mod audit;
+mod bundle;
+mod contributions;
+mod diagnostics;
+mod doc_paths;
 mod document;
+mod edits;
+mod history;
+pub mod http_inference;
+mod library;
+mod limits;
+pub mod pending_replies;
 pub mod prompts;
+mod saved_apps;
+mod sessions;
+mod text_sync;
+mod workspace_files;
 
 pub use document::{
-    AgentRequest, AgentResponse, ConversationFragment, DocumentContent, DocumentManager, Id,
-    LspAgent, NoStorage, StoredValue, Uri,
+    AgentRequest, AgentResponse, AppCodeLens, AppContributions, AppDiagnostic, AppHover,
+    AuditEntry, ConversationFragment, DocumentContent, DocumentManager, EditPosition, EditRange,
+    Id, LaunchContext, LibraryApp, LspAgent, NoStorage, ProposedEdit, Selection, Session,
+    SessionBundle, StoreValueBody, StoredValue, TextDocument, TextDocumentManager, Uri,
 };
 
+use automerge::ReadDoc;
+use automerge::transaction::Transaction;
 use automerge_repo::{ConnDirection, DocHandle, DocumentId, Repo, RepoHandle};
-use autosurgeon::{hydrate, reconcile};
+use autosurgeon::reconcile;
+use limits::{InferenceLimits, RateLimiter};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::process::{Child, Command};
 use tokio::runtime::Handle;
 use tokio::sync::{Mutex, mpsc, oneshot};
 use tokio::time::{Duration, sleep};
-use traits::{InferenceClient, Web, WebAgent, WorkspaceAgent};
+use traits::{
+    AppInfo, ChatTurn, CodeLens, EditProposal, ImportedSession, InferenceClient, LibraryAppInfo,
+    SelectedRange, SessionInfo, TextChange, TextPosition, TextSelection, Web, WebAgent,
+    WorkspaceAgent,
+};
 use uuid::Uuid;
+use workspace_files::WorkspaceAccess;
 
 fn find_repo_root(exe_path: &std::path::Path) -> Option<std::path::PathBuf> {
     for ancestor in exe_path.ancestors() {
@@ -33,33 +62,70 @@ fn find_repo_root(exe_path: &std::path::Path) -> Option<std::path::PathBuf> {
 const PEER1_PORT: u16 = 2341;
 const PEER2_PORT: u16 = 2342;
 const DEFAULT_TOOL_MAX_ITERATIONS: usize = 3;
+const DEFAULT_MAX_REQUEST_CHARS: usize = 200_000;
+/// How much of a rejected app is shown back to the model so it can fix it.
+const MAX_REJECTED_APP_CHARS: usize = 20_000;
+/// Exposed workspace files listed to the agent when it asks for documents.
+const MAX_LISTED_WORKSPACE_FILES: usize = 200;
+
+/// Paths of the open editor documents and the running apps' HTML in the shared document.
+const TEXT_DOCUMENTS: &[&str] = &["text_documents", "documents"];
+const WEBVIEWS: &[&str] = &["webviews", "documents"];
+const SELECTIONS: &[&str] = &["text_documents", "selections"];
+
+fn edit_position(position: TextPosition) -> EditPosition {
+    EditPosition {
+        line: position.line,
+        character: position.character,
+    }
+}
 
 #[derive(Deserialize, Debug)]
 struct ToolResponse {
     action: String,
     message: Option<String>,
     app: Option<String>,
+    app_id: Option<String>,
+    name: Option<String>,
 }
 
 pub fn start_infra(client: Arc<dyn InferenceClient>) -> Box<dyn WorkspaceAgent> {
-    let (doc_handle, task, chat_tx) = start_automerge_infrastructure(client);
+    let (repo_handle, doc_handle, task, chat_tx) = start_automerge_infrastructure(client);
     let child = spawn_web_client();
 
     Box::new(AutomergeAgent {
         doc_handle,
+        repo_handle,
         agent_task: Mutex::new(Some(task)),
         web_child: Mutex::new(child),
         chat_tx,
     })
 }
 
+/// A `WorkspaceAgent` that only edits `doc_handle`, without the agent task and
+/// web client `start_infra` starts, so chat requests go unanswered. Used to
+/// measure document access.
+pub fn document_agent(repo_handle: RepoHandle, doc_handle: DocHandle) -> Box<dyn WorkspaceAgent> {
+    let (chat_tx, _) = mpsc::channel(1);
+    Box::new(AutomergeAgent {
+        doc_handle,
+        repo_handle,
+        agent_task: Mutex::new(None),
+        web_child: Mutex::new(None),
+        chat_tx,
+    })
+}
+
 struct ChatRequest {
+    session: Option<String>,
     content: String,
     model: Option<String>,
+    editor_history: Option<Vec<ChatTurn>>,
     responder: oneshot::Sender<Option<String>>,
 }
 
 struct AutomergeAgent {
+    repo_handle: RepoHandle,
     doc_handle: DocHandle,
     agent_task: Mutex<Option<tokio::task::JoinHandle<()>>>,
     web_child: Mutex<Option<Child>>,
@@ -120,28 +186,39 @@ pub async fn start_web_backend(web: Arc<dyn Web>) -> (Box<dyn WebAgent>, mpsc::R
 impl Web for DocWebSink {
     async fn launch_app(&self, id: String, content: String) {
         self.doc_handle.with_doc_mut(|doc| {
-            let mut agent: LspAgent = hydrate(doc).unwrap();
-            agent.webviews.documents.insert(
-                id.clone(),
+            let mut tx = doc.transaction();
+            doc_paths::put(
+                &mut tx,
+                WEBVIEWS,
+                &id,
                 DocumentContent {
                     text: content.clone(),
                 },
             );
-            agent.responses.push(AgentResponse::WebApp { id, content });
-            let mut tx = doc.transaction();
-            reconcile(&mut tx, &agent).unwrap();
+            doc_paths::push(
+                &mut tx,
+                &[],
+                "responses",
+                AgentResponse::WebApp { id, content },
+            );
             tx.commit();
         });
     }
 
-    async fn handle_inference_response(&self, app_id: String, content: String) {
+    async fn handle_reply(&self, request_id: String, status: u16, content: String) {
+        self.push_response(AgentResponse::Reply {
+            request_id,
+            status,
+            content,
+        });
+    }
+}
+
+impl DocWebSink {
+    fn push_response(&self, response: AgentResponse) {
         self.doc_handle.with_doc_mut(|doc| {
-            let mut agent: LspAgent = hydrate(doc).unwrap();
-            agent
-                .responses
-                .push(AgentResponse::Inference { app_id, content });
             let mut tx = doc.transaction();
-            reconcile(&mut tx, &agent).unwrap();
+            doc_paths::push(&mut tx, &[], "responses", response);
             tx.commit();
         });
     }
@@ -151,16 +228,20 @@ impl Web for DocWebSink {
 impl WorkspaceAgent for AutomergeAgent {
     async fn shutdown(&self) {
         self.doc_handle.with_doc_mut(|doc| {
-            let mut agent: LspAgent = hydrate(doc).unwrap();
-            agent.should_exit = true;
             let mut tx = doc.transaction();
-            reconcile(&mut tx, &agent).unwrap();
+            doc_paths::put(&mut tx, &[], "should_exit", true);
             tx.commit();
         });
 
+        // The repo is only stopped once the agent task has seen `should_exit`;
+        // stopping it from that task could race with the change notification
+        // sent after the write above.
         if let Some(task) = self.agent_task.lock().await.take() {
             let _ = task.await;
         }
+        if let Err(e) = stop_repo(self.repo_handle.clone()).await {
+            eprintln!("[LSP Agent] Failed to stop the document repo: {}", e);
+        }
 
         if let Some(mut child) = self.web_child.lock().await.take() {
             let _ = child.kill().await;
@@ -168,57 +249,114 @@ impl WorkspaceAgent for AutomergeAgent {
         }
     }
 
-    async fn did_open(&self, uri: String, text: String) {
+    async fn did_open(&self, uri: String, language_id: String, version: i32, text: String) {
         self.doc_handle.with_doc_mut(|doc| {
-            let mut agent: LspAgent = hydrate(doc).unwrap();
-            agent
-                .text_documents
-                .documents
-                .insert(uri, DocumentContent { text });
             let mut tx = doc.transaction();
-            reconcile(&mut tx, &agent).unwrap();
+            let document = text_sync::opened(&uri, language_id, version, text);
+            doc_paths::put(&mut tx, TEXT_DOCUMENTS, &uri, document);
             tx.commit();
         });
     }
 
-    async fn did_change(&self, uri: String, text: String) {
+    async fn did_change(&self, uri: String, version: i32, changes: Vec<TextChange>) {
+        // Splice the edits straight into the document's text object rather than
+        // hydrating and reconciling the whole agent state on every keystroke.
         self.doc_handle.with_doc_mut(|doc| {
-            let mut agent: LspAgent = hydrate(doc).unwrap();
-            agent
-                .text_documents
-                .documents
-                .insert(uri, DocumentContent { text });
+            let Some(text) = text_sync::text_object(doc, &uri) else {
+                return;
+            };
+            let mut current = doc.text(&text).unwrap();
             let mut tx = doc.transaction();
-            reconcile(&mut tx, &agent).unwrap();
+            text_sync::apply_changes(&mut tx, &text, &mut current, &changes).unwrap();
+            text_sync::record_change(&mut tx, &uri, version, &current);
+            tx.commit();
+        });
+    }
+
+    async fn did_save(&self, uri: String) {
+        self.doc_handle.with_doc_mut(|doc| {
+            if text_sync::text_object(doc, &uri).is_none() {
+                return;
+            }
+            let mut tx = doc.transaction();
+            doc_paths::put(
+                &mut tx,
+                &["text_documents", "documents", &uri],
+                "dirty",
+                false,
+            );
             tx.commit();
         });
     }
 
     async fn did_close(&self, uri: String) {
         self.doc_handle.with_doc_mut(|doc| {
-            let mut agent: LspAgent = hydrate(doc).unwrap();
-            agent.text_documents.documents.remove(&uri);
             let mut tx = doc.transaction();
-            reconcile(&mut tx, &agent).unwrap();
+            doc_paths::delete(&mut tx, TEXT_DOCUMENTS, &uri);
+            doc_paths::delete(&mut tx, SELECTIONS, &uri);
+            tx.commit();
+        });
+    }
+
+    async fn set_selections(&self, uri: String, selections: Vec<TextSelection>) {
+        let selections: Vec<Selection> = selections
+            .into_iter()
+            .map(|selection| Selection {
+                anchor: edit_position(selection.anchor),
+                active: edit_position(selection.active),
+            })
+            .collect();
+        self.doc_handle.with_doc_mut(|doc| {
+            let mut tx = doc.transaction();
+            doc_paths::put(&mut tx, SELECTIONS, &uri, selections);
             tx.commit();
         });
     }
 
+    async fn hover(&self, uri: String, position: TextPosition) -> Option<String> {
+        self.doc_handle.with_doc(|doc| {
+            let by_app: contributions::ByApp =
+                doc_paths::get(doc, &[], "contributions").unwrap_or_default();
+            contributions::hover(&by_app, &uri, edit_position(position))
+        })
+    }
+
+    async fn code_lenses(&self, uri: String) -> Vec<CodeLens> {
+        self.doc_handle.with_doc(|doc| {
+            let by_app: contributions::ByApp =
+                doc_paths::get(doc, &[], "contributions").unwrap_or_default();
+            contributions::lenses(&by_app)
+                .remove(&uri)
+                .unwrap_or_default()
+        })
+    }
+
     async fn set_active_document(&self, uri: String) {
         self.doc_handle.with_doc_mut(|doc| {
-            let mut agent: LspAgent = hydrate(doc).unwrap();
-            agent.text_documents.active_document = Some(Uri { value: uri });
             let mut tx = doc.transaction();
-            reconcile(&mut tx, &agent).unwrap();
+            doc_paths::put(
+                &mut tx,
+                &["text_documents"],
+                "active_document",
+                Some(Uri { value: uri }),
+            );
             tx.commit();
         });
     }
 
-    async fn chat_request(&self, content: String, model: Option<String>) -> Option<String> {
+    async fn chat_request(
+        &self,
+        session: Option<String>,
+        content: String,
+        model: Option<String>,
+        editor_history: Option<Vec<ChatTurn>>,
+    ) -> Option<String> {
         let (tx, rx) = oneshot::channel();
         let req = ChatRequest {
+            session,
             content,
             model,
+            editor_history,
             responder: tx,
         };
         if self.chat_tx.send(req).await.is_err() {
@@ -226,68 +364,477 @@ impl WorkspaceAgent for AutomergeAgent {
         }
         rx.await.ok().flatten()
     }
+
+    async fn ask_about_selection(&self, selection: SelectedRange) -> Option<String> {
+        let SelectedRange { uri, range } = selection;
+        let selection = Selection {
+            anchor: edit_position(range.start),
+            active: edit_position(range.end),
+        };
+        let content = self.doc_handle.with_doc_mut(|doc| {
+            let text_obj = text_sync::text_object(doc, &uri)?;
+            let text = doc.text(&text_obj).ok()?;
+            let language_id: String =
+                doc_paths::get(doc, &["text_documents", "documents", &uri], "language_id")
+                    .unwrap_or_default();
+            let view = text_sync::selection_view(&uri, &text, &[selection]);
+            let selected = view.selections.first()?;
+            let content = prompts::build_selection_question(
+                &uri,
+                &language_id,
+                selected.start.line,
+                selected.end.line,
+                &selected.text,
+            );
+            let mut tx = doc.transaction();
+            doc_paths::put(
+                &mut tx,
+                &["text_documents"],
+                "active_document",
+                Some(Uri { value: uri.clone() }),
+            );
+            doc_paths::put(&mut tx, SELECTIONS, &uri, vec![selection]);
+            tx.commit();
+            Some(content)
+        })?;
+        self.chat_request(None, content, None, None).await
+    }
+
+    async fn audit_log(&self) -> String {
+        self.doc_handle
+            .with_doc(|doc| audit::render(&audit::entries(doc)))
+    }
+
+    async fn create_session(&self, name: String) -> Result<(), String> {
+        self.update(|tx| sessions::create(tx, &name))
+    }
+
+    async fn switch_session(&self, name: String) -> Result<(), String> {
+        self.update(|tx| sessions::switch(tx, &name))
+    }
+
+    async fn list_sessions(&self) -> Vec<SessionInfo> {
+        self.doc_handle.with_doc(sessions::list)
+    }
+
+    async fn delete_session(&self, name: String) -> Result<(), String> {
+        self.update(|tx| sessions::delete(tx, &name))
+    }
+
+    async fn export_session(&self, name: Option<String>) -> Result<Vec<u8>, String> {
+        self.doc_handle.with_doc(|doc| {
+            let name = sessions::resolve_name(doc, name.as_deref());
+            bundle::export(doc, &name)
+        })
+    }
+
+    async fn import_session(&self, bytes: Vec<u8>) -> Result<ImportedSession, String> {
+        self.update(|tx| bundle::import(tx, &bytes))
+    }
+
+    async fn set_workspace_folders(&self, folders: Vec<String>) {
+        self.doc_handle.with_doc_mut(|doc| {
+            let mut tx = doc.transaction();
+            doc_paths::put(&mut tx, &[], "workspace_folders", folders);
+            tx.commit();
+        });
+    }
+
+    async fn set_exposed_files(&self, patterns: Vec<String>) -> Result<(), String> {
+        WorkspaceAccess::new(&[], &patterns)?;
+        self.doc_handle.with_doc_mut(|doc| {
+            let mut tx = doc.transaction();
+            doc_paths::put(&mut tx, &[], "exposed_files", patterns);
+            tx.commit();
+        });
+        Ok(())
+    }
+
+    async fn list_apps(&self) -> Vec<AppInfo> {
+        self.doc_handle.with_doc(|doc| {
+            let webviews: HashMap<String, DocumentContent> =
+                doc_paths::get(doc, &["webviews"], "documents").unwrap_or_default();
+            let mut errors: HashMap<String, Vec<String>> =
+                doc_paths::get(doc, &[], "app_errors").unwrap_or_default();
+            let mut apps: Vec<AppInfo> = webviews
+                .iter()
+                .map(|(id, content)| AppInfo {
+                    id: id.clone(),
+                    title: app_html::title(&content.text).unwrap_or_else(|| id.clone()),
+                    errors: errors.remove(id).unwrap_or_default(),
+                })
+                .collect();
+            apps.sort_by(|a, b| a.title.cmp(&b.title));
+            apps
+        })
+    }
+
+    async fn save_app(&self, app_id: String, name: String) -> Result<String, String> {
+        let (root, html) = self.doc_handle.with_doc(|doc| {
+            let html = doc_paths::get::<_, DocumentContent>(doc, WEBVIEWS, &app_id)
+                .map(|content| content.text)
+                .ok_or_else(|| format!("App '{}' is not running.", app_id))?;
+            Ok::<_, String>((saved_apps::workspace_root(doc)?, html))
+        })?;
+        let path = saved_apps::save(&root, &name, &html)?;
+        Ok(path.display().to_string())
+    }
+
+    async fn list_saved_apps(&self) -> Vec<String> {
+        let root = self.doc_handle.with_doc(saved_apps::workspace_root);
+        root.map(|root| saved_apps::list(&root)).unwrap_or_default()
+    }
+
+    async fn launch_saved_app(
+        &self,
+        name: String,
+        selection: Option<SelectedRange>,
+    ) -> Result<String, String> {
+        let root = self.doc_handle.with_doc(saved_apps::workspace_root)?;
+        let html = saved_apps::load(&root, &name)?;
+        let app_id = format!("app-{}", Uuid::new_v4());
+        let prompt = format!("Launched from {}/{}.html", saved_apps::APPS_DIR, name);
+        self.launch_in_active_session(&app_id, html, |tx, html| {
+            library::record_launch(tx, &app_id, &prompt, html);
+            if let Some(SelectedRange { uri, range }) = selection {
+                let selection = Selection {
+                    anchor: edit_position(range.start),
+                    active: edit_position(range.end),
+                };
+                doc_paths::put(
+                    tx,
+                    &["launch_contexts"],
+                    &app_id,
+                    LaunchContext { uri, selection },
+                );
+            }
+            Ok(())
+        })
+        .await?;
+        Ok(app_id)
+    }
+
+    async fn app_library(&self) -> Vec<LibraryAppInfo> {
+        self.doc_handle.with_doc(library::list)
+    }
+
+    async fn relaunch_app(&self, app_id: String) -> Result<(), String> {
+        let html = self
+            .doc_handle
+            .with_doc(|doc| library::relaunchable(doc, &app_id))?;
+        self.launch_in_active_session(&app_id, html, |tx, html| {
+            library::touch(tx, &app_id, html);
+            Ok(())
+        })
+        .await
+    }
+}
+
+impl AutomergeAgent {
+    /// Registers `app_id` with the active session, applies `update`, then launches
+    /// the app through the shared document.
+    async fn launch_in_active_session(
+        &self,
+        app_id: &str,
+        html: String,
+        update: impl FnOnce(&mut Transaction<'_>, &str) -> Result<(), String>,
+    ) -> Result<(), String> {
+        self.update(|tx| {
+            update(tx, &html)?;
+            let session_name = sessions::active_name(tx);
+            sessions::add_app(tx, &session_name, app_id);
+            Ok(())
+        })?;
+        let sink = DocWebSink {
+            doc_handle: self.doc_handle.clone(),
+        };
+        sink.launch_app(app_id.to_string(), html).await;
+        Ok(())
+    }
+
+    /// Applies `update` in one transaction, which is only committed if it succeeds.
+    fn update<T>(
+        &self,
+        update: impl FnOnce(&mut Transaction<'_>) -> Result<T, String>,
+    ) -> Result<T, String> {
+        self.doc_handle.with_doc_mut(|doc| {
+            let mut tx = doc.transaction();
+            let result = update(&mut tx);
+            if result.is_ok() {
+                tx.commit();
+            } else {
+                tx.rollback();
+            }
+            result
+        })
+    }
 }
 
 #[async_trait::async_trait]
 impl WebAgent for DocWebAgent {
-    async fn app_inference_request(&self, content: String, app_id: String) {
+    async fn app_inference_request(&self, request_id: String, content: String, app_id: String) {
         self.doc_handle.with_doc_mut(|doc| {
-            let mut agent: LspAgent = hydrate(doc).unwrap();
-            agent
-                .requests
-                .push(AgentRequest::Inference { content, app_id });
             let mut tx = doc.transaction();
-            reconcile(&mut tx, &agent).unwrap();
+            audit::record(&mut tx, &app_id, "inference", "", content.len(), 0);
+            doc_paths::push(
+                &mut tx,
+                &[],
+                "requests",
+                AgentRequest::Inference {
+                    request_id,
+                    content,
+                    app_id,
+                },
+            );
             tx.commit();
         });
     }
 
-    async fn read_document(&self, uri: String) -> String {
-        self.doc_handle.with_doc(|doc| {
-            let agent: LspAgent = hydrate(doc).unwrap();
-            agent
-                .text_documents
-                .documents
-                .get(&uri)
-                .map(|doc| doc.text.clone())
-                .unwrap_or_default()
+    async fn read_document(&self, app_id: String, uri: String) -> String {
+        self.doc_handle.with_doc_mut(|doc| {
+            let text = doc_paths::get::<_, TextDocument>(doc, TEXT_DOCUMENTS, &uri)
+                .map(|doc| doc.text.as_str().to_string())
+                .unwrap_or_default();
+            let mut tx = doc.transaction();
+            audit::record(&mut tx, &app_id, "read_document", &uri, 0, text.len());
+            tx.commit();
+            text
+        })
+    }
+
+    async fn read_selection(&self, app_id: String, uri: String) -> Option<String> {
+        self.doc_handle.with_doc_mut(|doc| {
+            let uri = match uri.trim() {
+                "" => {
+                    doc_paths::get::<_, Option<Uri>>(doc, &["text_documents"], "active_document")
+                        .flatten()?
+                        .value
+                }
+                uri => uri.to_string(),
+            };
+            // Like documents, selections are only readable while the document is open.
+            let text_obj = text_sync::text_object(doc, &uri)?;
+            let text = doc.text(&text_obj).ok()?;
+            let selections: Vec<Selection> = doc_paths::get_list(doc, SELECTIONS, &uri);
+            let view = text_sync::selection_view(&uri, &text, &selections);
+            let json = serde_json::to_string(&view).ok()?;
+            let mut tx = doc.transaction();
+            audit::record(&mut tx, &app_id, "read_selection", &uri, 0, json.len());
+            tx.commit();
+            Some(json)
+        })
+    }
+
+    async fn read_launch_context(&self, app_id: String) -> Option<String> {
+        self.doc_handle.with_doc_mut(|doc| {
+            let context: LaunchContext = doc_paths::get(doc, &["launch_contexts"], &app_id)?;
+            let text_obj = text_sync::text_object(doc, &context.uri)?;
+            let text = doc.text(&text_obj).ok()?;
+            let view = text_sync::selection_view(&context.uri, &text, &[context.selection]);
+            let json = serde_json::to_string(&view).ok()?;
+            let mut tx = doc.transaction();
+            audit::record(
+                &mut tx,
+                &app_id,
+                "read_launch_context",
+                &context.uri,
+                0,
+                json.len(),
+            );
+            tx.commit();
+            Some(json)
         })
     }
 
     async fn close_app(&self, app_id: String) {
         self.doc_handle.with_doc_mut(|doc| {
-            let mut agent: LspAgent = hydrate(doc).unwrap();
-            agent.webviews.documents.remove(&app_id);
-            agent
-                .conversation_history
-                .push(ConversationFragment::Assistant(format!(
-                    "App closed: {}",
-                    app_id
-                )));
             let mut tx = doc.transaction();
-            reconcile(&mut tx, &agent).unwrap();
+            if let Some(content) = doc_paths::get::<_, DocumentContent>(&tx, WEBVIEWS, &app_id) {
+                doc_paths::delete(&mut tx, WEBVIEWS, &app_id);
+                doc_paths::delete(&mut tx, &["diagnostics"], &app_id);
+                doc_paths::delete(&mut tx, &["contributions"], &app_id);
+                doc_paths::delete(&mut tx, &["launch_contexts"], &app_id);
+                doc_paths::delete(&mut tx, &["app_errors"], &app_id);
+                let existing = doc_paths::get(&tx, &["app_library"], &app_id);
+                let app = library::touched(existing, &content.text);
+                doc_paths::put(&mut tx, &["app_library"], &app_id, &app);
+            }
+            if let Some(owner) = sessions::owner_of(&tx, &app_id) {
+                let mut apps: Vec<String> = doc_paths::get_list(&tx, &["sessions", &owner], "apps");
+                apps.retain(|id| id != &app_id);
+                doc_paths::put(&mut tx, &["sessions", &owner], "apps", apps);
+                sessions::extend_history(
+                    &mut tx,
+                    &owner,
+                    [ConversationFragment::Assistant(format!(
+                        "App closed: {}",
+                        app_id
+                    ))],
+                );
+            }
+            audit::record(&mut tx, &app_id, "close_app", "", 0, 0);
             tx.commit();
         });
     }
 
-    async fn store_value(&self, key: String, value: String, description: String) {
+    async fn store_value(&self, app_id: String, key: String, value: String, description: String) {
         self.doc_handle.with_doc_mut(|doc| {
-            let mut agent: LspAgent = hydrate(doc).unwrap();
-            agent
-                .stored_values
-                .insert(key, StoredValue { value, description });
             let mut tx = doc.transaction();
-            reconcile(&mut tx, &agent).unwrap();
+            audit::record(&mut tx, &app_id, "store_value", &key, value.len(), 0);
+            bundle::note_value_use(&mut tx, &app_id, &key);
+            doc_paths::put(
+                &mut tx,
+                &["stored_values"],
+                &key,
+                StoredValue { value, description },
+            );
             tx.commit();
         });
     }
 
-    async fn read_value(&self, key: String) -> Option<String> {
-        self.doc_handle.with_doc(|doc| {
-            let agent: LspAgent = hydrate(doc).unwrap();
-            agent.stored_values.get(&key).map(|v| v.value.clone())
+    async fn read_value(&self, app_id: String, key: String) -> Option<String> {
+        self.doc_handle.with_doc_mut(|doc| {
+            let value =
+                doc_paths::get::<_, StoredValue>(doc, &["stored_values"], &key).map(|v| v.value);
+            let response_bytes = value.as_ref().map(|v| v.len()).unwrap_or_default();
+            let mut tx = doc.transaction();
+            audit::record(&mut tx, &app_id, "read_value", &key, 0, response_bytes);
+            bundle::note_value_use(&mut tx, &app_id, &key);
+            tx.commit();
+            value
+        })
+    }
+
+    async fn read_workspace_file(&self, request_id: String, app_id: String, path: String) {
+        self.doc_handle.with_doc_mut(|doc| {
+            let mut tx = doc.transaction();
+            audit::record(&mut tx, &app_id, "read_workspace_file", &path, 0, 0);
+            doc_paths::push(
+                &mut tx,
+                &[],
+                "requests",
+                AgentRequest::ReadWorkspaceFile {
+                    request_id,
+                    app_id,
+                    path,
+                },
+            );
+            tx.commit();
+        });
+    }
+
+    async fn propose_edit(
+        &self,
+        request_id: String,
+        app_id: String,
+        body: String,
+    ) -> Result<(), String> {
+        let (uri, edits) = edits::parse_body(&body)?;
+        self.doc_handle.with_doc_mut(|doc| {
+            let mut tx = doc.transaction();
+            let request_bytes = edits.iter().map(|edit| edit.text.len()).sum();
+            audit::record(&mut tx, &app_id, "propose_edit", &uri, request_bytes, 0);
+            doc_paths::push(
+                &mut tx,
+                &[],
+                "requests",
+                AgentRequest::ProposeEdit {
+                    request_id,
+                    app_id,
+                    uri,
+                    edits,
+                },
+            );
+            tx.commit();
+        });
+        Ok(())
+    }
+
+    async fn publish_diagnostics(&self, app_id: String, body: String) -> Result<(), String> {
+        let (uri, mut diagnostics) = diagnostics::parse_body(&body)?;
+        self.doc_handle.with_doc_mut(|doc| {
+            // Apps may only annotate documents they could read.
+            if text_sync::text_object(doc, &uri).is_none() {
+                return Err(format!("'{}' is not open.", uri));
+            }
+            let source = doc_paths::get::<_, DocumentContent>(doc, WEBVIEWS, &app_id)
+                .and_then(|content| app_html::title(&content.text))
+                .unwrap_or_else(|| app_id.clone());
+            for diagnostic in &mut diagnostics {
+                diagnostic.source = source.clone();
+            }
+            let mut tx = doc.transaction();
+            audit::record(&mut tx, &app_id, "publish_diagnostics", &uri, body.len(), 0);
+            if diagnostics.is_empty() {
+                doc_paths::delete(&mut tx, &["diagnostics", &app_id], &uri);
+            } else {
+                doc_paths::put(&mut tx, &["diagnostics", &app_id], &uri, diagnostics);
+            }
+            tx.commit();
+            Ok(())
+        })
+    }
+
+    async fn publish_contributions(&self, app_id: String, body: String) -> Result<(), String> {
+        let (uri, contributions) = contributions::parse_body(&body)?;
+        self.doc_handle.with_doc_mut(|doc| {
+            if text_sync::text_object(doc, &uri).is_none() {
+                return Err(format!("'{}' is not open.", uri));
+            }
+            let mut tx = doc.transaction();
+            audit::record(
+                &mut tx,
+                &app_id,
+                "publish_contributions",
+                &uri,
+                body.len(),
+                0,
+            );
+            if contributions == AppContributions::default() {
+                doc_paths::delete(&mut tx, &["contributions", &app_id], &uri);
+            } else {
+                doc_paths::put(&mut tx, &["contributions", &app_id], &uri, contributions);
+            }
+            tx.commit();
+            Ok(())
         })
     }
+
+    async fn log_error(&self, app_id: String, message: String) {
+        self.doc_handle.with_doc_mut(|doc| {
+            // Errors of closed apps would never be cleared.
+            if doc_paths::get::<_, DocumentContent>(doc, WEBVIEWS, &app_id).is_none() {
+                return;
+            }
+            let errors = doc_paths::get(doc, &["app_errors"], &app_id).unwrap_or_default();
+            let mut tx = doc.transaction();
+            doc_paths::put(
+                &mut tx,
+                &["app_errors"],
+                &app_id,
+                app_errors::recorded(errors, &message),
+            );
+            tx.commit();
+        });
+    }
+
+    async fn list_directory(&self, request_id: String, app_id: String, path: String) {
+        self.doc_handle.with_doc_mut(|doc| {
+            let mut tx = doc.transaction();
+            audit::record(&mut tx, &app_id, "list_directory", &path, 0, 0);
+            doc_paths::push(
+                &mut tx,
+                &[],
+                "requests",
+                AgentRequest::ListDirectory {
+                    request_id,
+                    app_id,
+                    path,
+                },
+            );
+            tx.commit();
+        });
+    }
 }
 
 async fn setup_web_doc() -> DocHandle {
@@ -340,13 +887,14 @@ async fn wait_for_doc_id() -> DocumentId {
 
 async fn handle_web_doc_change(doc_handle: &DocHandle, web: &dyn Web) -> bool {
     let (should_exit, should_handle_response) = doc_handle.with_doc(|doc| {
-        let agent: LspAgent = hydrate(doc).unwrap();
-        let handle = match agent.responses.first() {
+        let responses: Vec<AgentResponse> = doc_paths::get_list(doc, &[], "responses");
+        let handle = match responses.first() {
             Some(AgentResponse::Chat(_)) => false,
             Some(_) => true,
             None => false,
         };
-        (agent.should_exit, handle)
+        let should_exit = doc_paths::get(doc, &[], "should_exit").unwrap_or(false);
+        (should_exit, handle)
     });
 
     if should_exit {
@@ -365,19 +913,12 @@ async fn handle_web_doc_change(doc_handle: &DocHandle, web: &dyn Web) -> bool {
 
 fn take_response(doc_handle: &DocHandle) -> Option<AgentResponse> {
     doc_handle.with_doc_mut(|doc| {
-        let mut agent: LspAgent = match hydrate(doc) {
-            Ok(a) => a,
-            Err(_) => return None,
-        };
-        let resp = if !agent.responses.is_empty() {
-            Some(agent.responses.remove(0))
-        } else {
-            None
-        };
+        let responses: Vec<AgentResponse> = doc_paths::get_list(doc, &[], "responses");
+        let resp = responses.into_iter().next();
 
         if resp.is_some() {
             let mut tx = doc.transaction();
-            reconcile(&mut tx, &agent).unwrap();
+            doc_paths::drain_front(&mut tx, &[], "responses", 1);
             tx.commit();
         }
 
@@ -393,8 +934,12 @@ async fn handle_web_response(web: &dyn Web, resp: AgentResponse) {
         AgentResponse::Chat(_) => {
             debug_assert!(false, "Web backend should not consume chat responses");
         }
-        AgentResponse::Inference { app_id, content } => {
-            web.handle_inference_response(app_id, content).await;
+        AgentResponse::Reply {
+            request_id,
+            status,
+            content,
+        } => {
+            web.handle_reply(request_id, status, content).await;
         }
     }
 }
@@ -411,18 +956,31 @@ fn spawn_web_client() -> Option<Child> {
             .to_path_buf()
     });
 
+    // `LSP_AGENT_WEB_RUNTIME=browser` serves apps to the user's browser instead
+    // of opening wry windows, `headless` runs them without a display.
+    let runtime = match std::env::var("LSP_AGENT_WEB_RUNTIME").as_deref() {
+        Ok("browser") => "browser",
+        Ok("headless") => "headless",
+        _ => "web",
+    };
     let mut candidates = Vec::new();
     if let Ok(override_path) = std::env::var("LSP_AGENT_WEB_BINARY") {
         candidates.push(std::path::PathBuf::from(override_path));
     }
-    candidates.push(project_root.join("target/debug/web"));
-    candidates.push(project_root.join("web/target/debug/web"));
+    candidates.push(project_root.join("target/debug").join(runtime));
+    candidates.push(
+        project_root
+            .join(runtime)
+            .join("target/debug")
+            .join(runtime),
+    );
 
     let web_binary = match candidates.into_iter().find(|path| path.exists()) {
         Some(path) => path,
         None => {
             eprintln!(
-                "[LSP Agent] Web client binary not found. Rebuild the web crate or set LSP_AGENT_WEB_BINARY."
+                "[LSP Agent] Web client binary not found. Rebuild the {} crate or set LSP_AGENT_WEB_BINARY.",
+                runtime
             );
             return None;
         }
@@ -451,6 +1009,7 @@ fn spawn_web_client() -> Option<Child> {
 fn start_automerge_infrastructure(
     client: Arc<dyn InferenceClient>,
 ) -> (
+    RepoHandle,
     DocHandle,
     tokio::task::JoinHandle<()>,
     mpsc::Sender<ChatRequest>,
@@ -488,10 +1047,13 @@ fn start_automerge_infrastructure(
     });
     let main_task_repo_handle = repo_handle1.clone();
     let main_task_client = client.clone();
+    let limiter = Arc::new(RateLimiter::new(InferenceLimits::from_env()));
 
     let (chat_tx, mut chat_rx) = mpsc::channel::<ChatRequest>(32);
     let main_task = handle.spawn(async move {
         spawn_peer_connections(main_task_repo_handle.clone());
+        let mut published_diagnostics = diagnostics::Published::new();
+        let mut shown_lenses = contributions::Lenses::new();
 
         loop {
             tokio::select! {
@@ -500,15 +1062,41 @@ fn start_automerge_infrastructure(
                         break;
                     }
 
-                    let (should_exit, pending_request, active_model) = check_agent_state(&main_task_doc_handle);
+                    let (should_exit, pending_requests, active_model) = check_agent_state(&main_task_doc_handle);
 
                     if should_exit {
-                        perform_shutdown(&main_task_client, &main_task_repo_handle).await;
+                        main_task_client.notify_shutdown().await;
                         break;
                     }
 
-                    if let Some(req) = pending_request {
-                        handle_inference_request(req, &main_task_client, active_model, web_sink.as_ref()).await;
+                    sync_diagnostics(&main_task_doc_handle, main_task_client.as_ref(), &mut published_diagnostics).await;
+                    sync_code_lenses(&main_task_doc_handle, main_task_client.as_ref(), &mut shown_lenses).await;
+
+                    for req in pending_requests {
+                        let web_sink = web_sink.clone();
+                        match req {
+                            AgentRequest::Inference { request_id, content, app_id } => {
+                                let client = main_task_client.clone();
+                                let limiter = limiter.clone();
+                                let active_model = active_model.clone();
+                                tokio::spawn(async move {
+                                    handle_inference_request(request_id, content, app_id, &client, active_model, web_sink.as_ref(), &limiter).await;
+                                });
+                            }
+                            AgentRequest::ProposeEdit { request_id, app_id, uri, edits } => {
+                                let proposal = edit_proposal(&main_task_doc_handle, &app_id, uri, edits);
+                                let client = main_task_client.clone();
+                                tokio::spawn(async move {
+                                    handle_edit_proposal(request_id, proposal, &client, web_sink.as_ref()).await;
+                                });
+                            }
+                            AgentRequest::ReadWorkspaceFile { .. } | AgentRequest::ListDirectory { .. } => {
+                                let access = workspace_access(&main_task_doc_handle);
+                                tokio::spawn(async move {
+                                    handle_workspace_request(req, access, web_sink.as_ref()).await;
+                                });
+                            }
+                        }
                     }
                 }
                 Some(chat_req) = chat_rx.recv() => {
@@ -521,71 +1109,205 @@ fn start_automerge_infrastructure(
         }
     });
 
-    (doc_handle, main_task, chat_tx)
+    (repo_handle1, doc_handle, main_task, chat_tx)
 }
 
-fn check_agent_state(doc_handle: &DocHandle) -> (bool, Option<AgentRequest>, Option<String>) {
+fn check_agent_state(doc_handle: &DocHandle) -> (bool, Vec<AgentRequest>, Option<String>) {
     doc_handle.with_doc_mut(|doc| {
-        let mut agent: LspAgent = hydrate(doc).unwrap();
-        let req = if !agent.requests.is_empty() {
-            Some(agent.requests.remove(0))
-        } else {
-            None
-        };
+        let reqs: Vec<AgentRequest> = doc_paths::get_list(doc, &[], "requests");
 
-        if req.is_some() {
+        if !reqs.is_empty() {
             let mut tx = doc.transaction();
-            reconcile(&mut tx, &agent).unwrap();
+            doc_paths::drain_front(&mut tx, &[], "requests", reqs.len());
             tx.commit();
         }
 
-        (agent.should_exit, req, agent.active_model)
+        let should_exit = doc_paths::get(doc, &[], "should_exit").unwrap_or(false);
+        let active_model = doc_paths::get(doc, &[], "active_model").flatten();
+        (should_exit, reqs, active_model)
     })
 }
 
-async fn perform_shutdown(client: &Arc<dyn InferenceClient>, repo_handle: &RepoHandle) {
-    client.notify_shutdown().await;
-    let repo_handle = repo_handle.clone();
-    Handle::current()
-        .spawn_blocking(move || {
-            repo_handle.stop().unwrap();
-        })
-        .await
-        .unwrap();
+/// Forwards changes to the apps' diagnostics to the editor. `published` is what
+/// the editor currently shows and is updated.
+async fn sync_diagnostics(
+    doc_handle: &DocHandle,
+    client: &dyn InferenceClient,
+    published: &mut diagnostics::Published,
+) {
+    let current = doc_handle.with_doc(|doc| {
+        let by_app: HashMap<String, HashMap<String, Vec<AppDiagnostic>>> =
+            doc_paths::get(doc, &[], "diagnostics").unwrap_or_default();
+        diagnostics::merged(&by_app)
+    });
+    for (uri, diagnostics) in diagnostics::changes(published, &current) {
+        client.publish_diagnostics(uri, diagnostics).await;
+    }
+    *published = current;
+}
+
+/// Asks the editor to refresh its code lenses when apps changed them since `shown`.
+async fn sync_code_lenses(
+    doc_handle: &DocHandle,
+    client: &dyn InferenceClient,
+    shown: &mut contributions::Lenses,
+) {
+    let current = doc_handle.with_doc(|doc| {
+        let by_app: contributions::ByApp =
+            doc_paths::get(doc, &[], "contributions").unwrap_or_default();
+        contributions::lenses(&by_app)
+    });
+    if current != *shown {
+        client.refresh_code_lenses().await;
+        *shown = current;
+    }
 }
 
+/// Runs an app's inference request and replies to `request_id` with the
+/// result, or with a 413 or 429 status when it is rejected by `limiter`.
 async fn handle_inference_request(
-    req: AgentRequest,
+    request_id: String,
+    content: String,
+    app_id: String,
     client: &Arc<dyn InferenceClient>,
     active_model: Option<String>,
     web_sink: &dyn Web,
+    limiter: &RateLimiter,
 ) {
-    match req {
-        AgentRequest::Inference { content, app_id } => {
-            let response_str = call_inference(client.as_ref(), content, active_model).await;
+    if let Err(too_large) = limiter.check_prompt_size(&content) {
+        web_sink
+            .handle_reply(request_id, 413, too_large.to_string())
+            .await;
+        return;
+    }
+    let permit = match limiter.try_acquire(&app_id, &content) {
+        Ok(permit) => permit,
+        Err(throttled) => {
+            if limiter.should_notify(&app_id) {
+                client
+                    .show_message(format!(
+                        "LSP Agent throttled app {}: {}.",
+                        app_id, throttled
+                    ))
+                    .await;
+            }
             web_sink
-                .handle_inference_response(app_id, response_str)
+                .handle_reply(request_id, 429, throttled.to_string())
                 .await;
+            return;
         }
-    }
+    };
+    let response_str = call_inference(client.as_ref(), content, active_model).await;
+    drop(permit);
+    web_sink.handle_reply(request_id, 200, response_str).await;
 }
 
-fn spawn_peer_connections(repo_handle: RepoHandle) {
-    let repo_clone1 = repo_handle.clone();
-    tokio::spawn(async move {
-        let addr1 = format!("127.0.0.1:{}", PEER1_PORT);
-        let listener = TcpListener::bind(addr1).await.unwrap();
-        loop {
-            if let Ok((socket, addr)) = listener.accept().await {
-                repo_clone1
-                    .connect_tokio_io(addr, socket, ConnDirection::Incoming)
-                    .await
-                    .unwrap();
-            }
-        }
+/// Builds the proposal the user is asked to approve, labelled with the app's title.
+fn edit_proposal(
+    doc_handle: &DocHandle,
+    app_id: &str,
+    uri: String,
+    edits: Vec<ProposedEdit>,
+) -> Result<EditProposal, String> {
+    let (text, version, title) = doc_handle.with_doc(|doc| {
+        let text = text_sync::text_object(doc, &uri).and_then(|obj| doc.text(&obj).ok());
+        let version = doc_paths::get(doc, &["text_documents", "documents", &uri], "version");
+        let html = doc_paths::get::<_, DocumentContent>(doc, WEBVIEWS, app_id);
+        let title = html.and_then(|content| app_html::title(&content.text));
+        (text, version, title)
     });
+    let label = format!("Edit proposed by {}", title.as_deref().unwrap_or(app_id));
+    edits::proposal(uri, label, edits, text.as_deref(), version)
+}
 
-    let repo_clone2 = repo_handle.clone();
+/// Asks the user to review an app's edit and tells the app the outcome: `200` when
+/// applied, `409` when the user declined it.
+async fn handle_edit_proposal(
+    request_id: String,
+    proposal: Result<EditProposal, String>,
+    client: &Arc<dyn InferenceClient>,
+    web_sink: &dyn Web,
+) {
+    let (status, content) = match proposal {
+        Ok(proposal) => match client.apply_edit(proposal).await {
+            Ok(true) => (200, "The edit was applied.".to_string()),
+            Ok(false) => (409, "The user declined the edit.".to_string()),
+            Err(e) => (500, format!("Failed to apply the edit: {}", e)),
+        },
+        Err(e) => (400, e),
+    };
+    web_sink.handle_reply(request_id, status, content).await;
+}
+
+/// Builds the workspace file rules from the folders and patterns in the doc.
+fn workspace_access(doc_handle: &DocHandle) -> Result<WorkspaceAccess, String> {
+    let (folders, patterns): (Vec<String>, Vec<String>) = doc_handle.with_doc(|doc| {
+        (
+            doc_paths::get_list(doc, &[], "workspace_folders"),
+            doc_paths::get_list(doc, &[], "exposed_files"),
+        )
+    });
+    WorkspaceAccess::new(&folders, &patterns)
+}
+
+/// Answers an app's workspace file or directory request. File system access runs
+/// on the blocking pool since listings may walk large directory trees.
+async fn handle_workspace_request(
+    req: AgentRequest,
+    access: Result<WorkspaceAccess, String>,
+    web_sink: &dyn Web,
+) {
+    let (request_id, reply) = match req {
+        AgentRequest::ReadWorkspaceFile {
+            request_id, path, ..
+        } => (
+            request_id,
+            tokio::task::spawn_blocking(move || access?.read_file(&path)),
+        ),
+        AgentRequest::ListDirectory {
+            request_id, path, ..
+        } => (
+            request_id,
+            tokio::task::spawn_blocking(move || {
+                let entries = access?.list_directory(&path)?;
+                Ok(serde_json::to_string(&entries).unwrap_or_else(|_| "[]".to_string()))
+            }),
+        ),
+        AgentRequest::Inference { .. } | AgentRequest::ProposeEdit { .. } => return,
+    };
+    let (status, content) = match reply.await {
+        Ok(Ok(content)) => (200, content),
+        Ok(Err(e)) => (e.status, e.message),
+        Err(e) => (500, e.to_string()),
+    };
+    web_sink.handle_reply(request_id, status, content).await;
+}
+
+/// Stops the repo off the async runtime, since it joins the repo's thread.
+async fn stop_repo(repo_handle: RepoHandle) -> Result<(), String> {
+    Handle::current()
+        .spawn_blocking(move || repo_handle.stop())
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| format!("{:?}", e))
+}
+
+fn spawn_peer_connections(repo_handle: RepoHandle) {
+    let repo_clone1 = repo_handle.clone();
+    tokio::spawn(async move {
+        let addr1 = format!("127.0.0.1:{}", PEER1_PORT);
+        let listener = TcpListener::bind(addr1).await.unwrap();
+        loop {
+            if let Ok((socket, addr)) = listener.accept().await {
+                repo_clone1
+                    .connect_tokio_io(addr, socket, ConnDirection::Incoming)
+                    .await
+                    .unwrap();
+            }
+        }
+    });
+
+    let repo_clone2 = repo_handle.clone();
     tokio::spawn(async move {
         let addr = format!("127.0.0.1:{}", PEER2_PORT);
         loop {
@@ -612,29 +1334,60 @@ async fn handle_chat_request(
     web_sink: &dyn Web,
 ) {
     let ChatRequest {
+        session: session_hint,
         content: latest_user,
         model: model_hint,
+        editor_history,
         responder,
     } = chat_req;
 
-    let (mut history, running_apps, docs_info, stored_values_info) = doc_handle.with_doc(|doc| {
-        let agent: LspAgent = hydrate(doc).unwrap();
-        (
-            agent.conversation_history.clone(),
-            collect_apps(&agent.webviews),
-            collect_docs(&agent.text_documents),
-            collect_stored_values(&agent.stored_values),
-        )
-    });
+    // The editor is the source of truth for what the user sees; align the
+    // session with it before building the prompt. A thread without turns has
+    // none from this session, so there is nothing to align.
+    if let Some(turns) = editor_history.filter(|turns| !turns.is_empty()) {
+        doc_handle.with_doc_mut(|doc| {
+            let session_name = sessions::resolve_name(doc, session_hint.as_deref());
+            let session = sessions::session(doc, &session_name);
+            let reconciled = history::reconcile_with_editor(&session, &turns);
+            if reconciled != session.conversation_history {
+                let mut tx = doc.transaction();
+                sessions::set_history(&mut tx, &session_name, reconciled);
+                tx.commit();
+            }
+        });
+    }
+
+    let (session_name, mut history, apps_info, docs_info, stored_values_info, workspace_root) =
+        doc_handle.with_doc(|doc| {
+            let session_name = sessions::resolve_name(doc, session_hint.as_deref());
+            let session = sessions::session(doc, &session_name);
+            let apps_info = prompts::AppsInfo {
+                running: collect_apps(doc, &session.apps),
+                closed: library::closed_apps(doc),
+            };
+            (
+                session_name,
+                session.conversation_history,
+                apps_info,
+                collect_docs(doc),
+                collect_stored_values(doc),
+                saved_apps::workspace_root(doc),
+            )
+        });
 
     let initial_history_len = history.len();
 
-    let mut apps_payload: Option<Vec<String>> = None;
+    let mut apps_payload: Option<prompts::AppsInfo> = None;
     let mut docs_payload: Option<prompts::DocsInfo> = None;
     let mut stored_values_payload: Option<Vec<prompts::StoredValueInfo>> = None;
     let mut response_message: Option<String> = None;
     let mut launched_app: Option<String> = None;
+    let mut relaunched_app_id: Option<String> = None;
     let mut did_nothing = false;
+    let mut app_problems: Vec<String> = Vec::new();
+    // Only the request right after a rejection carries the app; history keeps
+    // just the problems.
+    let mut rejected_app: Option<String> = None;
 
     let mut current_prompt_user = latest_user.clone();
     let mut pushed_user_message = false;
@@ -643,14 +1396,18 @@ async fn handle_chat_request(
         .ok()
         .and_then(|value| value.parse::<usize>().ok())
         .unwrap_or(DEFAULT_TOOL_MAX_ITERATIONS);
+    let max_request_chars =
+        limits::env_limit("LSP_AGENT_MAX_REQUEST_CHARS", DEFAULT_MAX_REQUEST_CHARS);
 
     for _ in 0..max_iterations {
         let request_text = prompts::build_web_request(
             &history,
             &current_prompt_user,
-            apps_payload.as_deref(),
+            apps_payload.as_ref(),
             docs_payload.as_ref(),
             stored_values_payload.as_deref(),
+            rejected_app.take().as_deref(),
+            max_request_chars,
         );
         let tool_response_str =
             call_inference(client.as_ref(), request_text, model_hint.clone()).await;
@@ -668,7 +1425,42 @@ async fn handle_chat_request(
                 break;
             }
             "launch_app" => {
-                launched_app = tool_response.app;
+                let Some(app) = tool_response.app else {
+                    break;
+                };
+                // Broken apps go back to the model instead of opening for the
+                // user, while iterations remain.
+                let problems = app_check::check(&app);
+                if problems.is_empty() {
+                    launched_app = Some(app);
+                    break;
+                }
+                next_turn_reason = Some(format!(
+                    "Assistant's app failed validation and was not launched:\n- {}\nThe assistant must fix these problems and launch the app again.",
+                    problems.join("\n- ")
+                ));
+                rejected_app = Some(prompts::cap_app(&app, Some(MAX_REJECTED_APP_CHARS)));
+                app_problems = problems;
+            }
+            "relaunch_app" => {
+                let app_id = tool_response.app_id.unwrap_or_default();
+                let html = doc_handle.with_doc(|doc| library::relaunchable(doc, &app_id));
+                match html {
+                    Ok(html) => {
+                        launched_app = Some(html);
+                        relaunched_app_id = Some(app_id);
+                    }
+                    Err(e) => response_message = Some(format!("Cannot relaunch app: {}", e)),
+                }
+                break;
+            }
+            "save_app" => {
+                response_message = Some(save_app_from_tool(
+                    &workspace_root,
+                    &apps_info.running,
+                    tool_response.app_id.as_deref(),
+                    tool_response.name.as_deref(),
+                ));
                 break;
             }
             "list_apps" => {
@@ -679,7 +1471,7 @@ async fn handle_chat_request(
                     );
                     break;
                 }
-                apps_payload = Some(running_apps.clone());
+                apps_payload = Some(apps_info.clone());
                 next_turn_reason = Some("Assistant requested info on running apps.".to_string());
             }
             "list_docs" => {
@@ -690,7 +1482,15 @@ async fn handle_chat_request(
                     );
                     break;
                 }
-                docs_payload = Some(docs_info.clone());
+                let mut docs = docs_info.clone();
+                if let Ok(access) = workspace_access(doc_handle) {
+                    docs.workspace_files = tokio::task::spawn_blocking(move || {
+                        access.files(MAX_LISTED_WORKSPACE_FILES)
+                    })
+                    .await
+                    .unwrap_or_default();
+                }
+                docs_payload = Some(docs);
                 next_turn_reason = Some("Assistant requested info on open documents.".to_string());
             }
             "list_app_values" => {
@@ -721,50 +1521,65 @@ async fn handle_chat_request(
     }
 
     if !did_nothing && launched_app.is_none() && response_message.is_none() {
-        response_message =
-            Some("No actionable response was produced. Please retry or rephrase.".to_string());
+        response_message = Some(if app_problems.is_empty() {
+            "No actionable response was produced. Please retry or rephrase.".to_string()
+        } else {
+            format!(
+                "The app was not launched because it still failed validation:\n- {}",
+                app_problems.join("\n- ")
+            )
+        });
     }
 
-    let launched_app_for_doc = launched_app.clone();
+    let is_relaunch = relaunched_app_id.is_some();
+    let launched_app_id = relaunched_app_id.or_else(|| {
+        launched_app
+            .as_ref()
+            .map(|_| format!("app-{}", Uuid::new_v4()))
+    });
     let did_launch_app = launched_app.is_some();
     // did_request_docs and did_request_apps removed as we use history diff
 
     doc_handle.with_doc_mut(|doc| {
-        let mut agent: LspAgent = hydrate(doc).unwrap();
+        let mut tx = doc.transaction();
         if let Some(model) = model_hint.clone() {
-            agent.active_model = Some(model);
+            doc_paths::put(&mut tx, &[], "active_model", Some(model));
         }
 
         // 1. Add any history accumulated during tool use (User messages + Assistant markers)
-        let new_fragments: Vec<ConversationFragment> =
+        let mut new_fragments: Vec<ConversationFragment> =
             history.iter().skip(initial_history_len).cloned().collect();
-        agent.conversation_history.extend(new_fragments);
 
         // 2. Ensuring user message is present if not already in history (e.g. immediate answer/launch)
         if !pushed_user_message
             && !latest_user.is_empty()
             && (did_launch_app || response_message.is_some())
         {
-            agent
-                .conversation_history
-                .push(ConversationFragment::User(latest_user.clone()));
+            new_fragments.push(ConversationFragment::User(latest_user.clone()));
         }
 
         // 3. Add final response
         if let Some(message) = response_message.clone() {
-            agent
-                .conversation_history
-                .push(ConversationFragment::Assistant(message));
+            new_fragments.push(ConversationFragment::Assistant(message));
+        }
+        sessions::extend_history(&mut tx, &session_name, new_fragments);
+
+        if let Some(app_id) = &launched_app_id {
+            sessions::add_app(&mut tx, &session_name, app_id);
+        }
+        if let (Some(app_id), Some(html)) = (&launched_app_id, &launched_app) {
+            if is_relaunch {
+                library::touch(&mut tx, app_id, html);
+            } else {
+                library::record_launch(&mut tx, app_id, &latest_user, html);
+            }
         }
 
-        let mut tx = doc.transaction();
-        reconcile(&mut tx, &agent).unwrap();
         tx.commit();
     });
 
-    if let Some(app) = launched_app_for_doc {
-        let app_id = format!("app-{}", Uuid::new_v4());
-        web_sink.launch_app(app_id.clone(), app.clone()).await;
+    if let (Some(app_id), Some(app)) = (launched_app_id, launched_app) {
+        web_sink.launch_app(app_id, app).await;
     }
 
     if let Some(message) = response_message {
@@ -772,6 +1587,61 @@ async fn handle_chat_request(
     } else {
         let _ = responder.send(None);
     }
+
+    // Compaction waits on another inference; the next chat request should not.
+    // A history changed meanwhile is left alone by `history::apply_compaction`.
+    tokio::spawn(compact_history(
+        doc_handle.clone(),
+        session_name,
+        client.clone(),
+        model_hint,
+        max_request_chars,
+    ));
+}
+
+/// Folds older fragments of a session into a single summary once its history
+/// exceeds the character budget. The raw fragments stay in `archived_history`.
+async fn compact_history(
+    doc_handle: DocHandle,
+    session_name: String,
+    client: Arc<dyn InferenceClient>,
+    model: Option<String>,
+    max_request_chars: usize,
+) {
+    let budget = limits::env_limit(
+        "LSP_AGENT_HISTORY_COMPACT_CHARS",
+        history::DEFAULT_COMPACT_CHARS,
+    );
+    let to_summarize = doc_handle.with_doc(|doc| {
+        let history = sessions::history(doc, &session_name);
+        history::compaction_split(&history, budget).map(|split| history[..split].to_vec())
+    });
+    let Some(mut to_summarize) = to_summarize else {
+        return;
+    };
+
+    let (request, covered) = prompts::build_summary_request(&to_summarize, max_request_chars);
+    to_summarize.truncate(covered);
+    let summary = match client.inference(request, model).await {
+        Ok(summary) if !summary.trim().is_empty() => summary.trim().to_string(),
+        Ok(_) => return,
+        Err(e) => {
+            eprintln!("[LSP Agent] History compaction failed: {}", e);
+            return;
+        }
+    };
+
+    doc_handle.with_doc_mut(|doc| {
+        if !sessions::exists(doc, &session_name) {
+            return;
+        }
+        let mut session = sessions::session(doc, &session_name);
+        if history::apply_compaction(&mut session, &to_summarize, summary) {
+            let mut tx = doc.transaction();
+            sessions::put(&mut tx, &session_name, &session);
+            tx.commit();
+        }
+    });
 }
 
 async fn call_inference(
@@ -797,52 +1667,113 @@ fn parse_tool_response(response: &str) -> ToolResponse {
             action: "answer".to_string(),
             message: Some(response.to_string()),
             app: None,
+            app_id: None,
+            name: None,
         },
     }
 }
 
-fn collect_apps(manager: &DocumentManager) -> Vec<String> {
-    manager
-        .documents
-        .values()
-        .map(|doc| doc.text.clone())
+/// Performs the `save_app` tool action and returns the message shown to the user.
+fn save_app_from_tool(
+    workspace_root: &Result<std::path::PathBuf, String>,
+    running_apps: &[prompts::AppInfo],
+    app_id: Option<&str>,
+    name: Option<&str>,
+) -> String {
+    let (Some(app_id), Some(name)) = (app_id, name) else {
+        return "The assistant tried to save an app without giving its `app_id` and `name`."
+            .to_string();
+    };
+    let Some(app) = running_apps.iter().find(|app| app.id == app_id) else {
+        return format!(
+            "Cannot save app '{}': it is not running in this session.",
+            app_id
+        );
+    };
+    let saved = workspace_root
+        .as_ref()
+        .map_err(|e| e.clone())
+        .and_then(|root| saved_apps::save(root, name, &app.html));
+    match saved {
+        Ok(path) => format!("Saved the app to {}.", path.display()),
+        Err(e) => format!("Cannot save app '{}': {}", app_id, e),
+    }
+}
+
+/// Returns the id, HTML and reported errors of the running apps listed in `app_ids`.
+fn collect_apps<D: doc_paths::Readable>(doc: &D, app_ids: &[String]) -> Vec<prompts::AppInfo> {
+    app_ids
+        .iter()
+        .filter_map(|id| {
+            doc_paths::get::<_, DocumentContent>(doc, WEBVIEWS, id).map(|content| {
+                prompts::AppInfo {
+                    id: id.clone(),
+                    html: content.text,
+                    errors: doc_paths::get_list(doc, &["app_errors"], id),
+                }
+            })
+        })
         .collect()
 }
 
-fn collect_docs(manager: &DocumentManager) -> prompts::DocsInfo {
-    let mut open_documents: Vec<String> = manager.documents.keys().cloned().collect();
-    open_documents.sort();
-    let active_document = manager
-        .active_document
-        .as_ref()
-        .map(|uri| uri.value.clone());
+/// Describes the open documents from their metadata, without reading their text.
+fn collect_docs<D: doc_paths::Readable>(doc: &D) -> prompts::DocsInfo {
+    let mut open_documents: Vec<prompts::DocumentInfo> = doc_paths::keys(doc, TEXT_DOCUMENTS)
+        .into_iter()
+        .map(|uri| {
+            let path = &["text_documents", "documents", uri.as_str()];
+            let metadata = prompts::DocumentMetadata {
+                language_id: doc_paths::get(doc, path, "language_id").unwrap_or_default(),
+                version: doc_paths::get(doc, path, "version").unwrap_or_default(),
+                line_count: doc_paths::get(doc, path, "line_count").unwrap_or_default(),
+                bytes: doc_paths::get(doc, path, "byte_size").unwrap_or_default(),
+                dirty: doc_paths::get(doc, path, "dirty").unwrap_or_default(),
+                untitled: doc_paths::get(doc, path, "untitled").unwrap_or_default(),
+            };
+            prompts::DocumentInfo {
+                uri,
+                metadata: Some(metadata),
+            }
+        })
+        .collect();
+    open_documents.sort_by(|a, b| a.uri.cmp(&b.uri));
+    let active_document =
+        doc_paths::get::<_, Option<Uri>>(doc, &["text_documents"], "active_document")
+            .flatten()
+            .map(|uri| uri.value);
     if let Some(active) = &active_document
-        && !open_documents.contains(active)
+        && !open_documents.iter().any(|doc| &doc.uri == active)
     {
-        open_documents.push(active.clone());
+        open_documents.push(prompts::DocumentInfo {
+            uri: active.clone(),
+            metadata: None,
+        });
     }
     prompts::DocsInfo {
         open_documents,
         active_document,
+        workspace_files: Vec::new(),
     }
 }
 
-fn collect_stored_values(
-    values: &std::collections::HashMap<String, StoredValue>,
-) -> Vec<prompts::StoredValueInfo> {
-    values
-        .iter()
-        .map(|(k, v)| prompts::StoredValueInfo {
-            key: k.clone(),
-            description: v.description.clone(),
+/// Lists the stored values' keys and descriptions, without reading the values.
+fn collect_stored_values<D: doc_paths::Readable>(doc: &D) -> Vec<prompts::StoredValueInfo> {
+    doc_paths::keys(doc, &["stored_values"])
+        .into_iter()
+        .map(|key| prompts::StoredValueInfo {
+            description: doc_paths::get(doc, &["stored_values", &key], "description")
+                .unwrap_or_default(),
+            key,
         })
         .collect()
 }
 
 #[cfg(test)]
+#[allow(dead_code, clippy::manual_unwrap_or_default)]
 mod tests {
     use super::*;
     use crate::document::{DocumentManager, StoredValue, Uri};
+    use autosurgeon::hydrate;
 
     #[test]
     fn test_find_repo_root_with_workspace() {
@@ -874,6 +1805,12 @@ mod tests {
         assert_eq!(result, None);
     }
 
+    fn doc_of(agent: &LspAgent) -> automerge::AutoCommit {
+        let mut doc = automerge::AutoCommit::new();
+        reconcile(&mut doc, agent).unwrap();
+        doc
+    }
+
     #[test]
     fn test_collect_apps() {
         let mut manager = DocumentManager::default();
@@ -890,71 +1827,170 @@ mod tests {
             },
         );
 
-        let apps = collect_apps(&manager);
+        let errors = HashMap::from([(
+            "app2".to_string(),
+            vec!["TypeError: x is undefined at line 42:7".to_string()],
+        )]);
+
+        let doc = doc_of(&LspAgent {
+            webviews: manager,
+            app_errors: errors,
+            ..Default::default()
+        });
+
+        let apps = collect_apps(&doc, &["app1".to_string(), "app2".to_string()]);
+        let html: Vec<&str> = apps.iter().map(|app| app.html.as_str()).collect();
         assert_eq!(apps.len(), 2);
-        assert!(apps.contains(&"html1".to_string()));
-        assert!(apps.contains(&"html2".to_string()));
+        assert!(html.contains(&"html1"));
+        assert!(html.contains(&"html2"));
+
+        // Only apps of the requesting session are listed.
+        let apps = collect_apps(&doc, &["app2".to_string(), "closed".to_string()]);
+        assert_eq!(
+            apps,
+            vec![prompts::AppInfo {
+                id: "app2".to_string(),
+                html: "html2".to_string(),
+                errors: vec!["TypeError: x is undefined at line 42:7".to_string()],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_save_app_from_tool() {
+        let root = tempfile::tempdir().unwrap();
+        let apps = vec![prompts::AppInfo {
+            id: "app1".to_string(),
+            html: "<html>saved</html>".to_string(),
+            errors: Vec::new(),
+        }];
+        let workspace_root = Ok(root.path().to_path_buf());
+
+        let message = save_app_from_tool(&workspace_root, &apps, Some("app1"), Some("notes"));
+        assert!(message.starts_with("Saved the app to"));
+        assert_eq!(
+            saved_apps::load(root.path(), "notes").unwrap(),
+            "<html>saved</html>"
+        );
+
+        let message = save_app_from_tool(&workspace_root, &apps, Some("other"), Some("notes"));
+        assert!(message.contains("not running in this session"));
+        let message = save_app_from_tool(&workspace_root, &apps, Some("app1"), None);
+        assert!(message.contains("without giving"));
+        let message = save_app_from_tool(
+            &Err("No workspace folder is open to save apps into.".to_string()),
+            &apps,
+            Some("app1"),
+            Some("notes"),
+        );
+        assert!(message.contains("No workspace folder"));
     }
 
     #[test]
     fn test_collect_docs_basic() {
-        let mut manager = DocumentManager::default();
+        let mut manager = TextDocumentManager::default();
         manager.documents.insert(
             "file1.rs".to_string(),
-            DocumentContent {
-                text: "code1".to_string(),
+            TextDocument {
+                text: "code1".into(),
+                ..Default::default()
             },
         );
         manager.documents.insert(
             "file2.rs".to_string(),
-            DocumentContent {
-                text: "code2".to_string(),
+            TextDocument {
+                text: "code2".into(),
+                ..Default::default()
             },
         );
 
-        let docs = collect_docs(&manager);
-        assert_eq!(docs.open_documents.len(), 2);
-        assert!(docs.open_documents.contains(&"file1.rs".to_string()));
-        assert!(docs.open_documents.contains(&"file2.rs".to_string()));
+        let docs = collect_docs(&doc_of(&LspAgent {
+            text_documents: manager,
+            ..Default::default()
+        }));
+        let uris: Vec<&str> = docs.open_documents.iter().map(|d| d.uri.as_str()).collect();
+        assert_eq!(uris.len(), 2);
+        assert!(uris.contains(&"file1.rs"));
+        assert!(uris.contains(&"file2.rs"));
         assert_eq!(docs.active_document, None);
     }
 
     #[test]
     fn test_collect_docs_with_active() {
-        let mut manager = DocumentManager::default();
+        let mut manager = TextDocumentManager::default();
         manager.documents.insert(
             "file1.rs".to_string(),
-            DocumentContent {
-                text: "code1".to_string(),
+            TextDocument {
+                text: "code1".into(),
+                ..Default::default()
             },
         );
         manager.active_document = Some(Uri {
             value: "file1.rs".to_string(),
         });
 
-        let docs = collect_docs(&manager);
+        let docs = collect_docs(&doc_of(&LspAgent {
+            text_documents: manager,
+            ..Default::default()
+        }));
         assert_eq!(docs.open_documents.len(), 1);
         assert_eq!(docs.active_document, Some("file1.rs".to_string()));
     }
 
     #[test]
     fn test_collect_docs_active_not_in_open() {
-        let mut manager = DocumentManager::default();
+        let mut manager = TextDocumentManager::default();
         manager.documents.insert(
             "file1.rs".to_string(),
-            DocumentContent {
-                text: "code1".to_string(),
+            TextDocument {
+                text: "code1".into(),
+                ..Default::default()
             },
         );
         manager.active_document = Some(Uri {
             value: "file2.rs".to_string(),
         });
 
-        let docs = collect_docs(&manager);
-        assert_eq!(docs.open_documents.len(), 2);
-        assert!(docs.open_documents.contains(&"file1.rs".to_string()));
-        assert!(docs.open_documents.contains(&"file2.rs".to_string()));
+        let docs = collect_docs(&doc_of(&LspAgent {
+            text_documents: manager,
+            ..Default::default()
+        }));
+        let uris: Vec<&str> = docs.open_documents.iter().map(|d| d.uri.as_str()).collect();
+        assert_eq!(uris.len(), 2);
+        assert!(uris.contains(&"file1.rs"));
+        assert!(uris.contains(&"file2.rs"));
         assert_eq!(docs.active_document, Some("file2.rs".to_string()));
+        assert_eq!(docs.open_documents[1].metadata, None);
+    }
+
+    #[test]
+    fn test_collect_docs_metadata() {
+        let mut manager = TextDocumentManager::default();
+        manager.documents.insert(
+            "untitled:Untitled-1".to_string(),
+            text_sync::opened(
+                "untitled:Untitled-1",
+                "markdown".to_string(),
+                3,
+                "# title\n\nbody".to_string(),
+            ),
+        );
+
+        let docs = collect_docs(&doc_of(&LspAgent {
+            text_documents: manager,
+            ..Default::default()
+        }));
+        assert_eq!(
+            docs.open_documents[0].metadata,
+            Some(prompts::DocumentMetadata {
+                language_id: "markdown".to_string(),
+                version: 3,
+                line_count: 3,
+                bytes: 13,
+                dirty: false,
+                untitled: true,
+            })
+        );
     }
 
     #[test]
@@ -975,7 +2011,10 @@ mod tests {
             },
         );
 
-        let infos = collect_stored_values(&values);
+        let infos = collect_stored_values(&doc_of(&LspAgent {
+            stored_values: values,
+            ..Default::default()
+        }));
         assert_eq!(infos.len(), 2);
 
         let info1 = infos.iter().find(|i| i.key == "key1").unwrap();
@@ -1029,6 +2068,10 @@ mod tests {
             impl InferenceClient for TestClient {
                 async fn inference(&self, request: String, model: Option<String>) -> Result<String, String>;
                 async fn notify_shutdown(&self);
+                async fn show_message(&self, message: String);
+                async fn apply_edit(&self, proposal: EditProposal) -> Result<bool, String>;
+                async fn publish_diagnostics(&self, uri: String, diagnostics: Vec<traits::Diagnostic>);
+                async fn refresh_code_lenses(&self);
             }
         }
 
@@ -1053,6 +2096,10 @@ mod tests {
             impl InferenceClient for TestClient {
                 async fn inference(&self, request: String, model: Option<String>) -> Result<String, String>;
                 async fn notify_shutdown(&self);
+                async fn show_message(&self, message: String);
+                async fn apply_edit(&self, proposal: EditProposal) -> Result<bool, String>;
+                async fn publish_diagnostics(&self, uri: String, diagnostics: Vec<traits::Diagnostic>);
+                async fn refresh_code_lenses(&self);
             }
         }
 
@@ -1065,162 +2112,930 @@ mod tests {
         assert_eq!(result, "Error: inference error");
     }
 
-    // Web response handling tests
     #[tokio::test]
-    async fn test_handle_web_doc_change_launch_app() {
-        struct RecordingWeb {
-            launched: tokio::sync::Mutex<Vec<(String, String)>>,
-            inference: tokio::sync::Mutex<Vec<(String, String)>>,
-        }
-
-        #[async_trait::async_trait]
-        impl Web for RecordingWeb {
-            async fn launch_app(&self, id: String, content: String) {
-                let mut l = self.launched.lock().await;
-                l.push((id, content));
-            }
-
-            async fn handle_inference_response(&self, app_id: String, content: String) {
-                let mut v = self.inference.lock().await;
-                v.push((app_id, content));
-            }
-        }
-
+    async fn test_web_agent_calls_are_audited() {
         let repo = Repo::new(None, Box::new(NoStorage));
         let repo_handle = repo.run();
         let doc_handle = repo_handle.new_document();
 
-        // Insert a WebApp response
         doc_handle.with_doc_mut(|doc| {
-            let mut agent: LspAgent = match hydrate(doc) {
-                Ok(a) => a,
-                Err(_) => LspAgent::default(),
-            };
-            agent.responses.push(AgentResponse::WebApp {
-                id: "appA".to_string(),
-                content: "<html/>".to_string(),
-            });
+            let mut agent = LspAgent::default();
+            agent.text_documents.documents.insert(
+                "file:///a.rs".to_string(),
+                TextDocument {
+                    text: "fn main() {}".into(),
+                    ..Default::default()
+                },
+            );
             let mut tx = doc.transaction();
             reconcile(&mut tx, &agent).unwrap();
             tx.commit();
         });
 
-        let web = RecordingWeb {
-            launched: tokio::sync::Mutex::new(vec![]),
-            inference: tokio::sync::Mutex::new(vec![]),
-        };
-        let rc = &web;
-
-        // Should handle and launch app
-        let handled = handle_web_doc_change(&doc_handle, rc).await;
-        assert!(!handled, "should not signal exit");
-
-        // verify launched
-        let launched = web.launched.lock().await;
-        assert_eq!(launched.len(), 1);
-        assert_eq!(launched[0].0, "appA");
-        assert_eq!(launched[0].1, "<html/>".to_string());
+        let web_agent = DocWebAgent::new(doc_handle.clone());
+        let text = web_agent
+            .read_document("app1".to_string(), "file:///a.rs".to_string())
+            .await;
+        web_agent
+            .store_value(
+                "app1".to_string(),
+                "k".to_string(),
+                "value".to_string(),
+                "desc".to_string(),
+            )
+            .await;
+        web_agent
+            .app_inference_request("req1".to_string(), "prompt".to_string(), "app2".to_string())
+            .await;
 
-        // ensure response removed
         doc_handle.with_doc(|doc| {
-            let agent: LspAgent = match hydrate(doc) {
-                Ok(a) => a,
-                Err(_) => LspAgent::default(),
-            };
-            assert!(agent.responses.is_empty());
+            let agent: LspAgent = hydrate(doc).unwrap();
+            let log = &agent.audit_log;
+            assert_eq!(log.len(), 3);
+            assert_eq!(log[0].action, "read_document");
+            assert_eq!(log[0].target, "file:///a.rs");
+            assert_eq!(log[0].response_bytes, text.len() as u64);
+            assert_eq!(log[1].action, "store_value");
+            assert_eq!(log[1].request_bytes, 5);
+            assert_eq!(log[2].app_id, "app2");
+            assert_eq!(log[2].request_bytes, 6);
         });
     }
 
     #[tokio::test]
-    async fn test_handle_web_doc_change_inference() {
-        struct RecordingWeb {
-            launched: tokio::sync::Mutex<Vec<(String, String)>>,
-            inference: tokio::sync::Mutex<Vec<(String, String)>>,
-        }
-
-        #[async_trait::async_trait]
-        impl Web for RecordingWeb {
-            async fn launch_app(&self, _id: String, _content: String) {
-                // no-op for this test
-            }
-
-            async fn handle_inference_response(&self, app_id: String, content: String) {
-                let mut v = self.inference.lock().await;
-                v.push((app_id, content));
-            }
-        }
-
+    async fn test_stored_value_keys_are_tracked_per_app() {
         let repo = Repo::new(None, Box::new(NoStorage));
         let repo_handle = repo.run();
         let doc_handle = repo_handle.new_document();
-
         doc_handle.with_doc_mut(|doc| {
-            let mut agent: LspAgent = match hydrate(doc) {
-                Ok(a) => a,
-                Err(_) => LspAgent::default(),
-            };
-            agent.responses.push(AgentResponse::Inference {
-                app_id: "a1".to_string(),
-                content: "ok".to_string(),
-            });
             let mut tx = doc.transaction();
-            reconcile(&mut tx, &agent).unwrap();
+            reconcile(&mut tx, LspAgent::default()).unwrap();
             tx.commit();
         });
 
-        let web = RecordingWeb {
-            launched: tokio::sync::Mutex::new(vec![]),
-            inference: tokio::sync::Mutex::new(vec![]),
-        };
-        let rc = &web;
-
-        let handled = handle_web_doc_change(&doc_handle, rc).await;
-        assert!(!handled);
-
-        let inf = web.inference.lock().await;
-        assert_eq!(inf.len(), 1);
-        assert_eq!(inf[0].0, "a1");
-        assert_eq!(inf[0].1, "ok".to_string());
+        let web_agent = DocWebAgent::new(doc_handle.clone());
+        web_agent
+            .store_value(
+                "app1".to_string(),
+                "k".to_string(),
+                "v".to_string(),
+                String::new(),
+            )
+            .await;
+        web_agent
+            .read_value("app1".to_string(), "k".to_string())
+            .await;
+        web_agent
+            .read_value("app2".to_string(), "other".to_string())
+            .await;
 
         doc_handle.with_doc(|doc| {
-            let agent: LspAgent = match hydrate(doc) {
-                Ok(a) => a,
-                Err(_) => LspAgent::default(),
-            };
-            assert!(agent.responses.is_empty());
+            let agent: LspAgent = hydrate(doc).unwrap();
+            assert_eq!(agent.app_values["app1"], vec!["k".to_string()]);
+            assert_eq!(agent.app_values["app2"], vec!["other".to_string()]);
         });
     }
 
     #[tokio::test]
-    async fn test_handle_web_doc_change_chat_ignored() {
-        struct RecordingWeb {
-            launched: tokio::sync::Mutex<Vec<(String, String)>>,
-            inference: tokio::sync::Mutex<Vec<(String, String)>>,
-        }
-
-        #[async_trait::async_trait]
-        impl Web for RecordingWeb {
-            async fn launch_app(&self, _id: String, _content: String) {}
-            async fn handle_inference_response(&self, _app_id: String, _content: String) {}
-        }
-
+    async fn test_read_selection() {
         let repo = Repo::new(None, Box::new(NoStorage));
         let repo_handle = repo.run();
         let doc_handle = repo_handle.new_document();
-
         doc_handle.with_doc_mut(|doc| {
-            let mut agent: LspAgent = match hydrate(doc) {
-                Ok(a) => a,
-                Err(_) => LspAgent::default(),
-            };
-            agent.responses.push(AgentResponse::Chat("hey".to_string()));
+            let mut agent = LspAgent::default();
+            agent.text_documents.documents.insert(
+                "file:///a.rs".to_string(),
+                TextDocument {
+                    text: "let x = 1;".into(),
+                    ..Default::default()
+                },
+            );
+            agent.text_documents.active_document = Some(Uri {
+                value: "file:///a.rs".to_string(),
+            });
+            let at = |character| EditPosition { line: 0, character };
+            for uri in ["file:///a.rs", "file:///closed.rs"] {
+                agent.text_documents.selections.insert(
+                    uri.to_string(),
+                    vec![Selection {
+                        anchor: at(4),
+                        active: at(5),
+                    }],
+                );
+            }
             let mut tx = doc.transaction();
             reconcile(&mut tx, &agent).unwrap();
             tx.commit();
         });
 
-        let web = RecordingWeb {
-            launched: tokio::sync::Mutex::new(vec![]),
+        let web_agent = DocWebAgent::new(doc_handle.clone());
+        let json = web_agent
+            .read_selection("app1".to_string(), String::new())
+            .await
+            .unwrap();
+        let view: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(view["uri"], "file:///a.rs");
+        assert_eq!(view["cursor"]["character"], 5);
+        assert_eq!(view["selections"][0]["text"], "x");
+
+        assert_eq!(
+            web_agent
+                .read_selection("app1".to_string(), "file:///closed.rs".to_string())
+                .await,
+            None
+        );
+        doc_handle.with_doc(|doc| {
+            let log: Vec<AuditEntry> = doc_paths::get_list(doc, &[], "audit_log");
+            assert_eq!(log.len(), 1);
+            assert_eq!(log[0].action, "read_selection");
+        });
+    }
+
+    #[tokio::test]
+    async fn test_selection_code_actions() {
+        let repo = Repo::new(None, Box::new(NoStorage));
+        let repo_handle = repo.run();
+        let doc_handle = repo_handle.new_document();
+        let root = tempfile::tempdir().unwrap();
+        saved_apps::save(root.path(), "explain", "<html>explain</html>").unwrap();
+        doc_handle.with_doc_mut(|doc| {
+            let mut agent = LspAgent {
+                workspace_folders: vec![root.path().display().to_string()],
+                ..Default::default()
+            };
+            agent.text_documents.documents.insert(
+                "file:///a.rs".to_string(),
+                TextDocument {
+                    text: "fn a() {}\nlet x = 1;".into(),
+                    language_id: "rust".to_string(),
+                    ..Default::default()
+                },
+            );
+            let mut tx = doc.transaction();
+            reconcile(&mut tx, &agent).unwrap();
+            tx.commit();
+        });
+
+        let (chat_tx, mut chat_rx) = mpsc::channel(1);
+        let agent = AutomergeAgent {
+            repo_handle: repo_handle.clone(),
+            doc_handle: doc_handle.clone(),
+            agent_task: Mutex::new(None),
+            web_child: Mutex::new(None),
+            chat_tx,
+        };
+        let at = |line, character| traits::TextPosition { line, character };
+        let selection = SelectedRange {
+            uri: "file:///a.rs".to_string(),
+            range: traits::TextRange {
+                start: at(1, 4),
+                end: at(1, 5),
+            },
+        };
+
+        let chat = tokio::spawn(async move {
+            let request = chat_rx.recv().await.unwrap();
+            let _ = request.responder.send(Some("done".to_string()));
+            request.content
+        });
+        let reply = agent.ask_about_selection(selection.clone()).await;
+        assert_eq!(reply.as_deref(), Some("done"));
+        let question = chat.await.unwrap();
+        assert!(question.contains("file:///a.rs (line 2)"));
+        assert!(question.contains("```rust\nx\n```"));
+
+        let web_agent = DocWebAgent::new(doc_handle.clone());
+        let json = web_agent
+            .read_selection("app1".to_string(), String::new())
+            .await
+            .unwrap();
+        let view: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(view["selections"][0]["text"], "x");
+
+        let app_id = agent
+            .launch_saved_app("explain".to_string(), Some(selection))
+            .await
+            .unwrap();
+        let plain_id = agent
+            .launch_saved_app("explain".to_string(), None)
+            .await
+            .unwrap();
+        let json = web_agent.read_launch_context(app_id.clone()).await.unwrap();
+        let view: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(view["uri"], "file:///a.rs");
+        assert_eq!(view["selections"][0]["text"], "x");
+        assert_eq!(web_agent.read_launch_context(plain_id).await, None);
+
+        web_agent.close_app(app_id.clone()).await;
+        assert_eq!(web_agent.read_launch_context(app_id).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_app_contributions_are_served_and_cleared_on_close() {
+        use async_trait::async_trait;
+        use mockall::mock;
+
+        mock! {
+            pub TestClient {}
+            #[async_trait]
+            impl InferenceClient for TestClient {
+                async fn inference(&self, request: String, model: Option<String>) -> Result<String, String>;
+                async fn notify_shutdown(&self);
+                async fn show_message(&self, message: String);
+                async fn apply_edit(&self, proposal: EditProposal) -> Result<bool, String>;
+                async fn publish_diagnostics(&self, uri: String, diagnostics: Vec<traits::Diagnostic>);
+                async fn refresh_code_lenses(&self);
+            }
+        }
+
+        let repo = Repo::new(None, Box::new(NoStorage));
+        let repo_handle = repo.run();
+        let doc_handle = repo_handle.new_document();
+        doc_handle.with_doc_mut(|doc| {
+            let mut agent = LspAgent::default();
+            agent.text_documents.documents.insert(
+                "file:///a.md".to_string(),
+                TextDocument {
+                    text: "An API call.".into(),
+                    ..Default::default()
+                },
+            );
+            agent.webviews.documents.insert(
+                "app1".to_string(),
+                DocumentContent {
+                    text: "<title>Glossary</title>".to_string(),
+                },
+            );
+            let mut tx = doc.transaction();
+            reconcile(&mut tx, &agent).unwrap();
+            tx.commit();
+        });
+
+        let body = |uri: &str| {
+            format!(
+                r#"{{"uri": "{}",
+                "hovers": [{{"range": {{"start": {{"line": 0, "character": 3}}, "end": {{"line": 0, "character": 6}}}}, "contents": "Application programming interface"}}],
+                "code_lenses": [{{"range": {{"start": {{"line": 0, "character": 0}}, "end": {{"line": 0, "character": 0}}}}, "title": "1 term"}}]}}"#,
+                uri
+            )
+        };
+        let web_agent = DocWebAgent::new(doc_handle.clone());
+        assert!(
+            web_agent
+                .publish_contributions("app1".to_string(), body("file:///closed.md"))
+                .await
+                .is_err()
+        );
+        web_agent
+            .publish_contributions("app1".to_string(), body("file:///a.md"))
+            .await
+            .unwrap();
+
+        let (chat_tx, _chat_rx) = mpsc::channel(1);
+        let agent = AutomergeAgent {
+            repo_handle: repo_handle.clone(),
+            doc_handle: doc_handle.clone(),
+            agent_task: Mutex::new(None),
+            web_child: Mutex::new(None),
+            chat_tx,
+        };
+        let at = |character| traits::TextPosition { line: 0, character };
+        assert_eq!(
+            agent
+                .hover("file:///a.md".to_string(), at(4))
+                .await
+                .as_deref(),
+            Some("Application programming interface")
+        );
+        assert_eq!(agent.hover("file:///a.md".to_string(), at(8)).await, None);
+        assert_eq!(
+            agent.code_lenses("file:///a.md".to_string()).await[0].title,
+            "1 term"
+        );
+
+        let mut mock_client = MockTestClient::new();
+        mock_client
+            .expect_refresh_code_lenses()
+            .times(2)
+            .returning(|| ());
+        let mut shown = contributions::Lenses::new();
+        sync_code_lenses(&doc_handle, &mock_client, &mut shown).await;
+        sync_code_lenses(&doc_handle, &mock_client, &mut shown).await;
+        web_agent.close_app("app1".to_string()).await;
+        sync_code_lenses(&doc_handle, &mock_client, &mut shown).await;
+
+        assert_eq!(agent.hover("file:///a.md".to_string(), at(4)).await, None);
+        assert!(
+            agent
+                .code_lenses("file:///a.md".to_string())
+                .await
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_app_diagnostics_are_published_and_cleared_on_close() {
+        use async_trait::async_trait;
+        use mockall::mock;
+
+        mock! {
+            pub TestClient {}
+            #[async_trait]
+            impl InferenceClient for TestClient {
+                async fn inference(&self, request: String, model: Option<String>) -> Result<String, String>;
+                async fn notify_shutdown(&self);
+                async fn show_message(&self, message: String);
+                async fn apply_edit(&self, proposal: EditProposal) -> Result<bool, String>;
+                async fn publish_diagnostics(&self, uri: String, diagnostics: Vec<traits::Diagnostic>);
+                async fn refresh_code_lenses(&self);
+            }
+        }
+
+        let repo = Repo::new(None, Box::new(NoStorage));
+        let repo_handle = repo.run();
+        let doc_handle = repo_handle.new_document();
+        doc_handle.with_doc_mut(|doc| {
+            let mut agent = LspAgent::default();
+            agent.text_documents.documents.insert(
+                "file:///a.rs".to_string(),
+                TextDocument {
+                    text: "let x = 1;".into(),
+                    ..Default::default()
+                },
+            );
+            agent.webviews.documents.insert(
+                "app1".to_string(),
+                DocumentContent {
+                    text: "<title>Linter</title>".to_string(),
+                },
+            );
+            let mut tx = doc.transaction();
+            reconcile(&mut tx, &agent).unwrap();
+            tx.commit();
+        });
+
+        let body = |uri: &str| {
+            format!(
+                r#"{{"uri": "{}", "diagnostics": [{{"range": {{"start": {{"line": 0, "character": 4}}, "end": {{"line": 0, "character": 5}}}}, "severity": "hint", "message": "unused"}}]}}"#,
+                uri
+            )
+        };
+        let web_agent = DocWebAgent::new(doc_handle.clone());
+        assert!(
+            web_agent
+                .publish_diagnostics("app1".to_string(), body("file:///closed.rs"))
+                .await
+                .is_err()
+        );
+        web_agent
+            .publish_diagnostics("app1".to_string(), body("file:///a.rs"))
+            .await
+            .unwrap();
+
+        type Calls = Vec<(String, Vec<traits::Diagnostic>)>;
+        let calls: Arc<std::sync::Mutex<Calls>> = Arc::default();
+        let mut mock_client = MockTestClient::new();
+        let recorded = calls.clone();
+        mock_client
+            .expect_publish_diagnostics()
+            .returning(move |uri, diagnostics| recorded.lock().unwrap().push((uri, diagnostics)));
+
+        let mut published = diagnostics::Published::new();
+        sync_diagnostics(&doc_handle, &mock_client, &mut published).await;
+        sync_diagnostics(&doc_handle, &mock_client, &mut published).await;
+        web_agent.close_app("app1".to_string()).await;
+        sync_diagnostics(&doc_handle, &mock_client, &mut published).await;
+
+        let calls = calls.lock().unwrap();
+        assert_eq!(calls.len(), 2);
+        assert_eq!(calls[0].0, "file:///a.rs");
+        assert_eq!(calls[0].1[0].message, "unused");
+        assert_eq!(calls[0].1[0].source, "Linter");
+        assert_eq!(calls[1], ("file:///a.rs".to_string(), vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_app_errors_are_recorded_and_cleared_on_close() {
+        let repo = Repo::new(None, Box::new(NoStorage));
+        let repo_handle = repo.run();
+        let doc_handle = repo_handle.new_document();
+        doc_handle.with_doc_mut(|doc| {
+            let mut agent = LspAgent::default();
+            agent.webviews.documents.insert(
+                "app1".to_string(),
+                DocumentContent {
+                    text: "<title>Todo</title>".to_string(),
+                },
+            );
+            let mut tx = doc.transaction();
+            reconcile(&mut tx, &agent).unwrap();
+            tx.commit();
+        });
+
+        let web_agent = DocWebAgent::new(doc_handle.clone());
+        let error = "TypeError: items is undefined at line 42:7";
+        web_agent
+            .log_error("app1".to_string(), error.to_string())
+            .await;
+        web_agent
+            .log_error("closed".to_string(), error.to_string())
+            .await;
+        let errors = |doc_handle: &DocHandle| {
+            doc_handle.with_doc(|doc| hydrate::<_, LspAgent>(doc).unwrap().app_errors)
+        };
+        assert_eq!(
+            errors(&doc_handle),
+            HashMap::from([("app1".to_string(), vec![error.to_string()])])
+        );
+
+        web_agent.close_app("app1".to_string()).await;
+        assert!(errors(&doc_handle).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_edit_proposal_round_trip() {
+        use async_trait::async_trait;
+        use mockall::mock;
+
+        mock! {
+            pub TestClient {}
+            #[async_trait]
+            impl InferenceClient for TestClient {
+                async fn inference(&self, request: String, model: Option<String>) -> Result<String, String>;
+                async fn notify_shutdown(&self);
+                async fn show_message(&self, message: String);
+                async fn apply_edit(&self, proposal: EditProposal) -> Result<bool, String>;
+                async fn publish_diagnostics(&self, uri: String, diagnostics: Vec<traits::Diagnostic>);
+                async fn refresh_code_lenses(&self);
+            }
+        }
+
+        let repo = Repo::new(None, Box::new(NoStorage));
+        let repo_handle = repo.run();
+        let doc_handle = repo_handle.new_document();
+        doc_handle.with_doc_mut(|doc| {
+            let mut agent = LspAgent::default();
+            agent.text_documents.documents.insert(
+                "file:///a.md".to_string(),
+                TextDocument {
+                    text: "one\ntwo".into(),
+                    version: 3,
+                    ..Default::default()
+                },
+            );
+            agent.webviews.documents.insert(
+                "app1".to_string(),
+                DocumentContent {
+                    text: "<title>Summarizer</title>".to_string(),
+                },
+            );
+            let mut tx = doc.transaction();
+            reconcile(&mut tx, &agent).unwrap();
+            tx.commit();
+        });
+
+        let web_agent = DocWebAgent::new(doc_handle.clone());
+        assert!(
+            web_agent
+                .propose_edit("req1".to_string(), "app1".to_string(), "{}".to_string())
+                .await
+                .is_err()
+        );
+        let request_id = "req2".to_string();
+        web_agent
+            .propose_edit(
+                request_id.clone(),
+                "app1".to_string(),
+                r#"{"uri": "file:///a.md", "edits": [{"text": "summary"}]}"#.to_string(),
+            )
+            .await
+            .unwrap();
+
+        let (_, requests, _) = check_agent_state(&doc_handle);
+        let Some(AgentRequest::ProposeEdit {
+            app_id, uri, edits, ..
+        }) = requests.into_iter().next()
+        else {
+            panic!("expected a queued edit proposal");
+        };
+        let proposal = edit_proposal(&doc_handle, &app_id, uri, edits).unwrap();
+        assert_eq!(proposal.label, "Edit proposed by Summarizer");
+        assert_eq!(proposal.version, Some(3));
+        assert_eq!(
+            proposal.edits[0].range.end,
+            traits::TextPosition {
+                line: 1,
+                character: 3
+            }
+        );
+
+        let mut mock_client = MockTestClient::new();
+        mock_client
+            .expect_apply_edit()
+            .times(1)
+            .returning(|_| Ok(false));
+        let client: Arc<dyn InferenceClient> = Arc::new(mock_client);
+        let web_sink = DocWebSink {
+            doc_handle: doc_handle.clone(),
+        };
+        handle_edit_proposal(request_id.clone(), Ok(proposal), &client, &web_sink).await;
+
+        let responses: Vec<AgentResponse> =
+            doc_handle.with_doc(|doc| doc_paths::get_list(doc, &[], "responses"));
+        assert_eq!(
+            responses,
+            vec![AgentResponse::Reply {
+                request_id,
+                status: 409,
+                content: "The user declined the edit.".to_string(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_throttled_inference_request_is_rejected() {
+        use async_trait::async_trait;
+        use mockall::mock;
+        use traits::InferenceClient;
+
+        mock! {
+            pub TestClient {}
+            #[async_trait]
+            impl InferenceClient for TestClient {
+                async fn inference(&self, request: String, model: Option<String>) -> Result<String, String>;
+                async fn notify_shutdown(&self);
+                async fn show_message(&self, message: String);
+                async fn apply_edit(&self, proposal: EditProposal) -> Result<bool, String>;
+                async fn publish_diagnostics(&self, uri: String, diagnostics: Vec<traits::Diagnostic>);
+                async fn refresh_code_lenses(&self);
+            }
+        }
+
+        struct RecordingWeb {
+            replies: tokio::sync::Mutex<Vec<(String, u16)>>,
+        }
+
+        #[async_trait::async_trait]
+        impl Web for RecordingWeb {
+            async fn launch_app(&self, _id: String, _content: String) {}
+            async fn handle_reply(&self, request_id: String, status: u16, _content: String) {
+                self.replies.lock().await.push((request_id, status));
+            }
+        }
+
+        let mut mock_client = MockTestClient::new();
+        mock_client
+            .expect_inference()
+            .times(1)
+            .returning(|_, _| Ok("ok".to_string()));
+        mock_client.expect_show_message().times(1).returning(|_| ());
+        let client: Arc<dyn InferenceClient> = Arc::new(mock_client);
+
+        let limiter = RateLimiter::new(limits::InferenceLimits {
+            per_app: limits::Limits {
+                requests_per_minute: 1,
+                max_concurrent: 0,
+                prompt_char_quota: 0,
+            },
+            global: limits::Limits {
+                requests_per_minute: 0,
+                max_concurrent: 0,
+                prompt_char_quota: 0,
+            },
+            max_prompt_chars: 0,
+        });
+        let web = RecordingWeb {
+            replies: tokio::sync::Mutex::new(vec![]),
+        };
+
+        for request_id in 0..3 {
+            handle_inference_request(
+                request_id.to_string(),
+                "hi".to_string(),
+                "looping".to_string(),
+                &client,
+                None,
+                &web,
+                &limiter,
+            )
+            .await;
+        }
+
+        let replies = web.replies.lock().await;
+        assert_eq!(
+            *replies,
+            vec![
+                ("0".to_string(), 200),
+                ("1".to_string(), 429),
+                ("2".to_string(), 429)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_inference_requests_complete_out_of_order() {
+        /// Answers with the prompt, after a delay named by the prompt.
+        struct DelayedClient;
+
+        #[async_trait::async_trait]
+        impl InferenceClient for DelayedClient {
+            async fn inference(
+                &self,
+                request: String,
+                _model: Option<String>,
+            ) -> Result<String, String> {
+                let delay = if request == "slow" { 100 } else { 10 };
+                sleep(Duration::from_millis(delay)).await;
+                Ok(request)
+            }
+            async fn notify_shutdown(&self) {}
+            async fn show_message(&self, _message: String) {}
+            async fn apply_edit(&self, _proposal: EditProposal) -> Result<bool, String> {
+                Ok(false)
+            }
+            async fn publish_diagnostics(
+                &self,
+                _uri: String,
+                _diagnostics: Vec<traits::Diagnostic>,
+            ) {
+            }
+            async fn refresh_code_lenses(&self) {}
+        }
+
+        struct RecordingWeb {
+            replies: tokio::sync::Mutex<Vec<(String, u16, String)>>,
+        }
+
+        #[async_trait::async_trait]
+        impl Web for RecordingWeb {
+            async fn launch_app(&self, _id: String, _content: String) {}
+            async fn handle_reply(&self, request_id: String, status: u16, content: String) {
+                self.replies
+                    .lock()
+                    .await
+                    .push((request_id, status, content));
+            }
+        }
+
+        let client: Arc<dyn InferenceClient> = Arc::new(DelayedClient);
+        let limiter = RateLimiter::new(limits::InferenceLimits {
+            per_app: limits::Limits {
+                requests_per_minute: 0,
+                max_concurrent: 2,
+                prompt_char_quota: 0,
+            },
+            global: limits::Limits {
+                requests_per_minute: 0,
+                max_concurrent: 0,
+                prompt_char_quota: 0,
+            },
+            max_prompt_chars: 0,
+        });
+        let web = RecordingWeb {
+            replies: tokio::sync::Mutex::new(vec![]),
+        };
+
+        let request = |request_id: &str, content: &str| {
+            handle_inference_request(
+                request_id.to_string(),
+                content.to_string(),
+                "app".to_string(),
+                &client,
+                None,
+                &web,
+                &limiter,
+            )
+        };
+        // The third request exceeds the app's concurrency limit while the
+        // others are in flight, and must not take either of their replies.
+        let third = async {
+            sleep(Duration::from_millis(1)).await;
+            request("3", "rejected").await;
+        };
+        tokio::join!(request("1", "slow"), request("2", "fast"), third);
+
+        let replies = web.replies.lock().await;
+        assert_eq!(replies.len(), 3);
+        assert_eq!(replies[0].0, "3");
+        assert_eq!(replies[0].1, 429);
+        assert_eq!(replies[1], ("2".to_string(), 200, "fast".to_string()));
+        assert_eq!(replies[2], ("1".to_string(), 200, "slow".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_workspace_request_replies() {
+        struct RecordingWeb {
+            replies: tokio::sync::Mutex<Vec<(String, u16, String)>>,
+        }
+
+        #[async_trait::async_trait]
+        impl Web for RecordingWeb {
+            async fn launch_app(&self, _id: String, _content: String) {}
+            async fn handle_reply(&self, request_id: String, status: u16, content: String) {
+                self.replies
+                    .lock()
+                    .await
+                    .push((request_id, status, content));
+            }
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.md"), "# notes").unwrap();
+        std::fs::write(dir.path().join(".env"), "SECRET=1").unwrap();
+        let folders = vec![dir.path().to_str().unwrap().to_string()];
+        let access = || WorkspaceAccess::new(&folders, &["*.md".to_string()]);
+        let web = RecordingWeb {
+            replies: tokio::sync::Mutex::new(vec![]),
+        };
+
+        let read = |request_id: &str, path: &str| AgentRequest::ReadWorkspaceFile {
+            request_id: request_id.to_string(),
+            app_id: "app".to_string(),
+            path: path.to_string(),
+        };
+        handle_workspace_request(read("1", "notes.md"), access(), &web).await;
+        handle_workspace_request(read("2", ".env"), access(), &web).await;
+        handle_workspace_request(
+            AgentRequest::ListDirectory {
+                request_id: "3".to_string(),
+                app_id: "app".to_string(),
+                path: String::new(),
+            },
+            access(),
+            &web,
+        )
+        .await;
+
+        let replies = web.replies.lock().await;
+        assert_eq!(replies[0], ("1".to_string(), 200, "# notes".to_string()));
+        assert_eq!((replies[1].0.as_str(), replies[1].1), ("2", 403));
+        assert_eq!(
+            replies[2],
+            (
+                "3".to_string(),
+                200,
+                r#"[{"name":"notes.md","kind":"file","bytes":7}]"#.to_string()
+            )
+        );
+    }
+
+    // Web response handling tests
+    #[tokio::test]
+    async fn test_handle_web_doc_change_launch_app() {
+        struct RecordingWeb {
+            launched: tokio::sync::Mutex<Vec<(String, String)>>,
+            inference: tokio::sync::Mutex<Vec<(String, String)>>,
+        }
+
+        #[async_trait::async_trait]
+        impl Web for RecordingWeb {
+            async fn launch_app(&self, id: String, content: String) {
+                let mut l = self.launched.lock().await;
+                l.push((id, content));
+            }
+
+            async fn handle_reply(&self, request_id: String, _status: u16, content: String) {
+                let mut v = self.inference.lock().await;
+                v.push((request_id, content));
+            }
+        }
+
+        let repo = Repo::new(None, Box::new(NoStorage));
+        let repo_handle = repo.run();
+        let doc_handle = repo_handle.new_document();
+
+        // Insert a WebApp response
+        doc_handle.with_doc_mut(|doc| {
+            let mut agent: LspAgent = match hydrate(doc) {
+                Ok(a) => a,
+                Err(_) => LspAgent::default(),
+            };
+            agent.responses.push(AgentResponse::WebApp {
+                id: "appA".to_string(),
+                content: "<html/>".to_string(),
+            });
+            let mut tx = doc.transaction();
+            reconcile(&mut tx, &agent).unwrap();
+            tx.commit();
+        });
+
+        let web = RecordingWeb {
+            launched: tokio::sync::Mutex::new(vec![]),
+            inference: tokio::sync::Mutex::new(vec![]),
+        };
+        let rc = &web;
+
+        // Should handle and launch app
+        let handled = handle_web_doc_change(&doc_handle, rc).await;
+        assert!(!handled, "should not signal exit");
+
+        // verify launched
+        let launched = web.launched.lock().await;
+        assert_eq!(launched.len(), 1);
+        assert_eq!(launched[0].0, "appA");
+        assert_eq!(launched[0].1, "<html/>".to_string());
+
+        // ensure response removed
+        doc_handle.with_doc(|doc| {
+            let agent: LspAgent = match hydrate(doc) {
+                Ok(a) => a,
+                Err(_) => LspAgent::default(),
+            };
+            assert!(agent.responses.is_empty());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_handle_web_doc_change_inference() {
+        struct RecordingWeb {
+            launched: tokio::sync::Mutex<Vec<(String, String)>>,
+            inference: tokio::sync::Mutex<Vec<(String, String)>>,
+        }
+
+        #[async_trait::async_trait]
+        impl Web for RecordingWeb {
+            async fn launch_app(&self, _id: String, _content: String) {
+                // no-op for this test
+            }
+
+            async fn handle_reply(&self, request_id: String, _status: u16, content: String) {
+                let mut v = self.inference.lock().await;
+                v.push((request_id, content));
+            }
+        }
+
+        let repo = Repo::new(None, Box::new(NoStorage));
+        let repo_handle = repo.run();
+        let doc_handle = repo_handle.new_document();
+
+        doc_handle.with_doc_mut(|doc| {
+            let mut agent: LspAgent = match hydrate(doc) {
+                Ok(a) => a,
+                Err(_) => LspAgent::default(),
+            };
+            agent.responses.push(AgentResponse::Reply {
+                request_id: "r1".to_string(),
+                status: 200,
+                content: "ok".to_string(),
+            });
+            let mut tx = doc.transaction();
+            reconcile(&mut tx, &agent).unwrap();
+            tx.commit();
+        });
+
+        let web = RecordingWeb {
+            launched: tokio::sync::Mutex::new(vec![]),
+            inference: tokio::sync::Mutex::new(vec![]),
+        };
+        let rc = &web;
+
+        let handled = handle_web_doc_change(&doc_handle, rc).await;
+        assert!(!handled);
+
+        let inf = web.inference.lock().await;
+        assert_eq!(inf.len(), 1);
+        assert_eq!(inf[0].0, "r1");
+        assert_eq!(inf[0].1, "ok".to_string());
+
+        doc_handle.with_doc(|doc| {
+            let agent: LspAgent = match hydrate(doc) {
+                Ok(a) => a,
+                Err(_) => LspAgent::default(),
+            };
+            assert!(agent.responses.is_empty());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_handle_web_doc_change_chat_ignored() {
+        struct RecordingWeb {
+            launched: tokio::sync::Mutex<Vec<(String, String)>>,
+            inference: tokio::sync::Mutex<Vec<(String, String)>>,
+        }
+
+        #[async_trait::async_trait]
+        impl Web for RecordingWeb {
+            async fn launch_app(&self, _id: String, _content: String) {}
+            async fn handle_reply(&self, _request_id: String, _status: u16, _content: String) {}
+        }
+
+        let repo = Repo::new(None, Box::new(NoStorage));
+        let repo_handle = repo.run();
+        let doc_handle = repo_handle.new_document();
+
+        doc_handle.with_doc_mut(|doc| {
+            let mut agent: LspAgent = match hydrate(doc) {
+                Ok(a) => a,
+                Err(_) => LspAgent::default(),
+            };
+            agent.responses.push(AgentResponse::Chat("hey".to_string()));
+            let mut tx = doc.transaction();
+            reconcile(&mut tx, &agent).unwrap();
+            tx.commit();
+        });
+
+        let web = RecordingWeb {
+            launched: tokio::sync::Mutex::new(vec![]),
             inference: tokio::sync::Mutex::new(vec![]),
         };
         let rc = &web;
@@ -1289,7 +3104,7 @@ mod tests {
         #[async_trait::async_trait]
         impl Web for RecordingWeb2 {
             async fn launch_app(&self, _id: String, _content: String) {}
-            async fn handle_inference_response(&self, _app_id: String, _content: String) {}
+            async fn handle_reply(&self, _request_id: String, _status: u16, _content: String) {}
         }
 
         let rc = RecordingWeb2 {
@@ -1299,4 +3114,234 @@ mod tests {
         let ret = handle_web_doc_change(&doc_handle, &rc).await;
         assert!(ret, "expected true when should_exit is set");
     }
+
+    #[tokio::test]
+    async fn test_chat_in_named_session_keeps_active_session() {
+        use async_trait::async_trait;
+        use mockall::mock;
+
+        mock! {
+            pub TestClient {}
+            #[async_trait]
+            impl InferenceClient for TestClient {
+                async fn inference(&self, request: String, model: Option<String>) -> Result<String, String>;
+                async fn notify_shutdown(&self);
+                async fn show_message(&self, message: String);
+                async fn apply_edit(&self, proposal: EditProposal) -> Result<bool, String>;
+                async fn publish_diagnostics(&self, uri: String, diagnostics: Vec<traits::Diagnostic>);
+                async fn refresh_code_lenses(&self);
+            }
+        }
+
+        struct NoWeb;
+
+        #[async_trait::async_trait]
+        impl Web for NoWeb {
+            async fn launch_app(&self, _id: String, _content: String) {}
+            async fn handle_reply(&self, _request_id: String, _status: u16, _content: String) {}
+        }
+
+        let repo = Repo::new(None, Box::new(NoStorage));
+        let repo_handle = repo.run();
+        let doc_handle = repo_handle.new_document();
+        doc_handle.with_doc_mut(|doc| {
+            let mut tx = doc.transaction();
+            reconcile(&mut tx, LspAgent::default()).unwrap();
+            sessions::create(&mut tx, "work").unwrap();
+            tx.commit();
+        });
+
+        let mut mock_client = MockTestClient::new();
+        mock_client
+            .expect_inference()
+            .returning(|_, _| Ok(r#"{"action": "answer", "message": "hi"}"#.to_string()));
+        let client: Arc<dyn InferenceClient> = Arc::new(mock_client);
+
+        let (responder, reply) = oneshot::channel();
+        let chat_req = ChatRequest {
+            session: Some("thread".to_string()),
+            content: "hello".to_string(),
+            model: None,
+            editor_history: None,
+            responder,
+        };
+        handle_chat_request(chat_req, &doc_handle, &client, &NoWeb).await;
+        assert_eq!(reply.await.unwrap(), Some("hi".to_string()));
+
+        doc_handle.with_doc(|doc| {
+            assert_eq!(sessions::active_name(doc), "work");
+            assert_eq!(
+                sessions::history(doc, "thread"),
+                vec![
+                    ConversationFragment::User("hello".to_string()),
+                    ConversationFragment::Assistant("hi".to_string()),
+                ]
+            );
+            assert!(sessions::history(doc, "work").is_empty());
+        });
+    }
+
+    #[tokio::test]
+    async fn test_empty_editor_history_keeps_session_history() {
+        use async_trait::async_trait;
+        use mockall::mock;
+
+        mock! {
+            pub TestClient {}
+            #[async_trait]
+            impl InferenceClient for TestClient {
+                async fn inference(&self, request: String, model: Option<String>) -> Result<String, String>;
+                async fn notify_shutdown(&self);
+                async fn show_message(&self, message: String);
+                async fn apply_edit(&self, proposal: EditProposal) -> Result<bool, String>;
+                async fn publish_diagnostics(&self, uri: String, diagnostics: Vec<traits::Diagnostic>);
+                async fn refresh_code_lenses(&self);
+            }
+        }
+
+        struct NoWeb;
+
+        #[async_trait::async_trait]
+        impl Web for NoWeb {
+            async fn launch_app(&self, _id: String, _content: String) {}
+            async fn handle_reply(&self, _request_id: String, _status: u16, _content: String) {}
+        }
+
+        let repo = Repo::new(None, Box::new(NoStorage));
+        let repo_handle = repo.run();
+        let doc_handle = repo_handle.new_document();
+        let earlier = vec![
+            ConversationFragment::User("earlier".to_string()),
+            ConversationFragment::Assistant("reply".to_string()),
+        ];
+        doc_handle.with_doc_mut(|doc| {
+            let mut tx = doc.transaction();
+            reconcile(&mut tx, LspAgent::default()).unwrap();
+            sessions::create(&mut tx, "work").unwrap();
+            sessions::set_history(&mut tx, "work", earlier.clone());
+            tx.commit();
+        });
+
+        let mut mock_client = MockTestClient::new();
+        mock_client
+            .expect_inference()
+            .returning(|_, _| Ok(r#"{"action": "answer", "message": "hi"}"#.to_string()));
+        let client: Arc<dyn InferenceClient> = Arc::new(mock_client);
+
+        let (responder, reply) = oneshot::channel();
+        let chat_req = ChatRequest {
+            session: Some("work".to_string()),
+            content: "hello".to_string(),
+            model: None,
+            editor_history: Some(Vec::new()),
+            responder,
+        };
+        handle_chat_request(chat_req, &doc_handle, &client, &NoWeb).await;
+        assert_eq!(reply.await.unwrap(), Some("hi".to_string()));
+
+        let mut expected = earlier.clone();
+        expected.push(ConversationFragment::User("hello".to_string()));
+        expected.push(ConversationFragment::Assistant("hi".to_string()));
+        doc_handle.with_doc(|doc| {
+            assert_eq!(sessions::history(doc, "work"), expected);
+        });
+    }
+
+    #[tokio::test]
+    async fn test_chat_returns_rejected_app_to_the_model() {
+        use async_trait::async_trait;
+        use mockall::mock;
+
+        mock! {
+            pub TestClient {}
+            #[async_trait]
+            impl InferenceClient for TestClient {
+                async fn inference(&self, request: String, model: Option<String>) -> Result<String, String>;
+                async fn notify_shutdown(&self);
+                async fn show_message(&self, message: String);
+                async fn apply_edit(&self, proposal: EditProposal) -> Result<bool, String>;
+                async fn publish_diagnostics(&self, uri: String, diagnostics: Vec<traits::Diagnostic>);
+                async fn refresh_code_lenses(&self);
+            }
+        }
+
+        struct RecordingWeb {
+            launched: tokio::sync::Mutex<Vec<String>>,
+        }
+
+        #[async_trait::async_trait]
+        impl Web for RecordingWeb {
+            async fn launch_app(&self, _id: String, content: String) {
+                self.launched.lock().await.push(content);
+            }
+            async fn handle_reply(&self, _request_id: String, _status: u16, _content: String) {}
+        }
+
+        let broken = "<!DOCTYPE html><html><body><script>if (a {</script></body></html>";
+        let fixed = "<!DOCTYPE html><html><body><script>if (a) {}</script></body></html>";
+
+        let repo = Repo::new(None, Box::new(NoStorage));
+        let repo_handle = repo.run();
+        let doc_handle = repo_handle.new_document();
+        doc_handle.with_doc_mut(|doc| {
+            let mut tx = doc.transaction();
+            reconcile(&mut tx, LspAgent::default()).unwrap();
+            tx.commit();
+        });
+
+        let requests = Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+        let seen = requests.clone();
+        let mut mock_client = MockTestClient::new();
+        mock_client.expect_inference().returning(move |request, _| {
+            let mut seen = seen.lock().unwrap();
+            seen.push(request);
+            let app = if seen.len() == 1 { broken } else { fixed };
+            Ok(serde_json::json!({"action": "launch_app", "app": app}).to_string())
+        });
+        let client: Arc<dyn InferenceClient> = Arc::new(mock_client);
+        let web = RecordingWeb {
+            launched: tokio::sync::Mutex::new(vec![]),
+        };
+
+        let (responder, reply) = oneshot::channel();
+        let chat_req = ChatRequest {
+            session: None,
+            content: "make an app".to_string(),
+            model: None,
+            editor_history: None,
+            responder,
+        };
+        handle_chat_request(chat_req, &doc_handle, &client, &web).await;
+        assert_eq!(reply.await.unwrap(), None);
+
+        assert_eq!(*web.launched.lock().await, vec![fixed.to_string()]);
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let retry: serde_json::Value = serde_json::from_str(&requests[1]).unwrap();
+        let feedback = retry["history"][1]["content"].as_str().unwrap();
+        assert!(
+            feedback.contains("Script 1 has a syntax error"),
+            "{}",
+            feedback
+        );
+        assert_eq!(retry["rejected_app"].as_str(), Some(broken));
+
+        let history = doc_handle.with_doc(|doc| sessions::history(doc, sessions::DEFAULT_SESSION));
+        assert!(!history.is_empty());
+        assert!(history.iter().all(|fragment| match fragment {
+            ConversationFragment::User(text) | ConversationFragment::Assistant(text) => {
+                !text.contains(broken)
+            }
+            ConversationFragment::Summary(_) => true,
+        }));
+    }
+
+    #[tokio::test]
+    async fn test_stop_repo() {
+        let repo = Repo::new(None, Box::new(NoStorage));
+        let repo_handle = repo.run();
+        repo_handle.new_document();
+
+        assert_eq!(stop_repo(repo_handle).await, Ok(()));
+    }
 }
@@ -0,0 +1,216 @@
+use crate::doc_paths::{self, Readable, Writable};
+use crate::{LibraryApp, app_html, audit, prompts};
+use traits::LibraryAppInfo;
+
+/// Closed apps kept in the library; each holds its full HTML in the shared
+/// document, so the least recently used ones are dropped beyond this.
+const MAX_LIBRARY_APPS: usize = 50;
+
+const LIBRARY: &[&str] = &["app_library"];
+const WEBVIEWS: &[&str] = &["webviews", "documents"];
+
+fn app_title(html: &str) -> String {
+    app_html::title(html).unwrap_or_else(|| "Untitled app".to_string())
+}
+
+fn is_running<D: Readable>(doc: &D, app_id: &str) -> bool {
+    doc_paths::contains(doc, WEBVIEWS, app_id)
+}
+
+/// Adds a newly launched app to the library.
+pub(crate) fn record_launch<D: Writable>(doc: &mut D, app_id: &str, prompt: &str, html: &str) {
+    let app = LibraryApp {
+        title: app_title(html),
+        prompt: prompt.to_string(),
+        html: html.to_string(),
+        last_used_ms: audit::now_ms(),
+    };
+    doc_paths::put(doc, LIBRARY, app_id, app);
+    evict(doc, MAX_LIBRARY_APPS);
+}
+
+/// Marks a library app as used now, adding it if it was launched before the
+/// library existed.
+pub(crate) fn touch<D: Writable>(doc: &mut D, app_id: &str, html: &str) {
+    let existing = doc_paths::get(doc, LIBRARY, app_id);
+    doc_paths::put(doc, LIBRARY, app_id, touched(existing, html));
+    evict(doc, MAX_LIBRARY_APPS);
+}
+
+/// Drops the least recently used closed apps beyond `max_closed`. Running apps
+/// are always kept; closing one puts it back in the library anyway.
+fn evict<D: Writable>(doc: &mut D, max_closed: usize) {
+    let mut closed: Vec<(u64, String)> = doc_paths::keys(doc, LIBRARY)
+        .into_iter()
+        .filter(|id| !is_running(doc, id))
+        .map(|id| {
+            let last_used_ms =
+                doc_paths::get(doc, &["app_library", &id], "last_used_ms").unwrap_or_default();
+            (last_used_ms, id)
+        })
+        .collect();
+    if closed.len() <= max_closed {
+        return;
+    }
+    closed.sort();
+    for (_, id) in &closed[..closed.len() - max_closed] {
+        doc_paths::delete(doc, LIBRARY, id);
+        doc_paths::delete(doc, &["app_values"], id);
+    }
+}
+
+/// Returns `existing` (or a new entry for `html`) with its last-used time set to now.
+pub(crate) fn touched(existing: Option<LibraryApp>, html: &str) -> LibraryApp {
+    let mut app = existing.unwrap_or_else(|| LibraryApp {
+        title: app_title(html),
+        html: html.to_string(),
+        ..Default::default()
+    });
+    app.last_used_ms = audit::now_ms();
+    app
+}
+
+/// Returns the HTML of a closed library app that can be launched again under its id.
+pub(crate) fn relaunchable<D: Readable>(doc: &D, app_id: &str) -> Result<String, String> {
+    let Some(app) = doc_paths::get::<_, LibraryApp>(doc, LIBRARY, app_id) else {
+        return Err(format!("App '{}' is not in the app library.", app_id));
+    };
+    if is_running(doc, app_id) {
+        return Err(format!("App '{}' is already running.", app.title));
+    }
+    Ok(app.html)
+}
+
+/// Closed apps as shown to the model, most recently used first.
+pub(crate) fn closed_apps<D: Readable>(doc: &D) -> Vec<prompts::ClosedAppInfo> {
+    list(doc)
+        .into_iter()
+        .filter(|app| !app.running)
+        .map(|app| prompts::ClosedAppInfo {
+            id: app.id,
+            title: app.title,
+            prompt: app.prompt,
+        })
+        .collect()
+}
+
+/// Lists the library, most recently used first. The apps' HTML is not read.
+pub(crate) fn list<D: Readable>(doc: &D) -> Vec<LibraryAppInfo> {
+    let mut apps: Vec<LibraryAppInfo> = doc_paths::keys(doc, LIBRARY)
+        .into_iter()
+        .map(|id| {
+            let path = &["app_library", id.as_str()];
+            LibraryAppInfo {
+                title: doc_paths::get(doc, path, "title").unwrap_or_default(),
+                prompt: doc_paths::get(doc, path, "prompt").unwrap_or_default(),
+                last_used_ms: doc_paths::get(doc, path, "last_used_ms").unwrap_or_default(),
+                running: is_running(doc, &id),
+                id,
+            }
+        })
+        .collect();
+    apps.sort_by(|a, b| {
+        b.last_used_ms
+            .cmp(&a.last_used_ms)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    apps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DocumentContent, LspAgent};
+    use automerge::AutoCommit;
+    use autosurgeon::{hydrate, reconcile};
+
+    fn doc_of(agent: &LspAgent) -> AutoCommit {
+        let mut doc = AutoCommit::new();
+        reconcile(&mut doc, agent).unwrap();
+        doc
+    }
+
+    #[test]
+    fn test_closed_app_stays_relaunchable() {
+        let mut doc = doc_of(&LspAgent::default());
+        let html = "<html><title>Notes</title></html>";
+        record_launch(&mut doc, "app-1", "make a note app", html);
+        doc_paths::put(
+            &mut doc,
+            WEBVIEWS,
+            "app-1",
+            DocumentContent {
+                text: html.to_string(),
+            },
+        );
+
+        assert!(relaunchable(&doc, "app-1").is_err());
+        assert!(closed_apps(&doc).is_empty());
+
+        doc_paths::delete(&mut doc, WEBVIEWS, "app-1");
+        touch(&mut doc, "app-1", html);
+
+        assert_eq!(relaunchable(&doc, "app-1").unwrap(), html);
+        let closed = closed_apps(&doc);
+        assert_eq!(closed.len(), 1);
+        assert_eq!(closed[0].title, "Notes");
+        assert_eq!(closed[0].prompt, "make a note app");
+        assert!(relaunchable(&doc, "unknown").is_err());
+    }
+
+    #[test]
+    fn test_touch_adds_untracked_app() {
+        let mut doc = doc_of(&LspAgent::default());
+        touch(&mut doc, "old", "<html></html>");
+
+        let listed = list(&doc);
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].title, "Untitled app");
+        assert!(listed[0].last_used_ms > 0);
+        assert!(!listed[0].running);
+    }
+
+    #[test]
+    fn test_evict_drops_least_recently_used_closed_apps() {
+        let mut agent = LspAgent::default();
+        for (id, last_used_ms) in [("a", 1), ("b", 4), ("c", 2), ("running", 0), ("d", 3)] {
+            agent.app_library.insert(
+                id.to_string(),
+                LibraryApp {
+                    last_used_ms,
+                    ..Default::default()
+                },
+            );
+            agent
+                .app_values
+                .insert(id.to_string(), vec!["key".to_string()]);
+        }
+        agent
+            .webviews
+            .documents
+            .insert("running".to_string(), DocumentContent::default());
+        let mut doc = doc_of(&agent);
+
+        evict(&mut doc, 2);
+
+        let agent: LspAgent = hydrate(&doc).unwrap();
+        let mut ids: Vec<&str> = agent.app_library.keys().map(String::as_str).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["b", "d", "running"]);
+        let mut ids: Vec<&str> = agent.app_values.keys().map(String::as_str).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["b", "d", "running"]);
+    }
+
+    #[test]
+    fn test_list_most_recent_first() {
+        let mut doc = doc_of(&LspAgent::default());
+        record_launch(&mut doc, "a", "", "<html></html>");
+        record_launch(&mut doc, "b", "", "<html></html>");
+        doc_paths::put(&mut doc, &["app_library", "a"], "last_used_ms", 1u64);
+        doc_paths::put(&mut doc, &["app_library", "b"], "last_used_ms", 2u64);
+
+        let ids: Vec<String> = list(&doc).into_iter().map(|app| app.id).collect();
+        assert_eq!(ids, vec!["b", "a"]);
+    }
+}
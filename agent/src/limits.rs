@@ -0,0 +1,459 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(60);
+/// Window over which prompt characters count against the quota.
+const QUOTA_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+const DEFAULT_APP_REQUESTS_PER_MINUTE: usize = 20;
+const DEFAULT_APP_MAX_CONCURRENT: usize = 2;
+const DEFAULT_APP_PROMPT_CHAR_QUOTA: usize = 1_000_000;
+const DEFAULT_GLOBAL_REQUESTS_PER_MINUTE: usize = 60;
+const DEFAULT_GLOBAL_MAX_CONCURRENT: usize = 4;
+const DEFAULT_GLOBAL_PROMPT_CHAR_QUOTA: usize = 5_000_000;
+const DEFAULT_MAX_APP_PROMPT_CHARS: usize = 100_000;
+
+/// A set of inference limits. A value of `0` disables that limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Limits {
+    pub requests_per_minute: usize,
+    pub max_concurrent: usize,
+    /// Prompt characters allowed per hour.
+    pub prompt_char_quota: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct InferenceLimits {
+    pub per_app: Limits,
+    pub global: Limits,
+    /// Largest single prompt an app may send. `0` disables the check.
+    pub max_prompt_chars: usize,
+}
+
+impl InferenceLimits {
+    pub fn from_env() -> Self {
+        Self {
+            per_app: Limits {
+                requests_per_minute: env_limit(
+                    "LSP_AGENT_APP_REQUESTS_PER_MINUTE",
+                    DEFAULT_APP_REQUESTS_PER_MINUTE,
+                ),
+                max_concurrent: env_limit(
+                    "LSP_AGENT_APP_MAX_CONCURRENT",
+                    DEFAULT_APP_MAX_CONCURRENT,
+                ),
+                prompt_char_quota: env_limit(
+                    "LSP_AGENT_APP_PROMPT_CHAR_QUOTA",
+                    DEFAULT_APP_PROMPT_CHAR_QUOTA,
+                ),
+            },
+            global: Limits {
+                requests_per_minute: env_limit(
+                    "LSP_AGENT_GLOBAL_REQUESTS_PER_MINUTE",
+                    DEFAULT_GLOBAL_REQUESTS_PER_MINUTE,
+                ),
+                max_concurrent: env_limit(
+                    "LSP_AGENT_GLOBAL_MAX_CONCURRENT",
+                    DEFAULT_GLOBAL_MAX_CONCURRENT,
+                ),
+                prompt_char_quota: env_limit(
+                    "LSP_AGENT_GLOBAL_PROMPT_CHAR_QUOTA",
+                    DEFAULT_GLOBAL_PROMPT_CHAR_QUOTA,
+                ),
+            },
+            max_prompt_chars: env_limit(
+                "LSP_AGENT_MAX_APP_PROMPT_CHARS",
+                DEFAULT_MAX_APP_PROMPT_CHARS,
+            ),
+        }
+    }
+}
+
+pub(crate) fn env_limit(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse::<usize>().ok())
+        .unwrap_or(default)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Scope {
+    App,
+    Global,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Throttled {
+    RequestsPerMinute(Scope, usize),
+    MaxConcurrent(Scope, usize),
+    PromptCharQuota(Scope, usize),
+}
+
+impl fmt::Display for Throttled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scope = |scope: &Scope| match scope {
+            Scope::App => "per-app",
+            Scope::Global => "global",
+        };
+        match self {
+            Throttled::RequestsPerMinute(s, limit) => write!(
+                f,
+                "{} limit of {} inference requests per minute reached",
+                scope(s),
+                limit
+            ),
+            Throttled::MaxConcurrent(s, limit) => write!(
+                f,
+                "{} limit of {} concurrent inference requests reached",
+                scope(s),
+                limit
+            ),
+            Throttled::PromptCharQuota(s, limit) => write!(
+                f,
+                "{} quota of {} prompt characters per hour exhausted",
+                scope(s),
+                limit
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct PromptTooLarge {
+    pub chars: usize,
+    pub max: usize,
+}
+
+impl fmt::Display for PromptTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "prompt of {} characters exceeds the maximum of {} characters",
+            self.chars, self.max
+        )
+    }
+}
+
+#[derive(Default)]
+struct Usage {
+    recent: VecDeque<Instant>,
+    in_flight: usize,
+    /// Prompt sizes admitted within the quota window, oldest first.
+    prompts: VecDeque<(Instant, usize)>,
+    prompt_chars: usize,
+    last_notified: Option<Instant>,
+}
+
+impl Usage {
+    /// Forgets requests that fell out of their windows.
+    fn expire(&mut self, now: Instant) {
+        while let Some(oldest) = self.recent.front() {
+            if now.duration_since(*oldest) >= WINDOW {
+                self.recent.pop_front();
+            } else {
+                break;
+            }
+        }
+        while let Some((oldest, chars)) = self.prompts.front() {
+            if now.duration_since(*oldest) >= QUOTA_WINDOW {
+                self.prompt_chars -= chars;
+                self.prompts.pop_front();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Whether nothing about this usage still affects a decision.
+    fn is_idle(&self, now: Instant) -> bool {
+        self.in_flight == 0
+            && self.recent.is_empty()
+            && self.prompts.is_empty()
+            && self
+                .last_notified
+                .is_none_or(|last| now.duration_since(last) >= WINDOW)
+    }
+
+    fn check(
+        &mut self,
+        limits: &Limits,
+        scope: Scope,
+        chars: usize,
+        now: Instant,
+    ) -> Result<(), Throttled> {
+        self.expire(now);
+
+        if limits.requests_per_minute > 0 && self.recent.len() >= limits.requests_per_minute {
+            return Err(Throttled::RequestsPerMinute(
+                scope,
+                limits.requests_per_minute,
+            ));
+        }
+        if limits.max_concurrent > 0 && self.in_flight >= limits.max_concurrent {
+            return Err(Throttled::MaxConcurrent(scope, limits.max_concurrent));
+        }
+        if limits.prompt_char_quota > 0 && self.prompt_chars + chars > limits.prompt_char_quota {
+            return Err(Throttled::PromptCharQuota(scope, limits.prompt_char_quota));
+        }
+        Ok(())
+    }
+
+    fn admit(&mut self, chars: usize, now: Instant) {
+        self.recent.push_back(now);
+        self.in_flight += 1;
+        self.prompts.push_back((now, chars));
+        self.prompt_chars += chars;
+    }
+}
+
+#[derive(Default)]
+struct LimiterState {
+    global: Usage,
+    apps: HashMap<String, Usage>,
+}
+
+/// Server-side gate for app inference requests.
+///
+/// Every app request must acquire a permit before inference is called; the
+/// permit releases its concurrency slot when dropped.
+pub(crate) struct RateLimiter {
+    limits: InferenceLimits,
+    state: Mutex<LimiterState>,
+}
+
+pub(crate) struct InferencePermit<'a> {
+    limiter: &'a RateLimiter,
+    app_id: String,
+}
+
+impl RateLimiter {
+    pub fn new(limits: InferenceLimits) -> Self {
+        Self {
+            limits,
+            state: Mutex::new(LimiterState::default()),
+        }
+    }
+
+    /// Rejects oversized prompts before they count against any quota.
+    pub fn check_prompt_size(&self, prompt: &str) -> Result<(), PromptTooLarge> {
+        let max = self.limits.max_prompt_chars;
+        let chars = prompt.chars().count();
+        if max > 0 && chars > max {
+            return Err(PromptTooLarge { chars, max });
+        }
+        Ok(())
+    }
+
+    pub fn try_acquire(
+        &self,
+        app_id: &str,
+        prompt: &str,
+    ) -> Result<InferencePermit<'_>, Throttled> {
+        self.try_acquire_at(app_id, prompt.chars().count(), Instant::now())
+    }
+
+    fn try_acquire_at(
+        &self,
+        app_id: &str,
+        chars: usize,
+        now: Instant,
+    ) -> Result<InferencePermit<'_>, Throttled> {
+        let mut state = self.state.lock().unwrap();
+        let LimiterState { global, apps } = &mut *state;
+        // Apps that stopped making requests are forgotten, so closed apps do
+        // not accumulate here.
+        apps.retain(|id, usage| {
+            usage.expire(now);
+            id == app_id || !usage.is_idle(now)
+        });
+        let app = apps.entry(app_id.to_string()).or_default();
+
+        app.check(&self.limits.per_app, Scope::App, chars, now)?;
+        global.check(&self.limits.global, Scope::Global, chars, now)?;
+
+        app.admit(chars, now);
+        global.admit(chars, now);
+
+        Ok(InferencePermit {
+            limiter: self,
+            app_id: app_id.to_string(),
+        })
+    }
+
+    /// Returns true at most once per minute per app, so a looping app does not
+    /// flood the editor with throttling notifications.
+    pub fn should_notify(&self, app_id: &str) -> bool {
+        self.should_notify_at(app_id, Instant::now())
+    }
+
+    fn should_notify_at(&self, app_id: &str, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let app = state.apps.entry(app_id.to_string()).or_default();
+        match app.last_notified {
+            Some(last) if now.duration_since(last) < WINDOW => false,
+            _ => {
+                app.last_notified = Some(now);
+                true
+            }
+        }
+    }
+
+    fn release(&self, app_id: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.global.in_flight = state.global.in_flight.saturating_sub(1);
+        if let Some(app) = state.apps.get_mut(app_id) {
+            app.in_flight = app.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+impl Drop for InferencePermit<'_> {
+    fn drop(&mut self) {
+        self.limiter.release(&self.app_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(per_app: (usize, usize, usize), global: (usize, usize, usize)) -> InferenceLimits {
+        InferenceLimits {
+            per_app: Limits {
+                requests_per_minute: per_app.0,
+                max_concurrent: per_app.1,
+                prompt_char_quota: per_app.2,
+            },
+            global: Limits {
+                requests_per_minute: global.0,
+                max_concurrent: global.1,
+                prompt_char_quota: global.2,
+            },
+            max_prompt_chars: 0,
+        }
+    }
+
+    #[test]
+    fn test_requests_per_minute_window() {
+        let limiter = RateLimiter::new(limits((2, 0, 0), (0, 0, 0)));
+        let start = Instant::now();
+
+        drop(limiter.try_acquire_at("a", 1, start).unwrap());
+        drop(limiter.try_acquire_at("a", 1, start).unwrap());
+        assert_eq!(
+            limiter.try_acquire_at("a", 1, start).err(),
+            Some(Throttled::RequestsPerMinute(Scope::App, 2))
+        );
+        // Other apps have their own budget.
+        assert!(limiter.try_acquire_at("b", 1, start).is_ok());
+        // The window slides.
+        assert!(limiter.try_acquire_at("a", 1, start + WINDOW).is_ok());
+    }
+
+    #[test]
+    fn test_max_concurrent_released_on_drop() {
+        let limiter = RateLimiter::new(limits((0, 0, 0), (0, 1, 0)));
+        let now = Instant::now();
+
+        let permit = limiter.try_acquire_at("a", 1, now).unwrap();
+        assert_eq!(
+            limiter.try_acquire_at("b", 1, now).err(),
+            Some(Throttled::MaxConcurrent(Scope::Global, 1))
+        );
+        drop(permit);
+        assert!(limiter.try_acquire_at("b", 1, now).is_ok());
+    }
+
+    #[test]
+    fn test_prompt_char_quota() {
+        let limiter = RateLimiter::new(limits((0, 0, 10), (0, 0, 0)));
+        let now = Instant::now();
+
+        drop(limiter.try_acquire_at("a", 8, now).unwrap());
+        assert_eq!(
+            limiter.try_acquire_at("a", 3, now).err(),
+            Some(Throttled::PromptCharQuota(Scope::App, 10))
+        );
+        assert!(limiter.try_acquire_at("a", 2, now).is_ok());
+    }
+
+    #[test]
+    fn test_prompt_char_quota_window() {
+        let limiter = RateLimiter::new(limits((0, 0, 10), (0, 0, 15)));
+        let start = Instant::now();
+
+        drop(limiter.try_acquire_at("a", 10, start).unwrap());
+        drop(limiter.try_acquire_at("b", 5, start + WINDOW).unwrap());
+        assert_eq!(
+            limiter.try_acquire_at("c", 1, start + WINDOW).err(),
+            Some(Throttled::PromptCharQuota(Scope::Global, 15))
+        );
+        // The oldest prompt leaves the window and frees its characters.
+        assert!(
+            limiter
+                .try_acquire_at("a", 10, start + QUOTA_WINDOW)
+                .is_ok()
+        );
+        assert_eq!(
+            limiter.try_acquire_at("c", 1, start + QUOTA_WINDOW).err(),
+            Some(Throttled::PromptCharQuota(Scope::Global, 15))
+        );
+    }
+
+    #[test]
+    fn test_idle_apps_are_forgotten() {
+        let limiter = RateLimiter::new(limits((0, 0, 0), (0, 0, 0)));
+        let start = Instant::now();
+
+        drop(limiter.try_acquire_at("a", 1, start).unwrap());
+        let permit = limiter.try_acquire_at("b", 1, start).unwrap();
+        assert!(limiter.should_notify_at("c", start));
+        assert_eq!(limiter.state.lock().unwrap().apps.len(), 3);
+
+        // `b` still has a request in flight.
+        drop(
+            limiter
+                .try_acquire_at("d", 1, start + QUOTA_WINDOW)
+                .unwrap(),
+        );
+        let mut apps: Vec<String> = limiter.state.lock().unwrap().apps.keys().cloned().collect();
+        apps.sort();
+        assert_eq!(apps, vec!["b", "d"]);
+        drop(permit);
+    }
+
+    #[test]
+    fn test_rejected_request_does_not_consume_budget() {
+        let limiter = RateLimiter::new(limits((1, 0, 0), (0, 0, 5)));
+        let now = Instant::now();
+
+        assert!(limiter.try_acquire_at("a", 10, now).is_err());
+        assert!(limiter.try_acquire_at("a", 5, now).is_ok());
+    }
+
+    #[test]
+    fn test_should_notify_once_per_window() {
+        let limiter = RateLimiter::new(limits((0, 0, 0), (0, 0, 0)));
+        let now = Instant::now();
+
+        assert!(limiter.should_notify_at("a", now));
+        assert!(!limiter.should_notify_at("a", now + Duration::from_secs(1)));
+        assert!(limiter.should_notify_at("b", now));
+        assert!(limiter.should_notify_at("a", now + WINDOW));
+    }
+
+    #[test]
+    fn test_check_prompt_size() {
+        let mut config = limits((0, 0, 0), (0, 0, 0));
+        config.max_prompt_chars = 5;
+        let limiter = RateLimiter::new(config);
+
+        assert!(limiter.check_prompt_size("12345").is_ok());
+        assert_eq!(
+            limiter.check_prompt_size("123456"),
+            Err(PromptTooLarge { chars: 6, max: 5 })
+        );
+    }
+}
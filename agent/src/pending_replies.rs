@@ -0,0 +1,86 @@
+//! Bookkeeping the web runtimes share for calls answered later through
+//! `Web::handle_reply`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How long a call may wait for its reply. Longer than an inference request
+/// may take, so only replies that were lost are given up on.
+pub const REPLY_TIMEOUT: Duration = Duration::from_secs(6 * 60);
+
+/// Waiters for replies, by request id.
+pub struct PendingReplies<T> {
+    waiting: Arc<Mutex<HashMap<String, T>>>,
+    timeout: Duration,
+}
+
+impl<T: Send + 'static> PendingReplies<T> {
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            waiting: Arc::new(Mutex::new(HashMap::new())),
+            timeout,
+        }
+    }
+
+    /// Registers `waiter` under a new request id, to be passed on with the
+    /// call. If no reply arrives in time the waiter is handed to `expire`.
+    /// Must be called within a tokio runtime.
+    pub fn register(&self, waiter: T, expire: impl FnOnce(T) + Send + 'static) -> String {
+        let request_id = Uuid::new_v4().to_string();
+        self.waiting
+            .lock()
+            .unwrap()
+            .insert(request_id.clone(), waiter);
+        let waiting = Arc::clone(&self.waiting);
+        let timeout = self.timeout;
+        let expired_id = request_id.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(timeout).await;
+            let waiter = waiting.lock().unwrap().remove(&expired_id);
+            if let Some(waiter) = waiter {
+                expire(waiter);
+            }
+        });
+        request_id
+    }
+
+    /// Removes the waiter for `request_id`, if it has neither been taken nor
+    /// expired.
+    pub fn take(&self, request_id: &str) -> Option<T> {
+        self.waiting.lock().unwrap().remove(request_id)
+    }
+}
+
+impl<T: Send + 'static> Default for PendingReplies<T> {
+    fn default() -> Self {
+        Self::with_timeout(REPLY_TIMEOUT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::oneshot;
+
+    #[tokio::test]
+    async fn test_reply_is_taken_once() {
+        let pending = PendingReplies::<u32>::default();
+        let request_id = pending.register(7, |_| panic!("should not expire"));
+        assert_eq!(pending.take(&request_id), Some(7));
+        assert_eq!(pending.take(&request_id), None);
+        assert_eq!(pending.take("unknown"), None);
+    }
+
+    #[tokio::test]
+    async fn test_unanswered_waiter_expires() {
+        let pending = PendingReplies::with_timeout(Duration::from_millis(10));
+        let (expired_tx, expired_rx) = oneshot::channel();
+        let request_id = pending.register(7u32, move |waiter| {
+            let _ = expired_tx.send(waiter);
+        });
+        assert_eq!(expired_rx.await, Ok(7));
+        assert_eq!(pending.take(&request_id), None);
+    }
+}
@@ -12,51 +12,150 @@ struct HistoryItem {
 #[derive(Serialize)]
 struct WebRequest<'a> {
     system: &'a str,
-    history: Vec<HistoryItem>,
+    history: &'a [HistoryItem],
     latest_user: &'a str,
     #[serde(skip_serializing_if = "Option::is_none")]
-    apps: Option<&'a [String]>,
+    apps: Option<&'a [AppInfo]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    closed_apps: Option<&'a [ClosedAppInfo]>,
     #[serde(skip_serializing_if = "Option::is_none")]
     apps_note: Option<&'a str>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    open_documents: Option<&'a [String]>,
+    open_documents: Option<&'a [DocumentInfo]>,
     #[serde(skip_serializing_if = "Option::is_none")]
     active_document: Option<&'a str>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    workspace_files: Option<&'a [WorkspaceFileInfo]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     docs_note: Option<&'a str>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stored_values: Option<&'a [StoredValueInfo]>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stored_values_note: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rejected_app: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    truncation_note: Option<String>,
 }
 
+const APP_TRUNCATION_MARKER: &str = "\n<!-- truncated to fit the request size limit -->";
+
+/// Builds the agent request, trimming it to at most `max_chars` characters
+/// (`0` disables the limit).
+///
+/// The oldest history entries are dropped first; if that is not enough, the
+/// app HTML is truncated. A `truncation_note` tells the model what was cut.
+/// `rejected_app` is the app that just failed validation, sent only with the
+/// request that asks for a fix.
 pub fn build_web_request(
     history: &[ConversationFragment],
     latest_user: &str,
-    apps: Option<&[String]>,
+    apps: Option<&AppsInfo>,
     docs: Option<&DocsInfo>,
     stored_values: Option<&[StoredValueInfo]>,
+    rejected_app: Option<&str>,
+    max_chars: usize,
 ) -> String {
-    let request = WebRequest {
-        system: WEB_ENVIRONMENT_SYSTEM_PROMPT.trim_end(),
-        history: render_history(history, false, false),
-        latest_user,
-        apps,
-        apps_note: apps
-            .as_ref()
-            .map(|_| "The app list below is provided because you requested running apps."),
-        open_documents: docs.map(|info| info.open_documents.as_slice()),
-        active_document: docs.and_then(|info| info.active_document.as_deref()),
-        docs_note: docs
-            .as_ref()
-            .map(|_| "The document list below is provided because you requested open documents."),
-        stored_values,
-        stored_values_note: stored_values
-            .as_ref()
-            .map(|_| "The stored values list below is provided because you requested it."),
-    };
+    let history = render_history(history, false, false);
+    // Entry sizes are measured once, so dropping the oldest entries does not
+    // re-serialize the request per entry.
+    let sizes: Vec<usize> = history.iter().map(history_item_chars).collect();
+    let mut omitted_history = 0;
+    let mut app_cap: Option<usize> = None;
 
-    serde_json::to_string_pretty(&request).unwrap_or_else(|_| "{}".to_string())
+    loop {
+        let capped_apps: Option<Vec<AppInfo>> = apps.map(|apps| {
+            apps.running
+                .iter()
+                .map(|app| AppInfo {
+                    id: app.id.clone(),
+                    html: cap_app(&app.html, app_cap),
+                    errors: app.errors.clone(),
+                })
+                .collect()
+        });
+        let request = WebRequest {
+            system: WEB_ENVIRONMENT_SYSTEM_PROMPT.trim_end(),
+            history: &history[omitted_history..],
+            latest_user,
+            apps: capped_apps.as_deref(),
+            closed_apps: apps.map(|info| info.closed.as_slice()),
+            apps_note: apps
+                .as_ref()
+                .map(|_| "The app list below is provided because you requested running apps."),
+            open_documents: docs.map(|info| info.open_documents.as_slice()),
+            active_document: docs.and_then(|info| info.active_document.as_deref()),
+            workspace_files: docs
+                .map(|info| info.workspace_files.as_slice())
+                .filter(|files| !files.is_empty()),
+            docs_note: docs.as_ref().map(
+                |_| "The document list below is provided because you requested open documents.",
+            ),
+            stored_values,
+            stored_values_note: stored_values
+                .as_ref()
+                .map(|_| "The stored values list below is provided because you requested it."),
+            rejected_app,
+            truncation_note: truncation_note(omitted_history, app_cap.is_some()),
+        };
+
+        let text = serde_json::to_string_pretty(&request).unwrap_or_else(|_| "{}".to_string());
+        let len = text.chars().count();
+        if max_chars == 0 || len <= max_chars {
+            return text;
+        }
+
+        if omitted_history < history.len() {
+            let mut excess = len - max_chars;
+            while excess > 0 && omitted_history < history.len() {
+                excess = excess.saturating_sub(sizes[omitted_history]);
+                omitted_history += 1;
+            }
+            continue;
+        }
+
+        if let Some(apps) = apps.map(|info| &info.running)
+            && !apps.is_empty()
+            && app_cap != Some(0)
+        {
+            let current = app_cap.unwrap_or_else(|| {
+                apps.iter()
+                    .map(|app| app.html.chars().count())
+                    .max()
+                    .unwrap_or_default()
+            });
+            let step = (len - max_chars).div_ceil(apps.len());
+            app_cap = Some(current.saturating_sub(step));
+            continue;
+        }
+
+        return text;
+    }
+}
+
+pub(crate) fn cap_app(app: &str, cap: Option<usize>) -> String {
+    match cap {
+        Some(cap) if app.chars().count() > cap => {
+            let mut cut: String = app.chars().take(cap).collect();
+            cut.push_str(APP_TRUNCATION_MARKER);
+            cut
+        }
+        _ => app.to_string(),
+    }
+}
+
+fn truncation_note(omitted_history: usize, truncated_apps: bool) -> Option<String> {
+    let mut parts = Vec::new();
+    if omitted_history > 0 {
+        parts.push(format!(
+            "{} earlier history entries were omitted to fit the request size limit.",
+            omitted_history
+        ));
+    }
+    if truncated_apps {
+        parts.push("Some app HTML was truncated to fit the request size limit.".to_string());
+    }
+    (!parts.is_empty()).then(|| parts.join(" "))
 }
 
 fn render_history(
@@ -75,6 +174,10 @@ fn render_history(
                 role: "user",
                 content: content.clone(),
             },
+            ConversationFragment::Summary(content) => HistoryItem {
+                role: "assistant",
+                content: format!("Summary of earlier conversation: {}", content),
+            },
         })
         .collect();
 
@@ -95,10 +198,141 @@ fn render_history(
     items
 }
 
+#[derive(Serialize)]
+struct SummaryRequest<'a> {
+    instructions: &'a str,
+    history: &'a [HistoryItem],
+}
+
+const SUMMARY_INSTRUCTIONS: &str = "Summarize the conversation below between a user and a web app \
+    assistant. Keep the user's goals, decisions, names of apps that were built or closed, and any \
+    open questions. Reply with the summary text only, no JSON and no markdown headings.";
+
+/// Most characters of indentation and separators a history item adds inside a
+/// request's pretty-printed array.
+const NESTED_ITEM_CHARS: usize = 20;
+
+/// Characters `item` takes up in the `history` array of a request.
+fn history_item_chars(item: &HistoryItem) -> usize {
+    serde_json::to_string_pretty(item)
+        .map(|text| text.chars().count())
+        .unwrap_or_default()
+        + NESTED_ITEM_CHARS
+}
+
+/// Builds the request used to compact older conversation fragments into one
+/// summary, keeping it within `max_chars` characters (`0` disables the limit).
+///
+/// Returns the request and how many leading fragments it covers; the rest are
+/// left for a later compaction. A first fragment too large on its own is cut.
+pub fn build_summary_request(
+    history: &[ConversationFragment],
+    max_chars: usize,
+) -> (String, usize) {
+    let mut items = render_history(history, false, false);
+    if max_chars != 0 && !items.is_empty() {
+        let mut total = summary_request(&[]).chars().count();
+        let covered = items
+            .iter()
+            .take_while(|item| {
+                total += history_item_chars(item);
+                total <= max_chars
+            })
+            .count();
+        if covered == 0 {
+            let first = &mut items[0];
+            let room = max_chars.saturating_sub(total - first.content.chars().count());
+            first.content = first.content.chars().take(room).collect();
+        }
+        items.truncate(covered.max(1));
+    }
+
+    (summary_request(&items), items.len())
+}
+
+fn summary_request(history: &[HistoryItem]) -> String {
+    let request = SummaryRequest {
+        instructions: SUMMARY_INSTRUCTIONS,
+        history,
+    };
+    serde_json::to_string_pretty(&request).unwrap_or_else(|_| "{}".to_string())
+}
+
+/// Builds the chat turn sent when the user asks about a selection from the editor.
+/// `first_line` and `last_line` are zero-based, as LSP sends them.
+pub fn build_selection_question(
+    uri: &str,
+    language_id: &str,
+    first_line: u32,
+    last_line: u32,
+    text: &str,
+) -> String {
+    let lines = if first_line == last_line {
+        format!("line {}", first_line + 1)
+    } else {
+        format!("lines {}-{}", first_line + 1, last_line + 1)
+    };
+    format!(
+        "Help me with this selection from {} ({}). Apps can read it again through \
+        wry://selection.\n\n```{}\n{}\n```",
+        uri, lines, language_id, text
+    )
+}
+
+/// A running app as shown to the model: its id, HTML and the errors it reported.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct AppInfo {
+    pub id: String,
+    pub html: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+}
+
+/// A closed app from the library that can be relaunched by id.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ClosedAppInfo {
+    pub id: String,
+    pub title: String,
+    pub prompt: String,
+}
+
+#[derive(Serialize, Clone, Default)]
+pub struct AppsInfo {
+    pub running: Vec<AppInfo>,
+    pub closed: Vec<ClosedAppInfo>,
+}
+
 #[derive(Serialize, Clone)]
 pub struct DocsInfo {
-    pub open_documents: Vec<String>,
+    pub open_documents: Vec<DocumentInfo>,
     pub active_document: Option<String>,
+    /// Files the user exposed to apps; apps can read them, the agent only sees this list.
+    pub workspace_files: Vec<WorkspaceFileInfo>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DocumentInfo {
+    pub uri: String,
+    /// Missing for an active document the editor has not opened through the server.
+    #[serde(flatten)]
+    pub metadata: Option<DocumentMetadata>,
+}
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DocumentMetadata {
+    pub language_id: String,
+    pub version: i32,
+    pub line_count: u64,
+    pub bytes: u64,
+    pub dirty: bool,
+    pub untitled: bool,
+}
+
+/// An exposed workspace file as the agent sees it: where it is and how big, never its content.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct WorkspaceFileInfo {
+    pub uri: String,
+    pub bytes: u64,
 }
 
 #[derive(Serialize, Clone)]
@@ -106,3 +340,154 @@ pub struct StoredValueInfo {
     pub key: String,
     pub description: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    #[test]
+    fn test_build_web_request_unlimited() {
+        let history = vec![ConversationFragment::User("x".repeat(1000))];
+        let request = build_web_request(&history, "hi", None, None, None, None, 0);
+        let parsed: Value = serde_json::from_str(&request).unwrap();
+
+        assert_eq!(parsed["history"].as_array().unwrap().len(), 1);
+        assert!(parsed.get("truncation_note").is_none());
+    }
+
+    #[test]
+    fn test_build_web_request_drops_oldest_history() {
+        let history: Vec<ConversationFragment> = (0..20)
+            .map(|i| ConversationFragment::User(format!("{}{}", i, "y".repeat(500))))
+            .collect();
+        let base = build_web_request(&[], "hi", None, None, None, None, 0)
+            .chars()
+            .count();
+        let max_chars = base + 3000;
+
+        let request = build_web_request(&history, "hi", None, None, None, None, max_chars);
+        let parsed: Value = serde_json::from_str(&request).unwrap();
+
+        assert!(request.chars().count() <= max_chars);
+        let items = parsed["history"].as_array().unwrap();
+        assert!(!items.is_empty() && items.len() < 20);
+        // The newest entry survives.
+        assert!(
+            items.last().unwrap()["content"]
+                .as_str()
+                .unwrap()
+                .starts_with("19")
+        );
+        assert!(
+            parsed["truncation_note"]
+                .as_str()
+                .unwrap()
+                .contains("earlier history entries were omitted")
+        );
+    }
+
+    #[test]
+    fn test_build_web_request_truncates_apps() {
+        let apps = AppsInfo {
+            running: vec![
+                AppInfo {
+                    id: "app-a".to_string(),
+                    html: "a".repeat(5000),
+                    errors: vec!["TypeError: a is undefined at line 3:1".to_string()],
+                },
+                AppInfo {
+                    id: "app-b".to_string(),
+                    html: "b".repeat(200),
+                    errors: Vec::new(),
+                },
+            ],
+            closed: vec![],
+        };
+        let base = build_web_request(&[], "hi", Some(&AppsInfo::default()), None, None, None, 0)
+            .chars()
+            .count();
+        let max_chars = base + 2000;
+
+        let request = build_web_request(&[], "hi", Some(&apps), None, None, None, max_chars);
+        let parsed: Value = serde_json::from_str(&request).unwrap();
+
+        assert!(request.chars().count() <= max_chars);
+        let apps_val = parsed["apps"].as_array().unwrap();
+        assert_eq!(apps_val.len(), 2);
+        assert_eq!(apps_val[0]["id"].as_str().unwrap(), "app-a");
+        assert!(
+            apps_val[0]["html"]
+                .as_str()
+                .unwrap()
+                .ends_with(APP_TRUNCATION_MARKER)
+        );
+        assert_eq!(apps_val[1]["html"].as_str().unwrap(), "b".repeat(200));
+        assert_eq!(
+            apps_val[0]["errors"][0].as_str().unwrap(),
+            "TypeError: a is undefined at line 3:1"
+        );
+        assert!(apps_val[1].get("errors").is_none());
+        assert!(
+            parsed["truncation_note"]
+                .as_str()
+                .unwrap()
+                .contains("app HTML was truncated")
+        );
+    }
+
+    #[test]
+    fn test_build_summary_request_fits_limit() {
+        let history: Vec<ConversationFragment> = (0..20)
+            .map(|i| ConversationFragment::User(format!("{}{}", i, "y".repeat(500))))
+            .collect();
+        let base = build_summary_request(&[], 0).0.chars().count();
+        let max_chars = base + 3000;
+
+        let (request, covered) = build_summary_request(&history, max_chars);
+        let parsed: Value = serde_json::from_str(&request).unwrap();
+
+        assert!(request.chars().count() <= max_chars);
+        assert!(covered > 0 && covered < 20);
+        let items = parsed["history"].as_array().unwrap();
+        assert_eq!(items.len(), covered);
+        assert!(items[0]["content"].as_str().unwrap().starts_with("0y"));
+
+        let huge = vec![ConversationFragment::User("z".repeat(10_000))];
+        let (request, covered) = build_summary_request(&huge, max_chars);
+        assert_eq!(covered, 1);
+        assert!(request.chars().count() <= max_chars);
+    }
+
+    #[test]
+    fn test_build_selection_question() {
+        let question = build_selection_question("file:///a.rs", "rust", 2, 4, "fn a() {}");
+        assert!(question.contains("file:///a.rs (lines 3-5)"));
+        assert!(question.ends_with("```rust\nfn a() {}\n```"));
+        assert!(build_selection_question("file:///a.rs", "rust", 0, 0, "x").contains("(line 1)"));
+    }
+
+    #[test]
+    fn test_build_summary_request_includes_previous_summary() {
+        let history = vec![
+            ConversationFragment::Summary("built a todo app".to_string()),
+            ConversationFragment::User("add due dates".to_string()),
+        ];
+        let (request, covered) = build_summary_request(&history, 0);
+        let parsed: Value = serde_json::from_str(&request).unwrap();
+        assert_eq!(covered, 2);
+
+        let items = parsed["history"].as_array().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(
+            items[0]["content"].as_str().unwrap(),
+            "Summary of earlier conversation: built a todo app"
+        );
+        assert!(
+            parsed["instructions"]
+                .as_str()
+                .unwrap()
+                .contains("Summarize")
+        );
+    }
+}
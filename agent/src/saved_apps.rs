@@ -0,0 +1,100 @@
+use crate::doc_paths::{self, Readable};
+use std::path::{Path, PathBuf};
+
+/// Workspace-relative directory that holds saved apps.
+pub(crate) const APPS_DIR: &str = ".lsp_agent/apps";
+
+/// Turns a user- or model-chosen app name into a safe file stem.
+///
+/// Runs of anything other than ASCII letters, digits, `-` and `_` become a single
+/// `-`, so a name can never escape the apps directory.
+pub(crate) fn file_stem(name: &str) -> Result<String, String> {
+    let name = name.trim();
+    let name = name.strip_suffix(".html").unwrap_or(name);
+    let mut stem = String::new();
+    for c in name.chars() {
+        if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+            stem.push(c);
+        } else if !stem.ends_with('-') {
+            stem.push('-');
+        }
+    }
+    let stem = stem.trim_matches('-').to_string();
+    if stem.is_empty() {
+        return Err(format!("'{}' is not a usable app name.", name));
+    }
+    Ok(stem)
+}
+
+/// Apps are saved under the first workspace folder.
+pub(crate) fn workspace_root<D: Readable>(doc: &D) -> Result<PathBuf, String> {
+    doc_paths::get_list::<_, String>(doc, &[], "workspace_folders")
+        .into_iter()
+        .next()
+        .map(PathBuf::from)
+        .ok_or_else(|| "No workspace folder is open to save apps into.".to_string())
+}
+
+fn apps_dir(root: &Path) -> PathBuf {
+    root.join(APPS_DIR)
+}
+
+/// Writes `html` to `<root>/.lsp_agent/apps/<name>.html`, replacing any app saved
+/// under the same name, and returns the written path.
+pub(crate) fn save(root: &Path, name: &str, html: &str) -> Result<PathBuf, String> {
+    let dir = apps_dir(root);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let path = dir.join(format!("{}.html", file_stem(name)?));
+    std::fs::write(&path, html)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// Returns the names of the saved apps, sorted. A missing directory means no apps.
+pub(crate) fn list(root: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(apps_dir(root)) else {
+        return Vec::new();
+    };
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "html"))
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+        .collect();
+    names.sort();
+    names
+}
+
+pub(crate) fn load(root: &Path, name: &str) -> Result<String, String> {
+    let path = apps_dir(root).join(format!("{}.html", file_stem(name)?));
+    std::fs::read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_stem() {
+        assert_eq!(file_stem("todo").unwrap(), "todo");
+        assert_eq!(file_stem("Todo List.html").unwrap(), "Todo-List");
+        assert_eq!(file_stem("../../etc/passwd").unwrap(), "etc-passwd");
+        assert!(file_stem(" / ").is_err());
+    }
+
+    #[test]
+    fn test_save_list_load() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(list(root.path()).is_empty());
+
+        let path = save(root.path(), "word count", "<html>1</html>").unwrap();
+        assert_eq!(path, root.path().join(APPS_DIR).join("word-count.html"));
+        save(root.path(), "alpha", "<html>2</html>").unwrap();
+        save(root.path(), "word count", "<html>3</html>").unwrap();
+
+        assert_eq!(list(root.path()), vec!["alpha", "word-count"]);
+        assert_eq!(load(root.path(), "word-count").unwrap(), "<html>3</html>");
+        assert!(load(root.path(), "missing").is_err());
+    }
+}
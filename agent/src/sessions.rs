@@ -0,0 +1,214 @@
+use crate::doc_paths::{self, Readable, Writable};
+use crate::{ConversationFragment, Session};
+use traits::SessionInfo;
+
+pub(crate) const DEFAULT_SESSION: &str = "default";
+
+const SESSIONS: &[&str] = &["sessions"];
+
+pub(crate) fn active_name<D: Readable>(doc: &D) -> String {
+    doc_paths::get::<_, String>(doc, &[], "active_session")
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| DEFAULT_SESSION.to_string())
+}
+
+/// Resolves the session a chat request should use: the named one, or the active one.
+pub(crate) fn resolve_name<D: Readable>(doc: &D, name: Option<&str>) -> String {
+    match name {
+        Some(name) if !name.trim().is_empty() => name.trim().to_string(),
+        _ => active_name(doc),
+    }
+}
+
+pub(crate) fn exists<D: Readable>(doc: &D, name: &str) -> bool {
+    doc_paths::contains(doc, SESSIONS, name)
+}
+
+pub(crate) fn session<D: Readable>(doc: &D, name: &str) -> Session {
+    doc_paths::get(doc, SESSIONS, name).unwrap_or_default()
+}
+
+pub(crate) fn history<D: Readable>(doc: &D, name: &str) -> Vec<ConversationFragment> {
+    doc_paths::get_list(doc, &["sessions", name], "conversation_history")
+}
+
+/// Stores the session under `name`, replacing the one there.
+pub(crate) fn put<D: Writable>(doc: &mut D, name: &str, session: &Session) {
+    doc_paths::put(doc, SESSIONS, name, session);
+}
+
+/// Creates the named session if needed.
+fn ensure<D: Writable>(doc: &mut D, name: &str) {
+    if !exists(doc, name) {
+        put(doc, name, &Session::default());
+    }
+}
+
+pub(crate) fn set_history<D: Writable>(
+    doc: &mut D,
+    name: &str,
+    history: Vec<ConversationFragment>,
+) {
+    ensure(doc, name);
+    doc_paths::put(doc, &["sessions", name], "conversation_history", history);
+}
+
+/// Appends `fragments` to the named session's history, creating the session if needed.
+pub(crate) fn extend_history<D: Writable>(
+    doc: &mut D,
+    name: &str,
+    fragments: impl IntoIterator<Item = ConversationFragment>,
+) {
+    ensure(doc, name);
+    for fragment in fragments {
+        doc_paths::push(doc, &["sessions", name], "conversation_history", fragment);
+    }
+}
+
+/// Ties `app_id` to the named session, creating the session if needed.
+pub(crate) fn add_app<D: Writable>(doc: &mut D, name: &str, app_id: &str) {
+    ensure(doc, name);
+    doc_paths::push(doc, &["sessions", name], "apps", app_id.to_string());
+}
+
+/// Returns the name of the session that launched `app_id`, if any.
+pub(crate) fn owner_of<D: Readable>(doc: &D, app_id: &str) -> Option<String> {
+    doc_paths::keys(doc, SESSIONS).into_iter().find(|name| {
+        doc_paths::get_list::<_, String>(doc, &["sessions", name], "apps")
+            .iter()
+            .any(|id| id == app_id)
+    })
+}
+
+pub(crate) fn create<D: Writable>(doc: &mut D, name: &str) -> Result<(), String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Session name must not be empty.".to_string());
+    }
+    if exists(doc, name) {
+        return Err(format!("Session '{}' already exists.", name));
+    }
+    put(doc, name, &Session::default());
+    doc_paths::put(doc, &[], "active_session", name);
+    Ok(())
+}
+
+pub(crate) fn switch<D: Writable>(doc: &mut D, name: &str) -> Result<(), String> {
+    let name = name.trim();
+    if name != DEFAULT_SESSION && !exists(doc, name) {
+        return Err(format!("Session '{}' does not exist.", name));
+    }
+    doc_paths::put(doc, &[], "active_session", name);
+    Ok(())
+}
+
+/// Deletes a session. Its apps keep running but are no longer tied to any conversation.
+pub(crate) fn delete<D: Writable>(doc: &mut D, name: &str) -> Result<(), String> {
+    let name = name.trim();
+    if name == DEFAULT_SESSION {
+        return Err("The default session cannot be deleted.".to_string());
+    }
+    if !exists(doc, name) {
+        return Err(format!("Session '{}' does not exist.", name));
+    }
+    doc_paths::delete(doc, SESSIONS, name);
+    if active_name(doc) == name {
+        doc_paths::put(doc, &[], "active_session", DEFAULT_SESSION);
+    }
+    Ok(())
+}
+
+/// Lists the sessions with the length of their history and apps, without
+/// reading the history itself.
+pub(crate) fn list<D: Readable>(doc: &D) -> Vec<SessionInfo> {
+    let active = active_name(doc);
+    let mut names = doc_paths::keys(doc, SESSIONS);
+    if !names.iter().any(|name| name == DEFAULT_SESSION) {
+        names.push(DEFAULT_SESSION.to_string());
+    }
+    names.sort();
+
+    names
+        .into_iter()
+        .map(|name| {
+            let path = &["sessions", name.as_str()];
+            SessionInfo {
+                active: name == active,
+                history_len: doc_paths::len(doc, path, "conversation_history"),
+                apps: doc_paths::len(doc, path, "apps"),
+                name,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LspAgent;
+    use automerge::AutoCommit;
+    use autosurgeon::reconcile;
+
+    fn doc() -> AutoCommit {
+        let mut doc = AutoCommit::new();
+        reconcile(&mut doc, LspAgent::default()).unwrap();
+        doc
+    }
+
+    #[test]
+    fn test_default_session_is_implicit() {
+        let doc = doc();
+        assert_eq!(active_name(&doc), DEFAULT_SESSION);
+        assert_eq!(resolve_name(&doc, None), DEFAULT_SESSION);
+        assert_eq!(resolve_name(&doc, Some("  ")), DEFAULT_SESSION);
+        assert_eq!(resolve_name(&doc, Some("work")), "work");
+
+        let listed = list(&doc);
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, DEFAULT_SESSION);
+        assert!(listed[0].active);
+    }
+
+    #[test]
+    fn test_create_switch_delete() {
+        let mut doc = doc();
+
+        create(&mut doc, "research").unwrap();
+        assert_eq!(active_name(&doc), "research");
+        assert!(create(&mut doc, "research").is_err());
+        assert!(create(&mut doc, " ").is_err());
+
+        switch(&mut doc, DEFAULT_SESSION).unwrap();
+        assert_eq!(active_name(&doc), DEFAULT_SESSION);
+        assert!(switch(&mut doc, "missing").is_err());
+
+        switch(&mut doc, "research").unwrap();
+        delete(&mut doc, "research").unwrap();
+        assert_eq!(active_name(&doc), DEFAULT_SESSION);
+        assert!(delete(&mut doc, "research").is_err());
+        assert!(delete(&mut doc, DEFAULT_SESSION).is_err());
+    }
+
+    #[test]
+    fn test_sessions_keep_separate_history_and_apps() {
+        let mut doc = doc();
+        extend_history(
+            &mut doc,
+            "a",
+            [ConversationFragment::User("hello a".to_string())],
+        );
+        add_app(&mut doc, "b", "app-1");
+
+        assert_eq!(history(&doc, "a").len(), 1);
+        assert!(history(&doc, "b").is_empty());
+        assert_eq!(session(&doc, "b").apps, vec!["app-1".to_string()]);
+        assert_eq!(owner_of(&doc, "app-1"), Some("b".to_string()));
+        assert_eq!(owner_of(&doc, "app-2"), None);
+
+        let listed = list(&doc);
+        let names: Vec<&str> = listed.iter().map(|info| info.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b", DEFAULT_SESSION]);
+        assert_eq!(listed[0].history_len, 1);
+        assert_eq!(listed[1].apps, 1);
+    }
+}
@@ -0,0 +1,301 @@
+use crate::doc_paths::{self, Writable};
+use crate::{EditPosition, Selection, TextDocument};
+use automerge::transaction::Transactable;
+use automerge::{ObjId, ObjType, ROOT, ReadDoc};
+use serde::Serialize;
+use traits::{TextChange, TextPosition};
+
+/// Counts lines the way editors do: an empty document has one line, and a
+/// trailing line break starts another.
+pub(crate) fn line_count(text: &str) -> u64 {
+    text.split('\n').count() as u64
+}
+
+/// A freshly opened document, which is clean until it is changed.
+pub(crate) fn opened(uri: &str, language_id: String, version: i32, text: String) -> TextDocument {
+    TextDocument {
+        language_id,
+        version,
+        line_count: line_count(&text),
+        byte_size: text.len() as u64,
+        dirty: false,
+        untitled: uri.starts_with("untitled:"),
+        text: text.into(),
+    }
+}
+
+/// Updates the metadata of the document at `uri` after a change left it with `text`.
+pub(crate) fn record_change<D: Writable>(doc: &mut D, uri: &str, version: i32, text: &str) {
+    let path = &["text_documents", "documents", uri];
+    doc_paths::put(doc, path, "version", version);
+    doc_paths::put(doc, path, "line_count", line_count(text));
+    doc_paths::put(doc, path, "byte_size", text.len() as u64);
+    doc_paths::put(doc, path, "dirty", true);
+}
+
+/// Finds the Automerge text object holding the text of an open document.
+pub(crate) fn text_object<D: ReadDoc>(doc: &D, uri: &str) -> Option<ObjId> {
+    let (_, manager) = doc.get(ROOT, "text_documents").ok()??;
+    let (_, documents) = doc.get(&manager, "documents").ok()??;
+    let (_, document) = doc.get(&documents, uri).ok()??;
+    match doc.get(&document, "text").ok()?? {
+        (automerge::Value::Object(ObjType::Text), text) => Some(text),
+        _ => None,
+    }
+}
+
+/// Converts an LSP position into a code point offset into `text`, which is how
+/// Automerge indexes text. Positions past the end of a line or of the document
+/// are clamped, as the LSP specification asks.
+pub(crate) fn char_offset(text: &str, position: TextPosition) -> usize {
+    let mut offset = 0;
+    let mut lines = text.split_inclusive('\n');
+    for _ in 0..position.line {
+        match lines.next() {
+            Some(line) => offset += line.chars().count(),
+            None => return offset,
+        }
+    }
+    let Some(line) = lines.next() else {
+        return offset;
+    };
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+
+    let mut units = 0;
+    for c in line.chars() {
+        if units >= position.character as usize {
+            break;
+        }
+        units += c.len_utf16();
+        offset += 1;
+    }
+    offset
+}
+
+fn byte_offset(text: &str, chars: usize) -> usize {
+    text.char_indices()
+        .nth(chars)
+        .map(|(index, _)| index)
+        .unwrap_or(text.len())
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct SelectedText {
+    pub start: EditPosition,
+    pub end: EditPosition,
+    pub text: String,
+}
+
+/// What an app sees of an editor's selections: the cursor and the selected text.
+#[derive(Debug, Serialize, PartialEq)]
+pub(crate) struct SelectionView {
+    pub uri: String,
+    pub cursor: Option<EditPosition>,
+    pub selections: Vec<SelectedText>,
+}
+
+/// Describes the `selections` made in the document `uri` whose content is `text`.
+pub(crate) fn selection_view(uri: &str, text: &str, selections: &[Selection]) -> SelectionView {
+    let offset = |position: EditPosition| {
+        let chars = char_offset(
+            text,
+            TextPosition {
+                line: position.line,
+                character: position.character,
+            },
+        );
+        byte_offset(text, chars)
+    };
+    SelectionView {
+        uri: uri.to_string(),
+        cursor: selections.first().map(|selection| selection.active),
+        selections: selections
+            .iter()
+            .map(|selection| {
+                let start = selection.anchor.min(selection.active);
+                let end = selection.anchor.max(selection.active);
+                SelectedText {
+                    start,
+                    end,
+                    text: text[offset(start)..offset(end)].to_string(),
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Splices `changes` into the text object `obj`, in order. `current` must hold the
+/// object's text before the call and is kept in sync, since each change's range
+/// refers to the text produced by the previous one.
+pub(crate) fn apply_changes<T: Transactable>(
+    tx: &mut T,
+    obj: &ObjId,
+    current: &mut String,
+    changes: &[TextChange],
+) -> Result<(), automerge::AutomergeError> {
+    for change in changes {
+        let (start, end) = match change.range {
+            Some(range) => {
+                let start = char_offset(current, range.start);
+                (start, char_offset(current, range.end).max(start))
+            }
+            None => (0, current.chars().count()),
+        };
+        tx.splice_text(obj, start, (end - start) as isize, &change.text)?;
+        let (start_byte, end_byte) = (byte_offset(current, start), byte_offset(current, end));
+        current.replace_range(start_byte..end_byte, &change.text);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::LspAgent;
+    use automerge::AutoCommit;
+    use autosurgeon::{hydrate, reconcile};
+    use traits::TextRange;
+
+    fn pos(line: u32, character: u32) -> TextPosition {
+        TextPosition { line, character }
+    }
+
+    fn edit(start: TextPosition, end: TextPosition, text: &str) -> TextChange {
+        TextChange {
+            range: Some(TextRange { start, end }),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_char_offset() {
+        let text = "ab\r\ncd\n";
+        assert_eq!(char_offset(text, pos(0, 0)), 0);
+        assert_eq!(char_offset(text, pos(0, 2)), 2);
+        // Clamped to the end of the line, before the line break.
+        assert_eq!(char_offset(text, pos(0, 10)), 2);
+        assert_eq!(char_offset(text, pos(1, 1)), 5);
+        assert_eq!(char_offset(text, pos(2, 0)), 7);
+        assert_eq!(char_offset(text, pos(9, 0)), 7);
+    }
+
+    #[test]
+    fn test_char_offset_counts_utf16_units() {
+        // The emoji is two UTF-16 code units but a single code point.
+        let text = "a😀b";
+        assert_eq!(char_offset(text, pos(0, 1)), 1);
+        assert_eq!(char_offset(text, pos(0, 3)), 2);
+        assert_eq!(char_offset(text, pos(0, 4)), 3);
+    }
+
+    fn open_document(text: &str) -> AutoCommit {
+        let mut agent = LspAgent::default();
+        agent.text_documents.documents.insert(
+            "file:///a.rs".to_string(),
+            opened("file:///a.rs", "rust".to_string(), 1, text.to_string()),
+        );
+        let mut doc = AutoCommit::new();
+        reconcile(&mut doc, &agent).unwrap();
+        doc
+    }
+
+    fn document_text(doc: &AutoCommit) -> String {
+        let agent: LspAgent = hydrate(doc).unwrap();
+        agent.text_documents.documents["file:///a.rs"]
+            .text
+            .as_str()
+            .to_string()
+    }
+
+    #[test]
+    fn test_apply_changes_in_place() {
+        let mut doc = open_document("fn main() {\n    let ö = 1;\n}\n");
+        let obj = text_object(&doc, "file:///a.rs").unwrap();
+        let mut current = doc.text(&obj).unwrap();
+
+        let changes = vec![
+            edit(pos(1, 8), pos(1, 9), "x"),
+            edit(pos(1, 13), pos(1, 13), "0"),
+            edit(pos(2, 1), pos(2, 1), "\n// end"),
+        ];
+        apply_changes(&mut doc, &obj, &mut current, &changes).unwrap();
+
+        let expected = "fn main() {\n    let x = 10;\n}\n// end\n";
+        assert_eq!(current, expected);
+        assert_eq!(document_text(&doc), expected);
+        // Still the same text object, edited in place.
+        assert_eq!(text_object(&doc, "file:///a.rs"), Some(obj));
+    }
+
+    #[test]
+    fn test_apply_full_replacement() {
+        let mut doc = open_document("old");
+        let obj = text_object(&doc, "file:///a.rs").unwrap();
+        let mut current = doc.text(&obj).unwrap();
+
+        let changes = vec![TextChange {
+            range: None,
+            text: "new text".to_string(),
+        }];
+        apply_changes(&mut doc, &obj, &mut current, &changes).unwrap();
+
+        assert_eq!(document_text(&doc), "new text");
+        assert!(text_object(&doc, "file:///missing.rs").is_none());
+    }
+
+    #[test]
+    fn test_line_count() {
+        assert_eq!(line_count(""), 1);
+        assert_eq!(line_count("a"), 1);
+        assert_eq!(line_count("a\nb"), 2);
+        assert_eq!(line_count("a\r\nb\n"), 3);
+    }
+
+    #[test]
+    fn test_record_change_updates_metadata() {
+        let mut doc = open_document("fn main() {}\n");
+        let obj = text_object(&doc, "file:///a.rs").unwrap();
+        let mut current = doc.text(&obj).unwrap();
+        apply_changes(
+            &mut doc,
+            &obj,
+            &mut current,
+            &[edit(pos(1, 0), pos(1, 0), "// ö\n")],
+        )
+        .unwrap();
+        record_change(&mut doc, "file:///a.rs", 2, &current);
+
+        let agent: LspAgent = hydrate(&doc).unwrap();
+        let document = &agent.text_documents.documents["file:///a.rs"];
+        assert_eq!(document.version, 2);
+        assert_eq!(document.line_count, 3);
+        assert_eq!(document.byte_size, current.len() as u64);
+        assert!(document.dirty);
+        assert!(!document.untitled);
+        assert_eq!(document.language_id, "rust");
+    }
+
+    #[test]
+    fn test_selection_view() {
+        let at = |line, character| EditPosition { line, character };
+        let selections = [
+            // Selected backwards, from the end of "main" to its start.
+            Selection {
+                anchor: at(0, 7),
+                active: at(0, 3),
+            },
+            Selection {
+                anchor: at(1, 4),
+                active: at(2, 1),
+            },
+        ];
+
+        let view = selection_view("file:///a.rs", "fn main() {\n    ö();\n}\n", &selections);
+        assert_eq!(view.cursor, Some(at(0, 3)));
+        assert_eq!(view.selections[0].text, "main");
+        assert_eq!(view.selections[0].start, at(0, 3));
+        assert_eq!(view.selections[1].text, "ö();\n}");
+        assert_eq!(selection_view("file:///a.rs", "", &[]).cursor, None);
+    }
+}
@@ -0,0 +1,399 @@
+//! Read-only access to workspace files that the user chose to expose to apps.
+//!
+//! Nothing is exposed by default. The user lists glob patterns (matched against
+//! paths relative to a workspace folder) and only matching files can be read, or
+//! show up in directory listings. Paths are canonicalized before matching, so `..`
+//! segments and symlinks cannot reach outside the workspace folders.
+
+use crate::limits;
+use crate::prompts::WorkspaceFileInfo;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use reqwest::Url;
+use serde::Serialize;
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_MAX_FILE_BYTES: usize = 1_000_000;
+
+/// Upper bound on the entries looked at when searching a directory tree for
+/// exposed files, so a huge workspace cannot stall a request.
+const MAX_WALK_ENTRIES: usize = 20_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct AccessError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl AccessError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+        }
+    }
+
+    fn not_exposed(path: &str) -> Self {
+        Self::new(403, format!("'{}' is not exposed to apps.", path))
+    }
+}
+
+/// Rules that could not be built (e.g. an invalid pattern) are a server-side problem.
+impl From<String> for AccessError {
+    fn from(message: String) -> Self {
+        Self::new(500, message)
+    }
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum EntryKind {
+    File,
+    Directory,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct DirectoryEntry {
+    pub name: String,
+    pub kind: EntryKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<u64>,
+}
+
+pub(crate) struct WorkspaceAccess {
+    roots: Vec<PathBuf>,
+    allowed: GlobSet,
+}
+
+impl WorkspaceAccess {
+    /// Builds the access rules from the workspace folders and the user's patterns.
+    /// Folders that do not exist are ignored.
+    pub fn new(folders: &[String], patterns: &[String]) -> Result<Self, String> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            let glob = GlobBuilder::new(pattern.trim())
+                .literal_separator(true)
+                .build()
+                .map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))?;
+            builder.add(glob);
+        }
+        let allowed = builder.build().map_err(|e| e.to_string())?;
+        let roots = folders
+            .iter()
+            .filter_map(|folder| Path::new(folder).canonicalize().ok())
+            .collect();
+        Ok(Self { roots, allowed })
+    }
+
+    fn is_enabled(&self) -> bool {
+        !self.roots.is_empty() && !self.allowed.is_empty()
+    }
+
+    fn is_allowed(&self, relative: &Path) -> bool {
+        self.allowed.is_match(relative)
+    }
+
+    /// Resolves a workspace-relative path, an absolute path or a `file://` URI to
+    /// its canonical location and the workspace-relative path used for matching.
+    fn resolve(&self, path: &str) -> Result<(PathBuf, PathBuf), AccessError> {
+        if !self.is_enabled() {
+            return Err(AccessError::new(
+                403,
+                "No workspace files are exposed to apps.",
+            ));
+        }
+        let trimmed = path.trim();
+        let requested = match Url::parse(trimmed) {
+            Ok(url) if url.scheme() == "file" => url
+                .to_file_path()
+                .map_err(|_| AccessError::new(400, format!("'{}' is not a file path.", path)))?,
+            _ => PathBuf::from(trimmed.trim_start_matches("./")),
+        };
+
+        let candidates: Vec<PathBuf> = if requested.is_absolute() {
+            vec![requested]
+        } else {
+            self.roots
+                .iter()
+                .map(|root| root.join(&requested))
+                .collect()
+        };
+        for candidate in candidates {
+            let Ok(canonical) = candidate.canonicalize() else {
+                continue;
+            };
+            let Some(root) = self.roots.iter().find(|root| canonical.starts_with(root)) else {
+                return Err(AccessError::not_exposed(path));
+            };
+            let relative = canonical.strip_prefix(root).unwrap().to_path_buf();
+            return Ok((canonical, relative));
+        }
+        Err(AccessError::new(404, format!("'{}' does not exist.", path)))
+    }
+
+    /// Reads an exposed file. Invalid UTF-8 is replaced rather than rejected.
+    pub fn read_file(&self, path: &str) -> Result<String, AccessError> {
+        let (canonical, relative) = self.resolve(path)?;
+        if !canonical.is_file() || !self.is_allowed(&relative) {
+            return Err(AccessError::not_exposed(path));
+        }
+        let max_bytes = limits::env_limit("LSP_AGENT_MAX_FILE_BYTES", DEFAULT_MAX_FILE_BYTES);
+        let read_error =
+            |e: std::io::Error| AccessError::new(500, format!("Failed to read '{}': {}", path, e));
+        let too_large = || {
+            AccessError::new(
+                413,
+                format!("'{}' is larger than {} bytes.", path, max_bytes),
+            )
+        };
+        let file = std::fs::File::open(&canonical).map_err(read_error)?;
+        // Checked before reading, and the read is capped too in case the file grew.
+        let limit = match max_bytes {
+            0 => u64::MAX,
+            max => max as u64,
+        };
+        if file.metadata().map_err(read_error)?.len() > limit {
+            return Err(too_large());
+        }
+        let mut bytes = Vec::new();
+        file.take(limit.saturating_add(1))
+            .read_to_end(&mut bytes)
+            .map_err(read_error)?;
+        if bytes.len() as u64 > limit {
+            return Err(too_large());
+        }
+        Ok(String::from_utf8_lossy(&bytes).into_owned())
+    }
+
+    /// Lists the exposed files directly in a directory, and the subdirectories that
+    /// contain exposed files. An empty path lists the first workspace folder.
+    pub fn list_directory(&self, path: &str) -> Result<Vec<DirectoryEntry>, AccessError> {
+        let (canonical, relative) = if path.trim().is_empty() {
+            self.resolve(".")?
+        } else {
+            self.resolve(path)?
+        };
+        if !canonical.is_dir() {
+            return Err(AccessError::new(
+                400,
+                format!("'{}' is not a directory.", path),
+            ));
+        }
+        // One walk of the tree finds the exposed files; subdirectories are listed
+        // when any of them lies below.
+        let exposed = self.walk(&canonical, &relative, usize::MAX);
+        if !relative.as_os_str().is_empty() && exposed.is_empty() {
+            return Err(AccessError::not_exposed(path));
+        }
+
+        let mut entries = Vec::new();
+        let mut directories = BTreeSet::new();
+        for file in exposed {
+            let Ok(inside) = file.strip_prefix(&canonical) else {
+                continue;
+            };
+            let mut components = inside.components();
+            let Some(name) = components.next().and_then(|c| c.as_os_str().to_str()) else {
+                continue;
+            };
+            if components.next().is_some() {
+                directories.insert(name.to_string());
+            } else if let Ok(metadata) = file.symlink_metadata() {
+                entries.push(DirectoryEntry {
+                    name: name.to_string(),
+                    kind: EntryKind::File,
+                    bytes: Some(metadata.len()),
+                });
+            }
+        }
+        entries.extend(directories.into_iter().map(|name| DirectoryEntry {
+            name,
+            kind: EntryKind::Directory,
+            bytes: None,
+        }));
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    /// Returns up to `limit` exposed files across all workspace folders, shallowest first.
+    pub fn files(&self, limit: usize) -> Vec<WorkspaceFileInfo> {
+        if !self.is_enabled() {
+            return Vec::new();
+        }
+        self.roots
+            .iter()
+            .flat_map(|root| self.walk(root, Path::new(""), limit))
+            .take(limit)
+            .filter_map(|path| {
+                let bytes = path.metadata().ok()?.len();
+                Some(WorkspaceFileInfo {
+                    uri: Url::from_file_path(&path).ok()?.to_string(),
+                    bytes,
+                })
+            })
+            .collect()
+    }
+
+    /// Breadth-first search for exposed files under `dir`.
+    fn walk(&self, dir: &Path, relative: &Path, limit: usize) -> Vec<PathBuf> {
+        let mut found = Vec::new();
+        let mut visited = 0;
+        let mut queue = VecDeque::from([(dir.to_path_buf(), relative.to_path_buf())]);
+        while let Some((dir, relative)) = queue.pop_front() {
+            for child in sorted_children(&dir) {
+                visited += 1;
+                if visited > MAX_WALK_ENTRIES {
+                    return found;
+                }
+                let Some(name) = child.file_name() else {
+                    continue;
+                };
+                let child_relative = relative.join(name);
+                // Symlinks are not followed, so the walk stays inside the folder.
+                let Ok(file_type) = child.symlink_metadata().map(|m| m.file_type()) else {
+                    continue;
+                };
+                if file_type.is_dir() {
+                    queue.push_back((child, child_relative));
+                } else if file_type.is_file() && self.is_allowed(&child_relative) {
+                    found.push(child);
+                    if found.len() >= limit {
+                        return found;
+                    }
+                }
+            }
+        }
+        found
+    }
+}
+
+fn sorted_children(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut children: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+    children.sort();
+    children
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src/nested")).unwrap();
+        std::fs::create_dir_all(dir.path().join("secrets")).unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(dir.path().join("src/nested/lib.rs"), "pub fn f() {}").unwrap();
+        std::fs::write(dir.path().join("src/notes.txt"), "notes").unwrap();
+        std::fs::write(dir.path().join("secrets/key.rs"), "secret").unwrap();
+        std::fs::write(dir.path().join("README.md"), "readme").unwrap();
+        dir
+    }
+
+    fn access(dir: &tempfile::TempDir, patterns: &[&str]) -> WorkspaceAccess {
+        let folders = vec![dir.path().to_str().unwrap().to_string()];
+        let patterns: Vec<String> = patterns.iter().map(|p| p.to_string()).collect();
+        WorkspaceAccess::new(&folders, &patterns).unwrap()
+    }
+
+    #[test]
+    fn test_read_file_respects_allowlist() {
+        let dir = workspace();
+        let access = access(&dir, &["src/**/*.rs"]);
+
+        assert_eq!(access.read_file("src/main.rs").unwrap(), "fn main() {}");
+        assert_eq!(
+            access.read_file("src/nested/lib.rs").unwrap(),
+            "pub fn f() {}"
+        );
+        let uri = Url::from_file_path(dir.path().join("src/main.rs")).unwrap();
+        assert!(access.read_file(uri.as_str()).is_ok());
+
+        assert_eq!(access.read_file("src/notes.txt").unwrap_err().status, 403);
+        assert_eq!(access.read_file("secrets/key.rs").unwrap_err().status, 403);
+        assert_eq!(
+            access
+                .read_file("src/../secrets/key.rs")
+                .unwrap_err()
+                .status,
+            403
+        );
+        assert_eq!(access.read_file("src/missing.rs").unwrap_err().status, 404);
+        assert_eq!(access.read_file("/etc/hostname").unwrap_err().status, 403);
+    }
+
+    #[test]
+    fn test_nothing_exposed_by_default() {
+        let dir = workspace();
+        let access = access(&dir, &[]);
+
+        assert_eq!(access.read_file("README.md").unwrap_err().status, 403);
+        assert_eq!(access.list_directory("").unwrap_err().status, 403);
+        assert!(access.files(10).is_empty());
+    }
+
+    #[test]
+    fn test_list_directory_hides_unexposed_entries() {
+        let dir = workspace();
+        let access = access(&dir, &["src/**/*.rs", "*.md"]);
+
+        let names = |entries: Vec<DirectoryEntry>| -> Vec<(String, EntryKind)> {
+            entries.into_iter().map(|e| (e.name, e.kind)).collect()
+        };
+        assert_eq!(
+            names(access.list_directory("").unwrap()),
+            vec![
+                ("README.md".to_string(), EntryKind::File),
+                ("src".to_string(), EntryKind::Directory),
+            ]
+        );
+        assert_eq!(
+            names(access.list_directory("src").unwrap()),
+            vec![
+                ("main.rs".to_string(), EntryKind::File),
+                ("nested".to_string(), EntryKind::Directory),
+            ]
+        );
+        assert_eq!(access.list_directory("secrets").unwrap_err().status, 403);
+    }
+
+    #[test]
+    fn test_read_file_rejects_large_files() {
+        let dir = workspace();
+        std::fs::write(dir.path().join("src/big.rs"), "x".repeat(2_000_000)).unwrap();
+        let access = access(&dir, &["src/**/*.rs"]);
+
+        assert_eq!(access.read_file("src/big.rs").unwrap_err().status, 413);
+    }
+
+    #[test]
+    fn test_files_lists_uris_and_sizes() {
+        let dir = workspace();
+        let access = access(&dir, &["src/**/*.rs"]);
+
+        let files = access.files(10);
+        assert_eq!(files.len(), 2);
+        assert!(files[0].uri.starts_with("file://"));
+        assert!(files[0].uri.ends_with("src/main.rs"));
+        assert_eq!(files[0].bytes, "fn main() {}".len() as u64);
+        assert_eq!(access.files(1).len(), 1);
+    }
+
+    #[test]
+    fn test_invalid_pattern() {
+        assert!(WorkspaceAccess::new(&[], &["src/[".to_string()]).is_err());
+    }
+}
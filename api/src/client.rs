@@ -0,0 +1,84 @@
+use crate::events::Event;
+use agent::http_inference::HttpInference;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::sync::{broadcast, oneshot};
+use tokio::time::Duration;
+use traits::{Diagnostic, EditProposal, InferenceClient};
+
+/// How long an edit proposal waits for a client's decision before it counts as declined.
+const PROPOSAL_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Edit proposals streamed to clients and waiting for a decision, by proposal id.
+#[derive(Default)]
+pub struct Proposals {
+    pending: Mutex<HashMap<String, oneshot::Sender<bool>>>,
+}
+
+impl Proposals {
+    /// Resolves a pending proposal; `false` if there is none with this id.
+    pub fn decide(&self, proposal_id: &str, accepted: bool) -> bool {
+        match self.pending.lock().unwrap().remove(proposal_id) {
+            Some(responder) => responder.send(accepted).is_ok(),
+            None => false,
+        }
+    }
+}
+
+/// Runs inference over HTTP and streams everything else to WebSocket clients.
+pub struct ApiClient {
+    pub inference: Option<HttpInference>,
+    pub events: broadcast::Sender<Event>,
+    pub proposals: std::sync::Arc<Proposals>,
+}
+
+#[async_trait::async_trait]
+impl InferenceClient for ApiClient {
+    async fn inference(&self, request: String, model: Option<String>) -> Result<String, String> {
+        match &self.inference {
+            Some(inference) => inference.complete(request, model).await,
+            None => Err("Set LSP_AGENT_INFERENCE_URL to run inference from the API.".to_string()),
+        }
+    }
+
+    async fn notify_shutdown(&self) {}
+
+    async fn show_message(&self, message: String) {
+        let _ = self.events.send(Event::Message { message });
+    }
+
+    /// Streams the proposal and waits for a client to decide. Nothing is written
+    /// here: clients own the files and apply accepted edits themselves.
+    async fn apply_edit(&self, proposal: EditProposal) -> Result<bool, String> {
+        let proposal_id = uuid::Uuid::new_v4().to_string();
+        let (responder, decision) = oneshot::channel();
+        self.proposals
+            .pending
+            .lock()
+            .unwrap()
+            .insert(proposal_id.clone(), responder);
+        let event = Event::EditProposal {
+            proposal_id: proposal_id.clone(),
+            uri: proposal.uri,
+            label: proposal.label,
+            edits: proposal.edits.into_iter().map(Into::into).collect(),
+        };
+        if self.events.send(event).is_err() {
+            self.proposals.pending.lock().unwrap().remove(&proposal_id);
+            return Err("No API client is connected to review the edit.".to_string());
+        }
+        let accepted = tokio::time::timeout(PROPOSAL_TIMEOUT, decision).await;
+        self.proposals.pending.lock().unwrap().remove(&proposal_id);
+        Ok(matches!(accepted, Ok(Ok(true))))
+    }
+
+    async fn publish_diagnostics(&self, uri: String, diagnostics: Vec<Diagnostic>) {
+        let _ = self.events.send(Event::Diagnostics {
+            uri,
+            diagnostics: diagnostics.into_iter().map(Into::into).collect(),
+        });
+    }
+
+    // Code lenses are an editor feature with nothing to refresh over the API.
+    async fn refresh_code_lenses(&self) {}
+}
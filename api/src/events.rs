@@ -0,0 +1,182 @@
+use serde::{Deserialize, Serialize};
+use traits::{AppInfo, Diagnostic, DiagnosticSeverity, TextEdit, TextPosition, TextRange};
+
+/// A position as LSP sends it: zero-based line and UTF-16 offset within the line.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl From<TextPosition> for Position {
+    fn from(position: TextPosition) -> Self {
+        Position {
+            line: position.line,
+            character: position.character,
+        }
+    }
+}
+
+impl From<Position> for TextPosition {
+    fn from(position: Position) -> Self {
+        TextPosition {
+            line: position.line,
+            character: position.character,
+        }
+    }
+}
+
+impl From<TextRange> for Range {
+    fn from(range: TextRange) -> Self {
+        Range {
+            start: range.start.into(),
+            end: range.end.into(),
+        }
+    }
+}
+
+impl From<Range> for TextRange {
+    fn from(range: Range) -> Self {
+        TextRange {
+            start: range.start.into(),
+            end: range.end.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Edit {
+    pub range: Range,
+    pub text: String,
+}
+
+impl From<TextEdit> for Edit {
+    fn from(edit: TextEdit) -> Self {
+        Edit {
+            range: edit.range.into(),
+            text: edit.text,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DiagnosticEvent {
+    pub range: Range,
+    pub severity: &'static str,
+    pub message: String,
+    pub source: String,
+}
+
+impl From<Diagnostic> for DiagnosticEvent {
+    fn from(diagnostic: Diagnostic) -> Self {
+        DiagnosticEvent {
+            range: diagnostic.range.into(),
+            severity: match diagnostic.severity {
+                DiagnosticSeverity::Error => "error",
+                DiagnosticSeverity::Warning => "warning",
+                DiagnosticSeverity::Information => "information",
+                DiagnosticSeverity::Hint => "hint",
+            },
+            message: diagnostic.message,
+            source: diagnostic.source,
+        }
+    }
+}
+
+/// What is streamed to WebSocket clients, as JSON objects tagged by `type`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    ChatReply {
+        message: String,
+        reply: Option<String>,
+    },
+    AppLaunched {
+        app_id: String,
+        title: String,
+    },
+    AppClosed {
+        app_id: String,
+    },
+    Message {
+        message: String,
+    },
+    /// An app's edit, applied only once a client accepts it through
+    /// `POST /proposals/{proposal_id}` or a `decide` message.
+    EditProposal {
+        proposal_id: String,
+        uri: String,
+        label: String,
+        edits: Vec<Edit>,
+    },
+    Diagnostics {
+        uri: String,
+        diagnostics: Vec<DiagnosticEvent>,
+    },
+}
+
+/// Launch and close events between two `list_apps` snapshots.
+pub fn app_events(before: &[AppInfo], after: &[AppInfo]) -> Vec<Event> {
+    let closed = before
+        .iter()
+        .filter(|app| !after.iter().any(|other| other.id == app.id))
+        .map(|app| Event::AppClosed {
+            app_id: app.id.clone(),
+        });
+    let launched = after
+        .iter()
+        .filter(|app| !before.iter().any(|other| other.id == app.id))
+        .map(|app| Event::AppLaunched {
+            app_id: app.id.clone(),
+            title: app.title.clone(),
+        });
+    closed.chain(launched).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(id: &str) -> AppInfo {
+        AppInfo {
+            id: id.to_string(),
+            title: format!("{} title", id),
+            errors: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_app_events() {
+        assert_eq!(
+            app_events(&[app("a"), app("b")], &[app("b"), app("c")]),
+            vec![
+                Event::AppClosed {
+                    app_id: "a".to_string()
+                },
+                Event::AppLaunched {
+                    app_id: "c".to_string(),
+                    title: "c title".to_string()
+                },
+            ]
+        );
+        assert!(app_events(&[app("a")], &[app("a")]).is_empty());
+    }
+
+    #[test]
+    fn test_event_json() {
+        let event = Event::AppLaunched {
+            app_id: "app-1".to_string(),
+            title: "Todo".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({"type": "app_launched", "app_id": "app-1", "title": "Todo"})
+        );
+    }
+}
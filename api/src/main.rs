@@ -0,0 +1,110 @@
+mod client;
+mod events;
+mod routes;
+
+use agent::http_inference::HttpInference;
+use agent::start_infra;
+use clap::Parser;
+use client::{ApiClient, Proposals};
+use routes::ApiState;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::time::Duration;
+use traits::WorkspaceAgent;
+
+/// How often running apps are listed to stream launch and close events.
+const APPS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Serve the agent over a local HTTP and WebSocket API, for scripts and tools
+/// that drive it without an editor.
+#[derive(Parser)]
+struct Args {
+    /// Port to listen on; the server only binds to 127.0.0.1.
+    #[arg(long, default_value_t = 2350)]
+    port: u16,
+    /// Workspace folder for saved apps and exposed files; the current directory by default.
+    #[arg(long)]
+    workspace: Option<PathBuf>,
+    /// Glob pattern of workspace files apps may read, like `lspAgent.exposedFiles`. Repeatable.
+    #[arg(long = "expose")]
+    exposed_files: Vec<String>,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    let inference = HttpInference::from_env();
+    if inference.is_none() {
+        eprintln!("LSP_AGENT_INFERENCE_URL is not set; chat turns will fail until it is.");
+    }
+    let token = match std::env::var("LSP_AGENT_API_TOKEN") {
+        Ok(token) if !token.is_empty() => token,
+        _ => {
+            let token = uuid::Uuid::new_v4().to_string();
+            eprintln!("LSP_AGENT_API_TOKEN is not set; using token {}", token);
+            token
+        }
+    };
+
+    let (events, _) = broadcast::channel(64);
+    let proposals = Arc::new(Proposals::default());
+    let agent: Arc<dyn WorkspaceAgent> = Arc::from(start_infra(Arc::new(ApiClient {
+        inference,
+        events: events.clone(),
+        proposals: proposals.clone(),
+    })));
+
+    let workspace = args
+        .workspace
+        .unwrap_or_else(|| PathBuf::from("."))
+        .canonicalize()
+        .expect("Failed to resolve the workspace folder");
+    agent
+        .set_workspace_folders(vec![workspace.display().to_string()])
+        .await;
+    if !args.exposed_files.is_empty()
+        && let Err(e) = agent.set_exposed_files(args.exposed_files).await
+    {
+        eprintln!("--expose: {}", e);
+    }
+
+    let apps = {
+        let agent = agent.clone();
+        let events = events.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(APPS_INTERVAL);
+            let mut running = Vec::new();
+            loop {
+                interval.tick().await;
+                let now = agent.list_apps().await;
+                for event in events::app_events(&running, &now) {
+                    let _ = events.send(event);
+                }
+                running = now;
+            }
+        })
+    };
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", args.port))
+        .await
+        .expect("Failed to bind the API port");
+    eprintln!("Listening on http://127.0.0.1:{}", args.port);
+    let app = routes::router(ApiState {
+        agent: agent.clone(),
+        events,
+        proposals,
+        token: token.into(),
+    });
+    let served = axum::serve(listener, app)
+        .with_graceful_shutdown(async {
+            let _ = tokio::signal::ctrl_c().await;
+        })
+        .await;
+    if let Err(e) = served {
+        eprintln!("API server error: {}", e);
+    }
+
+    apps.abort();
+    agent.shutdown().await;
+}
@@ -0,0 +1,241 @@
+use crate::client::Proposals;
+use crate::events::{Event, Range};
+use axum::Json;
+use axum::Router;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use traits::{TextChange, WorkspaceAgent};
+
+#[derive(Clone)]
+pub struct ApiState {
+    pub agent: Arc<dyn WorkspaceAgent>,
+    pub events: broadcast::Sender<Event>,
+    pub proposals: Arc<Proposals>,
+    pub token: Arc<str>,
+}
+
+#[derive(Deserialize)]
+struct OpenDocument {
+    uri: String,
+    language_id: String,
+    version: i32,
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct Change {
+    range: Option<Range>,
+    text: String,
+}
+
+#[derive(Deserialize)]
+struct ChangeDocument {
+    uri: String,
+    version: i32,
+    changes: Vec<Change>,
+}
+
+#[derive(Deserialize)]
+struct DocumentUri {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+struct Chat {
+    message: String,
+    session: Option<String>,
+    model: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Decision {
+    accepted: bool,
+}
+
+/// Messages a WebSocket client may send; others are answered with a `message` event.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    Chat {
+        message: String,
+        session: Option<String>,
+        model: Option<String>,
+    },
+    Decide {
+        proposal_id: String,
+        accepted: bool,
+    },
+}
+
+/// Whether a request carries the API token, as a bearer `Authorization` header
+/// or, for WebSocket clients that cannot set headers, a `token` query parameter.
+pub fn authorized(authorization: Option<&str>, query_token: Option<&str>, token: &str) -> bool {
+    let bearer = authorization.and_then(|value| value.strip_prefix("Bearer "));
+    bearer.or(query_token) == Some(token)
+}
+
+async fn require_token(
+    State(state): State<ApiState>,
+    Query(query): Query<HashMap<String, String>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if authorized(
+        authorization,
+        query.get("token").map(String::as_str),
+        &state.token,
+    ) {
+        next.run(request).await
+    } else {
+        StatusCode::UNAUTHORIZED.into_response()
+    }
+}
+
+/// Runs a chat turn and streams the reply to WebSocket clients as well.
+async fn chat_turn(state: &ApiState, chat: Chat) -> Option<String> {
+    let reply = state
+        .agent
+        .chat_request(chat.session, chat.message.clone(), chat.model, None)
+        .await;
+    let _ = state.events.send(Event::ChatReply {
+        message: chat.message,
+        reply: reply.clone(),
+    });
+    reply
+}
+
+async fn open_document(State(state): State<ApiState>, Json(body): Json<OpenDocument>) {
+    state
+        .agent
+        .did_open(body.uri, body.language_id, body.version, body.text)
+        .await;
+}
+
+async fn change_document(State(state): State<ApiState>, Json(body): Json<ChangeDocument>) {
+    let changes = body
+        .changes
+        .into_iter()
+        .map(|change| TextChange {
+            range: change.range.map(Into::into),
+            text: change.text,
+        })
+        .collect();
+    state
+        .agent
+        .did_change(body.uri, body.version, changes)
+        .await;
+}
+
+async fn close_document(State(state): State<ApiState>, Json(body): Json<DocumentUri>) {
+    state.agent.did_close(body.uri).await;
+}
+
+async fn set_active_document(State(state): State<ApiState>, Json(body): Json<DocumentUri>) {
+    state.agent.set_active_document(body.uri).await;
+}
+
+async fn chat(State(state): State<ApiState>, Json(body): Json<Chat>) -> Response {
+    match chat_turn(&state, body).await {
+        Some(reply) => Json(serde_json::json!({ "reply": reply })).into_response(),
+        None => (StatusCode::BAD_GATEWAY, "The agent did not reply.").into_response(),
+    }
+}
+
+async fn decide(
+    State(state): State<ApiState>,
+    Path(proposal_id): Path<String>,
+    Json(body): Json<Decision>,
+) -> StatusCode {
+    if state.proposals.decide(&proposal_id, body.accepted) {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+async fn websocket(State(state): State<ApiState>, upgrade: WebSocketUpgrade) -> Response {
+    upgrade.on_upgrade(move |socket| serve_socket(state, socket))
+}
+
+/// Streams events to the socket while handling the messages it sends. Chat
+/// turns run in their own task so events keep flowing while they are pending.
+async fn serve_socket(state: ApiState, mut socket: WebSocket) {
+    let mut events = state.events.subscribe();
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let text = serde_json::to_string(&event).unwrap();
+                    if socket.send(Message::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                    Ok(ClientMessage::Chat { message, session, model }) => {
+                        let state = state.clone();
+                        tokio::spawn(async move {
+                            chat_turn(&state, Chat { message, session, model }).await;
+                        });
+                    }
+                    Ok(ClientMessage::Decide { proposal_id, accepted }) => {
+                        state.proposals.decide(&proposal_id, accepted);
+                    }
+                    Err(e) => {
+                        let event = Event::Message {
+                            message: format!("Ignored WebSocket message: {}", e),
+                        };
+                        let text = serde_json::to_string(&event).unwrap();
+                        if socket.send(Message::Text(text)).await.is_err() {
+                            break;
+                        }
+                    }
+                },
+                Some(Ok(_)) => {}
+                Some(Err(_)) | None => break,
+            },
+        }
+    }
+}
+
+pub fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/documents/open", post(open_document))
+        .route("/documents/change", post(change_document))
+        .route("/documents/close", post(close_document))
+        .route("/documents/active", post(set_active_document))
+        .route("/chat", post(chat))
+        .route("/proposals/:proposal_id", post(decide))
+        .route("/ws", get(websocket))
+        .layer(middleware::from_fn_with_state(state.clone(), require_token))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authorized() {
+        assert!(authorized(Some("Bearer secret"), None, "secret"));
+        assert!(authorized(None, Some("secret"), "secret"));
+        assert!(!authorized(Some("Bearer other"), None, "secret"));
+        assert!(!authorized(Some("secret"), None, "secret"));
+        assert!(!authorized(None, None, "secret"));
+    }
+}
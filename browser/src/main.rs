@@ -0,0 +1,58 @@
+mod page;
+mod routes;
+mod runtime;
+
+use agent::start_web_backend;
+use routes::BrowserState;
+use runtime::BrowserRuntime;
+use std::sync::Arc;
+
+/// Serves apps to the user's browser from a local HTTP server, in place of the
+/// wry windows of the `web` runtime. The server spawns it when
+/// `LSP_AGENT_WEB_RUNTIME=browser` is set.
+///
+/// - `LSP_AGENT_BROWSER_PORT`: port on 127.0.0.1, 2351 by default.
+/// - `LSP_AGENT_BROWSER_TOKEN`: secret path of the page listing the apps; random
+///   by default. Each app is served under its own secret path.
+/// - `LSP_AGENT_BROWSER_OPEN=0`: only print app URLs instead of opening a browser,
+///   e.g. on a remote machine whose port is forwarded.
+#[tokio::main]
+async fn main() {
+    let port = std::env::var("LSP_AGENT_BROWSER_PORT")
+        .ok()
+        .and_then(|port| port.parse().ok())
+        .unwrap_or(2351u16);
+    let token = std::env::var("LSP_AGENT_BROWSER_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let open_browser = std::env::var("LSP_AGENT_BROWSER_OPEN").as_deref() != Ok("0");
+
+    let listener = match tokio::net::TcpListener::bind(("127.0.0.1", port)).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("[Browser] Failed to bind port {}: {}", port, e);
+            return;
+        }
+    };
+    let origin = format!("http://127.0.0.1:{}/", port);
+    eprintln!("[Browser] Apps are listed at {}{}/", origin, token);
+
+    let runtime = Arc::new(BrowserRuntime::new(origin, open_browser));
+    let (agent, mut exit_rx) = start_web_backend(runtime.clone()).await;
+    let app = routes::router(
+        BrowserState {
+            runtime,
+            agent: Arc::from(agent),
+        },
+        &token,
+    );
+    let served = axum::serve(listener, app)
+        .with_graceful_shutdown(async move {
+            let _ = exit_rx.recv().await;
+        })
+        .await;
+    if let Err(e) = served {
+        eprintln!("[Browser] Server error: {}", e);
+    }
+}
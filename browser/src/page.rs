@@ -0,0 +1,101 @@
+use agent::app_html::clean_html;
+
+/// Loaded before the app's own scripts. It sends `fetch('wry://<protocol>')`
+/// calls to the app's `wry/<protocol>` endpoint, relative to the page, and
+/// turns storage updates streamed from `events` into `doc_changed` events as
+/// the wry runtime dispatches them.
+const SHIM: &str = r#"<script>
+(() => {
+    const fetch = window.fetch.bind(window);
+    window.fetch = (input, init) => {
+        const url = input instanceof Request ? input.url : String(input);
+        if (!url.startsWith('wry://')) {
+            return fetch(input, init);
+        }
+        const local = 'wry/' + url.slice('wry://'.length);
+        return fetch(input instanceof Request ? new Request(local, input) : local, init);
+    };
+    const events = new EventSource('events');
+    events.addEventListener('doc_changed', (event) => {
+        window.dispatchEvent(new CustomEvent('doc_changed', { detail: { key: JSON.parse(event.data) } }));
+    });
+})();
+</script>
+"#;
+
+/// The shim followed by the agent's error reporter, which the wry runtime
+/// injects as an initialization script.
+fn injected() -> String {
+    format!(
+        "{}<script>\n{}</script>\n",
+        SHIM,
+        agent::app_errors::REPORTER_SCRIPT
+    )
+}
+
+/// The app's HTML with the shim and error reporter inserted at the start of
+/// `<head>`, or before everything else when there is no head.
+pub fn with_shim(content: &str) -> String {
+    let html = clean_html(content);
+    let head = html
+        .to_ascii_lowercase()
+        .find("<head")
+        .and_then(|start| html[start..].find('>').map(|end| start + end + 1));
+    match head {
+        Some(at) => format!("{}\n{}{}", &html[..at], injected(), &html[at..]),
+        None => format!("{}{}", injected(), html),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Lists the running apps by key and title, so a single bookmarked tab can
+/// reach all of them.
+pub fn index(apps: &[(String, String)]) -> String {
+    let items: String = apps
+        .iter()
+        .map(|(key, title)| {
+            format!(
+                "<li><a href=\"/apps/{}/\">{}</a></li>\n",
+                escape(key),
+                escape(title)
+            )
+        })
+        .collect();
+    let list = if items.is_empty() {
+        "<p>No apps are running.</p>".to_string()
+    } else {
+        format!("<ul>\n{}</ul>", items)
+    };
+    format!(
+        "<!DOCTYPE html>\n<html><head><title>LSP Agent Apps</title></head>\n<body><h1>LSP Agent Apps</h1>\n{}\n</body></html>\n",
+        list
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_shim() {
+        let page =
+            with_shim("```html\n<html><HEAD lang=\"en\"><title>Todo</title></head></html>\n```");
+        assert!(page.starts_with("<html><HEAD lang=\"en\">\n<script>"));
+        assert!(page.ends_with("</script>\n<title>Todo</title></head></html>"));
+        assert!(page.contains("fetch('wry://log'"));
+        assert!(with_shim("<p>hi</p>").starts_with("<script>"));
+    }
+
+    #[test]
+    fn test_index() {
+        let page = index(&[("key-1".to_string(), "<Todo>".to_string())]);
+        assert!(page.contains("<a href=\"/apps/key-1/\">&lt;Todo&gt;</a>"));
+        assert!(index(&[]).contains("No apps are running."));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::page;
+use crate::runtime::BrowserRuntime;
+use agent::StoreValueBody;
+use axum::extract::{Path, State};
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Router, middleware};
+use futures::Stream;
+use std::sync::Arc;
+use tokio::sync::{broadcast, oneshot};
+use tokio::time::Duration;
+use traits::WebAgent;
+
+/// How long an app stays open after its last tab went away, so that reloading
+/// the page does not close it.
+const CLOSE_GRACE: Duration = Duration::from_secs(10);
+
+#[derive(Clone)]
+pub struct BrowserState {
+    pub runtime: Arc<BrowserRuntime>,
+    pub agent: Arc<dyn WebAgent>,
+}
+
+fn text(status: u16, body: String) -> Response {
+    let status = StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    (status, body).into_response()
+}
+
+fn json_or_not_found(json: Option<String>) -> Response {
+    match json {
+        Some(json) => ([(header::CONTENT_TYPE, "application/json")], json).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+async fn reply(response: oneshot::Receiver<(u16, String)>) -> Response {
+    match response.await {
+        Ok((status, content)) => text(status, content),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+async fn index(State(state): State<BrowserState>) -> Html<String> {
+    Html(page::index(&state.runtime.apps().await))
+}
+
+fn not_running() -> Response {
+    (StatusCode::NOT_FOUND, "This app is not running.").into_response()
+}
+
+async fn app_page(State(state): State<BrowserState>, Path(key): Path<String>) -> Response {
+    let Some(app_id) = state.runtime.app_id(&key).await else {
+        return not_running();
+    };
+    match state.runtime.page(&app_id).await {
+        Some(html) => Html(html).into_response(),
+        None => not_running(),
+    }
+}
+
+/// Counts the tab as showing the app until the stream is dropped; the app is
+/// closed once no tab has shown it for `CLOSE_GRACE`.
+struct Connection {
+    state: BrowserState,
+    app_id: String,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let Ok(handle) = tokio::runtime::Handle::try_current() else {
+            return;
+        };
+        let state = self.state.clone();
+        let app_id = self.app_id.clone();
+        handle.spawn(async move {
+            state.runtime.disconnect(&app_id).await;
+            tokio::time::sleep(CLOSE_GRACE).await;
+            if state.runtime.remove_if_unused(&app_id).await {
+                state.agent.close_app(app_id).await;
+            }
+        });
+    }
+}
+
+/// Storage updates for the shim to dispatch as `doc_changed` events.
+async fn events(State(state): State<BrowserState>, Path(key): Path<String>) -> Response {
+    let Some(app_id) = state.runtime.app_id(&key).await else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !state.runtime.connect(&app_id).await {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    let updates = state.runtime.storage_updates();
+    let connection = Connection { state, app_id };
+    Sse::new(storage_events(updates, connection))
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
+
+fn storage_events(
+    updates: broadcast::Receiver<String>,
+    connection: Connection,
+) -> impl Stream<Item = Result<Event, axum::Error>> {
+    futures::stream::unfold(
+        (updates, connection),
+        |(mut updates, connection)| async move {
+            loop {
+                match updates.recv().await {
+                    Ok(key) => {
+                        let event = Event::default().event("doc_changed").json_data(key);
+                        return Some((event, (updates, connection)));
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        },
+    )
+}
+
+/// Serves `fetch('wry://<protocol>')` calls, as the wry runtime's custom
+/// protocol handler does.
+async fn protocol(
+    State(state): State<BrowserState>,
+    Path((key, protocol)): Path<(String, String)>,
+    body: String,
+) -> Response {
+    let BrowserState { runtime, agent } = state;
+    // The app is identified by its key, never by an id it could name itself.
+    let Some(app_id) = runtime.app_id(&key).await else {
+        return not_running();
+    };
+    match protocol.as_str() {
+        "inference" => {
+            let (request_id, response) = runtime.enqueue_reply();
+            agent.app_inference_request(request_id, body, app_id).await;
+            reply(response).await
+        }
+        "document" => text(200, agent.read_document(app_id, body).await),
+        "selection" => json_or_not_found(agent.read_selection(app_id, body).await),
+        "launch_context" => json_or_not_found(agent.read_launch_context(app_id).await),
+        "workspace_file" => {
+            let (request_id, response) = runtime.enqueue_reply();
+            agent.read_workspace_file(request_id, app_id, body).await;
+            reply(response).await
+        }
+        "list_directory" => {
+            let (request_id, response) = runtime.enqueue_reply();
+            agent.list_directory(request_id, app_id, body).await;
+            reply(response).await
+        }
+        "propose_edit" => {
+            let (request_id, response) = runtime.enqueue_reply();
+            match agent.propose_edit(request_id.clone(), app_id, body).await {
+                Ok(()) => reply(response).await,
+                Err(message) => {
+                    runtime.cancel_reply(&request_id);
+                    text(400, message)
+                }
+            }
+        }
+        "diagnostics" => match agent.publish_diagnostics(app_id, body).await {
+            Ok(()) => StatusCode::OK.into_response(),
+            Err(message) => text(400, message),
+        },
+        "contributions" => match agent.publish_contributions(app_id, body).await {
+            Ok(()) => StatusCode::OK.into_response(),
+            Err(message) => text(400, message),
+        },
+        "store_value" => match serde_json::from_str::<StoreValueBody>(&body) {
+            Ok(parsed) => {
+                agent
+                    .store_value(app_id, parsed.key.clone(), parsed.value, parsed.description)
+                    .await;
+                runtime.notify_storage_update(parsed.key);
+                StatusCode::OK.into_response()
+            }
+            Err(e) => text(400, e.to_string()),
+        },
+        "read_value" => text(
+            200,
+            agent.read_value(app_id, body).await.unwrap_or_default(),
+        ),
+        "log" => {
+            agent.log_error(app_id, body).await;
+            StatusCode::OK.into_response()
+        }
+        _ => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Keeps the index URL, and with it the token, out of `document.referrer`
+/// of the apps opened from it.
+async fn no_referrer(mut response: Response) -> Response {
+    response.headers_mut().insert(
+        header::REFERRER_POLICY,
+        HeaderValue::from_static("no-referrer"),
+    );
+    response
+}
+
+/// The index is served under `/<token>/` and each app under `/apps/<key>/`,
+/// with keys issued at launch. Apps share the origin, so they can request
+/// any path, but never learn the token or the other apps' keys.
+pub fn router(state: BrowserState, token: &str) -> Router {
+    Router::new()
+        .route(&format!("/{}/", token), get(index))
+        .route("/apps/:key/", get(app_page))
+        .route("/apps/:key/events", get(events))
+        .route("/apps/:key/wry/:protocol", post(protocol))
+        .layer(middleware::map_response(no_referrer))
+        .with_state(state)
+}
@@ -0,0 +1,200 @@
+use crate::page;
+use agent::pending_replies::PendingReplies;
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use tokio::sync::{Mutex, broadcast, oneshot};
+use traits::Web;
+
+/// Status and body of a response that arrives later through the shared document.
+pub type Responder = oneshot::Sender<(u16, String)>;
+
+struct App {
+    /// Secret path segment the app is served under. Apps share one origin, so
+    /// an app may only reach the endpoints of the key its page was loaded from.
+    key: String,
+    html: String,
+    title: String,
+    /// Open `events` streams, i.e. browser tabs showing the app.
+    connections: usize,
+}
+
+/// Keeps the running apps' pages and the requests waiting for a response,
+/// which the HTTP handlers in `routes` serve to the browser.
+pub struct BrowserRuntime {
+    /// Origin the apps are served from, ending in `/`.
+    origin: String,
+    open_browser: bool,
+    apps: Mutex<BTreeMap<String, App>>,
+    pending_replies: PendingReplies<Responder>,
+    storage_updates: broadcast::Sender<String>,
+}
+
+impl BrowserRuntime {
+    pub fn new(origin: String, open_browser: bool) -> Self {
+        Self {
+            origin,
+            open_browser,
+            apps: Mutex::new(BTreeMap::new()),
+            pending_replies: PendingReplies::default(),
+            storage_updates: broadcast::channel(32).0,
+        }
+    }
+
+    /// The id of the running app served under `key`.
+    pub async fn app_id(&self, key: &str) -> Option<String> {
+        let apps = self.apps.lock().await;
+        apps.iter()
+            .find(|(_, app)| app.key == key)
+            .map(|(id, _)| id.clone())
+    }
+
+    /// The app's page, with the shim that maps `wry://` to HTTP.
+    pub async fn page(&self, app_id: &str) -> Option<String> {
+        let apps = self.apps.lock().await;
+        apps.get(app_id).map(|app| page::with_shim(&app.html))
+    }
+
+    /// Keys and titles of the running apps, sorted by app id.
+    pub async fn apps(&self) -> Vec<(String, String)> {
+        let apps = self.apps.lock().await;
+        apps.values()
+            .map(|app| (app.key.clone(), app.title.clone()))
+            .collect()
+    }
+
+    /// Records a tab showing the app; `false` if it is not running.
+    pub async fn connect(&self, app_id: &str) -> bool {
+        match self.apps.lock().await.get_mut(app_id) {
+            Some(app) => {
+                app.connections += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub async fn disconnect(&self, app_id: &str) {
+        if let Some(app) = self.apps.lock().await.get_mut(app_id) {
+            app.connections = app.connections.saturating_sub(1);
+        }
+    }
+
+    /// Removes the app if no tab shows it anymore, returning whether it did.
+    pub async fn remove_if_unused(&self, app_id: &str) -> bool {
+        let mut apps = self.apps.lock().await;
+        if apps.get(app_id).is_some_and(|app| app.connections == 0) {
+            apps.remove(app_id);
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn storage_updates(&self) -> broadcast::Receiver<String> {
+        self.storage_updates.subscribe()
+    }
+
+    pub fn notify_storage_update(&self, key: String) {
+        let _ = self.storage_updates.send(key);
+    }
+
+    /// A request id for a call whose reply arrives later, and the receiver it
+    /// arrives on. A reply that never comes is answered with a timeout.
+    pub fn enqueue_reply(&self) -> (String, oneshot::Receiver<(u16, String)>) {
+        let (responder, response) = oneshot::channel();
+        let request_id = self.pending_replies.register(responder, |responder| {
+            let _ = responder.send((504, "No reply arrived in time.".to_string()));
+        });
+        (request_id, response)
+    }
+
+    /// Stops waiting for a reply to a call the agent refused.
+    pub fn cancel_reply(&self, request_id: &str) {
+        self.pending_replies.take(request_id);
+    }
+}
+
+/// Opens `url` with the platform's default browser. Failing is fine on
+/// headless machines: the URL is printed for the user to open elsewhere.
+fn open_in_browser(url: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        tokio::process::Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = tokio::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        tokio::process::Command::new("xdg-open")
+    };
+    command
+        .arg(url)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null());
+    if let Err(e) = command.spawn() {
+        eprintln!("[Browser] Could not open a browser: {}", e);
+    }
+}
+
+#[async_trait]
+impl Web for BrowserRuntime {
+    async fn launch_app(&self, id: String, content: String) {
+        let title = agent::app_html::title(&content).unwrap_or_else(|| "LSP Agent Web".to_string());
+        let mut apps = self.apps.lock().await;
+        // Tabs already showing a relaunched app keep working.
+        let (key, connections) = apps.get(&id).map_or_else(
+            || (uuid::Uuid::new_v4().to_string(), 0),
+            |app| (app.key.clone(), app.connections),
+        );
+        let url = format!("{}apps/{}/", self.origin, key);
+        eprintln!("[Browser] {} is running at {}", title, url);
+        apps.insert(
+            id,
+            App {
+                key,
+                html: content,
+                title,
+                connections,
+            },
+        );
+        drop(apps);
+        if self.open_browser {
+            open_in_browser(&url);
+        }
+    }
+
+    async fn handle_reply(&self, request_id: String, status: u16, content: String) {
+        if let Some(responder) = self.pending_replies.take(&request_id) {
+            let _ = responder.send((status, content));
+        } else {
+            eprintln!("[Browser] Reply for unknown request id: {}", request_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_apps_are_served_under_secret_keys() {
+        let runtime = BrowserRuntime::new("http://127.0.0.1:2351/".to_string(), false);
+        runtime
+            .launch_app("app-1".to_string(), "<title>Todo</title>".to_string())
+            .await;
+        runtime
+            .launch_app("app-2".to_string(), "<title>Notes</title>".to_string())
+            .await;
+
+        let apps = runtime.apps().await;
+        let (key, title) = &apps[0];
+        assert_eq!(title, "Todo");
+        assert_ne!(key, &apps[1].0);
+        assert_eq!(runtime.app_id(key).await.as_deref(), Some("app-1"));
+        assert_eq!(runtime.app_id("app-1").await, None);
+
+        runtime
+            .launch_app("app-1".to_string(), "<title>Todo 2</title>".to_string())
+            .await;
+        assert_eq!(runtime.app_id(key).await.as_deref(), Some("app-1"));
+    }
+}
@@ -44,6 +44,38 @@ cargo test
 cargo build
 cd ../..
 
+echo "=== Testing and Building Terminal Client ==="
+cd cli
+cargo fmt
+cargo clippy -- -D warnings
+cargo test
+cargo build
+cd ..
+
+echo "=== Testing and Building API Server ==="
+cd api
+cargo fmt
+cargo clippy -- -D warnings
+cargo test
+cargo build
+cd ..
+
+echo "=== Testing and Building Browser Runtime ==="
+cd browser
+cargo fmt
+cargo clippy -- -D warnings
+cargo test
+cargo build
+cd ..
+
+echo "=== Testing and Building Headless Runtime ==="
+cd headless
+cargo fmt
+cargo clippy -- -D warnings
+cargo test
+cargo build
+cd ..
+
 echo "=== Testing and Building Rust Web Client ==="
 cd web
 cargo fmt
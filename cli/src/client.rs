@@ -0,0 +1,68 @@
+use agent::http_inference::HttpInference;
+use tokio::sync::{mpsc, oneshot};
+use traits::{Diagnostic, EditProposal, InferenceClient};
+
+/// An edit proposed by an app, waiting for the user to accept or decline it at
+/// the prompt.
+pub struct Proposal {
+    pub edit: EditProposal,
+    pub responder: oneshot::Sender<Result<bool, String>>,
+}
+
+/// Runs inference over HTTP and surfaces everything else in the terminal.
+pub struct CliClient {
+    pub inference: Option<HttpInference>,
+    pub proposals: mpsc::Sender<Proposal>,
+}
+
+#[async_trait::async_trait]
+impl InferenceClient for CliClient {
+    async fn inference(&self, request: String, model: Option<String>) -> Result<String, String> {
+        match &self.inference {
+            Some(inference) => inference.complete(request, model).await,
+            None => {
+                Err("Set LSP_AGENT_INFERENCE_URL to run inference from the terminal.".to_string())
+            }
+        }
+    }
+
+    async fn notify_shutdown(&self) {}
+
+    async fn show_message(&self, message: String) {
+        println!("[agent] {}", message);
+    }
+
+    async fn apply_edit(&self, proposal: EditProposal) -> Result<bool, String> {
+        let (responder, response) = oneshot::channel();
+        self.proposals
+            .send(Proposal {
+                edit: proposal,
+                responder,
+            })
+            .await
+            .map_err(|_| "The terminal is no longer reading input.".to_string())?;
+        response
+            .await
+            .map_err(|_| "The proposal was dropped.".to_string())?
+    }
+
+    async fn publish_diagnostics(&self, uri: String, diagnostics: Vec<Diagnostic>) {
+        if diagnostics.is_empty() {
+            println!("[diagnostics] {}: cleared", uri);
+        }
+        for diagnostic in diagnostics {
+            println!(
+                "[diagnostics] {}:{}:{}: {:?}: {} ({})",
+                uri,
+                diagnostic.range.start.line + 1,
+                diagnostic.range.start.character + 1,
+                diagnostic.severity,
+                diagnostic.message,
+                diagnostic.source
+            );
+        }
+    }
+
+    // Code lenses are an editor feature with nothing to refresh in a terminal.
+    async fn refresh_code_lenses(&self) {}
+}
@@ -0,0 +1,98 @@
+/// A line typed at the prompt: a chat message, or a `/` command.
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    Chat(String),
+    Open(String),
+    Close(String),
+    Active(String),
+    Files,
+    Apps,
+    Saved,
+    Launch(String),
+    Reopen(String),
+    Sessions,
+    NewSession(String),
+    Session(String),
+    Audit,
+    Help,
+    Quit,
+}
+
+pub const HELP: &str = "\
+Type a message to chat with the agent, or one of:
+  /open <path>     register a file as an open document and watch it for changes
+  /close <path>    stop tracking a file
+  /active <path>   make an open file the active document
+  /files           list open documents
+  /apps            list running apps
+  /saved           list apps saved in the workspace
+  /launch <name>   launch a saved app
+  /reopen <id>     reopen an app from the library
+  /sessions        list sessions
+  /new <name>      create a session and switch to it
+  /session <name>  switch to a session
+  /audit           show the audit log
+  /help            show this help
+  /quit            shut down the agent and exit";
+
+/// Parses one line of input; `None` for blank lines.
+pub fn parse(line: &str) -> Result<Option<Input>, String> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let Some(command) = line.strip_prefix('/') else {
+        return Ok(Some(Input::Chat(line.to_string())));
+    };
+    let (name, argument) = match command.split_once(char::is_whitespace) {
+        Some((name, argument)) => (name, argument.trim()),
+        None => (command, ""),
+    };
+    let required = |input: fn(String) -> Input| {
+        if argument.is_empty() {
+            Err(format!("/{} needs an argument, see /help.", name))
+        } else {
+            Ok(input(argument.to_string()))
+        }
+    };
+    let input = match name {
+        "open" => required(Input::Open)?,
+        "close" => required(Input::Close)?,
+        "active" => required(Input::Active)?,
+        "files" => Input::Files,
+        "apps" => Input::Apps,
+        "saved" => Input::Saved,
+        "launch" => required(Input::Launch)?,
+        "reopen" => required(Input::Reopen)?,
+        "sessions" => Input::Sessions,
+        "new" => required(Input::NewSession)?,
+        "session" => required(Input::Session)?,
+        "audit" => Input::Audit,
+        "help" => Input::Help,
+        "quit" | "exit" => Input::Quit,
+        _ => return Err(format!("Unknown command /{}, see /help.", name)),
+    };
+    Ok(Some(input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse("  "), Ok(None));
+        assert_eq!(
+            parse("summarize the active doc"),
+            Ok(Some(Input::Chat("summarize the active doc".to_string())))
+        );
+        assert_eq!(
+            parse("/open  notes/todo.md "),
+            Ok(Some(Input::Open("notes/todo.md".to_string())))
+        );
+        assert_eq!(parse("/apps"), Ok(Some(Input::Apps)));
+        assert_eq!(parse("/exit"), Ok(Some(Input::Quit)));
+        assert!(parse("/launch").is_err());
+        assert!(parse("/unknown").is_err());
+    }
+}
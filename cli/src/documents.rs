@@ -0,0 +1,249 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use traits::{TextChange, TextEdit, TextPosition, WorkspaceAgent};
+use url::Url;
+
+/// A file registered with the agent as an open document.
+struct OpenFile {
+    uri: String,
+    version: i32,
+    modified: Option<SystemTime>,
+    text: String,
+}
+
+/// Files from disk registered as documents, keyed by canonical path. Changes
+/// made to them by other programs are picked up by `sync`.
+#[derive(Default)]
+pub struct OpenFiles {
+    files: HashMap<PathBuf, OpenFile>,
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn canonical(path: &Path) -> Result<PathBuf, String> {
+    std::fs::canonicalize(path).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// Language id for a file, as editors derive it from the extension.
+pub fn language_id(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()).unwrap_or("") {
+        "rs" => "rust",
+        "md" => "markdown",
+        "js" | "mjs" => "javascript",
+        "ts" => "typescript",
+        "py" => "python",
+        "html" | "htm" => "html",
+        "css" => "css",
+        "json" => "json",
+        "toml" => "toml",
+        "yaml" | "yml" => "yaml",
+        "sh" => "shellscript",
+        _ => "plaintext",
+    }
+}
+
+pub fn file_uri(path: &Path) -> Result<String, String> {
+    Url::from_file_path(path)
+        .map(|uri| uri.to_string())
+        .map_err(|_| format!("{} is not an absolute path.", path.display()))
+}
+
+pub fn file_path(uri: &str) -> Result<PathBuf, String> {
+    Url::parse(uri)
+        .ok()
+        .and_then(|uri| uri.to_file_path().ok())
+        .ok_or_else(|| format!("{} is not a file.", uri))
+}
+
+impl OpenFiles {
+    /// Registers the file at `path` with the agent and returns its URI. Opening
+    /// a file twice re-reads it.
+    pub async fn open(
+        &mut self,
+        agent: &dyn WorkspaceAgent,
+        path: &Path,
+    ) -> Result<String, String> {
+        let path = canonical(path)?;
+        let text = std::fs::read_to_string(&path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let uri = file_uri(&path)?;
+        agent
+            .did_open(uri.clone(), language_id(&path).to_string(), 1, text.clone())
+            .await;
+        self.files.insert(
+            path.clone(),
+            OpenFile {
+                uri: uri.clone(),
+                version: 1,
+                modified: modified(&path),
+                text,
+            },
+        );
+        Ok(uri)
+    }
+
+    pub async fn close(
+        &mut self,
+        agent: &dyn WorkspaceAgent,
+        path: &Path,
+    ) -> Result<String, String> {
+        let path = canonical(path)?;
+        let file = self
+            .files
+            .remove(&path)
+            .ok_or_else(|| format!("{} is not open.", path.display()))?;
+        agent.did_close(file.uri.clone()).await;
+        Ok(file.uri)
+    }
+
+    pub fn uri(&self, path: &Path) -> Result<String, String> {
+        let path = canonical(path)?;
+        self.files
+            .get(&path)
+            .map(|file| file.uri.clone())
+            .ok_or_else(|| format!("{} is not open, /open it first.", path.display()))
+    }
+
+    pub fn uris(&self) -> Vec<String> {
+        let mut uris: Vec<String> = self.files.values().map(|file| file.uri.clone()).collect();
+        uris.sort();
+        uris
+    }
+
+    /// Sends the new contents of files modified on disk since the last call, and
+    /// closes files that were deleted. Returns the URIs of the closed files.
+    pub async fn sync(&mut self, agent: &dyn WorkspaceAgent) -> Vec<String> {
+        let mut closed = Vec::new();
+        for (path, file) in &mut self.files {
+            let now = modified(path);
+            if now == file.modified {
+                continue;
+            }
+            file.modified = now;
+            let Ok(text) = std::fs::read_to_string(path) else {
+                closed.push(path.clone());
+                continue;
+            };
+            if text == file.text {
+                continue;
+            }
+            file.version += 1;
+            file.text = text.clone();
+            agent
+                .did_change(
+                    file.uri.clone(),
+                    file.version,
+                    vec![TextChange { range: None, text }],
+                )
+                .await;
+        }
+        let mut uris = Vec::new();
+        for path in closed {
+            if let Some(file) = self.files.remove(&path) {
+                agent.did_close(file.uri.clone()).await;
+                uris.push(file.uri);
+            }
+        }
+        uris
+    }
+}
+
+/// Byte offset of an LSP position (UTF-16 code units) in `text`, clamped to the
+/// end of its line and of the text.
+fn byte_offset(text: &str, position: TextPosition) -> usize {
+    let mut offset = 0;
+    let mut lines = text.split_inclusive('\n');
+    for _ in 0..position.line {
+        match lines.next() {
+            Some(line) => offset += line.len(),
+            None => return text.len(),
+        }
+    }
+    let Some(line) = lines.next() else {
+        return text.len();
+    };
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    let mut units = 0;
+    for c in line.chars() {
+        if units >= position.character as usize {
+            break;
+        }
+        units += c.len_utf16();
+        offset += c.len_utf8();
+    }
+    offset
+}
+
+/// Applies non-overlapping `edits`, whose ranges all refer to the original `text`.
+pub fn apply_edits(text: &str, edits: &[TextEdit]) -> String {
+    let mut spans: Vec<(usize, usize, &str)> = edits
+        .iter()
+        .map(|edit| {
+            let start = byte_offset(text, edit.range.start);
+            let end = byte_offset(text, edit.range.end).max(start);
+            (start, end, edit.text.as_str())
+        })
+        .collect();
+    spans.sort_by_key(|(start, end, _)| (*start, *end));
+    let mut result = String::with_capacity(text.len());
+    let mut copied = 0;
+    for (start, end, new_text) in spans {
+        let start = start.max(copied);
+        result.push_str(&text[copied..start]);
+        result.push_str(new_text);
+        copied = end.max(start);
+    }
+    result.push_str(&text[copied..]);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use traits::TextRange;
+
+    fn edit(start: (u32, u32), end: (u32, u32), text: &str) -> TextEdit {
+        let at = |(line, character)| TextPosition { line, character };
+        TextEdit {
+            range: TextRange {
+                start: at(start),
+                end: at(end),
+            },
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_apply_edits() {
+        let text = "let a = 1;\r\nlet 😀 = 2;\n";
+        assert_eq!(
+            apply_edits(
+                text,
+                &[edit((1, 6), (1, 7), "b"), edit((0, 4), (0, 5), "x")]
+            ),
+            "let x = 1;\r\nlet 😀b= 2;\n"
+        );
+        assert_eq!(apply_edits(text, &[edit((0, 0), (5, 0), "all")]), "all");
+        assert_eq!(
+            apply_edits(text, &[edit((0, 99), (0, 99), "!")]),
+            "let a = 1;!\r\nlet 😀 = 2;\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_language_id_and_uris() {
+        assert_eq!(language_id(Path::new("src/main.rs")), "rust");
+        assert_eq!(language_id(Path::new("LICENSE")), "plaintext");
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = canonical(dir.path()).unwrap().join("notes.md");
+        let uri = file_uri(&path).unwrap();
+        assert!(uri.starts_with("file:///"));
+        assert_eq!(file_path(&uri).unwrap(), path);
+        assert!(file_path("untitled:Untitled-1").is_err());
+    }
+}
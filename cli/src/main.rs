@@ -0,0 +1,231 @@
+mod client;
+mod commands;
+mod documents;
+
+use agent::http_inference::HttpInference;
+use agent::start_infra;
+use clap::Parser;
+use client::{CliClient, Proposal};
+use commands::Input;
+use documents::OpenFiles;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, BufReader, Lines, Stdin};
+use tokio::sync::{Mutex, mpsc};
+use tokio::time::Duration;
+use traits::WorkspaceAgent;
+
+/// How often open files are checked for changes made on disk.
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Chat with the agent from a terminal. Apps open in the web client as they do
+/// from the editor.
+#[derive(Parser)]
+struct Args {
+    /// Workspace folder for saved apps and exposed files; the current directory by default.
+    #[arg(long)]
+    workspace: Option<PathBuf>,
+    /// Model for chat turns, passed on to the inference backend.
+    #[arg(long)]
+    model: Option<String>,
+    /// Glob pattern of workspace files apps may read, like `lspAgent.exposedFiles`. Repeatable.
+    #[arg(long = "expose")]
+    exposed_files: Vec<String>,
+    /// Files to open as documents; the first one becomes the active document.
+    files: Vec<PathBuf>,
+}
+
+fn prompt() {
+    print!("> ");
+    let _ = std::io::stdout().flush();
+}
+
+async fn run(
+    input: Input,
+    agent: &dyn WorkspaceAgent,
+    files: &Mutex<OpenFiles>,
+    model: Option<String>,
+) -> Result<(), String> {
+    match input {
+        Input::Chat(message) => match agent.chat_request(None, message, model, None).await {
+            Some(reply) => println!("{}", reply),
+            None => println!("The agent did not reply."),
+        },
+        Input::Open(path) => {
+            let uri = files.lock().await.open(agent, Path::new(&path)).await?;
+            println!("Opened {}", uri);
+        }
+        Input::Close(path) => {
+            let uri = files.lock().await.close(agent, Path::new(&path)).await?;
+            println!("Closed {}", uri);
+        }
+        Input::Active(path) => {
+            let uri = files.lock().await.uri(Path::new(&path))?;
+            agent.set_active_document(uri).await;
+        }
+        Input::Files => {
+            for uri in files.lock().await.uris() {
+                println!("{}", uri);
+            }
+        }
+        Input::Apps => {
+            for app in agent.list_apps().await {
+                println!("{}  {}", app.id, app.title);
+                for error in app.errors {
+                    println!("    {}", error);
+                }
+            }
+        }
+        Input::Saved => {
+            for name in agent.list_saved_apps().await {
+                println!("{}", name);
+            }
+        }
+        Input::Launch(name) => {
+            let app_id = agent.launch_saved_app(name, None).await?;
+            println!("Launched {}", app_id);
+        }
+        Input::Reopen(app_id) => agent.relaunch_app(app_id).await?,
+        Input::Sessions => {
+            for session in agent.list_sessions().await {
+                println!(
+                    "{} {} ({} turns, {} apps)",
+                    if session.active { "*" } else { " " },
+                    session.name,
+                    session.history_len,
+                    session.apps
+                );
+            }
+        }
+        Input::NewSession(name) => agent.create_session(name).await?,
+        Input::Session(name) => agent.switch_session(name).await?,
+        Input::Audit => println!("{}", agent.audit_log().await),
+        Input::Help => println!("{}", commands::HELP),
+        Input::Quit => {}
+    }
+    Ok(())
+}
+
+/// Shows an app's edit proposal and applies it to the file on disk if the user
+/// accepts. The file watcher then syncs the change to the agent.
+async fn review(proposal: Proposal, lines: &mut Lines<BufReader<Stdin>>) {
+    let Proposal { edit, responder } = proposal;
+    println!(
+        "\n{} proposes {} edit(s) to {}:",
+        edit.label,
+        edit.edits.len(),
+        edit.uri
+    );
+    for change in &edit.edits {
+        println!(
+            "  {}:{}-{}:{} -> {:?}",
+            change.range.start.line + 1,
+            change.range.start.character + 1,
+            change.range.end.line + 1,
+            change.range.end.character + 1,
+            change.text
+        );
+    }
+    print!("Apply? [y/N] ");
+    let _ = std::io::stdout().flush();
+    let accepted = matches!(
+        lines.next_line().await,
+        Ok(Some(answer)) if answer.trim().eq_ignore_ascii_case("y")
+    );
+    let result = if accepted {
+        apply_to_disk(&edit.uri, &edit.edits).map(|()| true)
+    } else {
+        Ok(false)
+    };
+    if let Err(e) = &result {
+        eprintln!("{}", e);
+    }
+    let _ = responder.send(result);
+}
+
+fn apply_to_disk(uri: &str, edits: &[traits::TextEdit]) -> Result<(), String> {
+    let path = documents::file_path(uri)?;
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    std::fs::write(&path, documents::apply_edits(&text, edits))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    let inference = HttpInference::from_env();
+    if inference.is_none() {
+        eprintln!("LSP_AGENT_INFERENCE_URL is not set; chat turns will fail until it is.");
+    }
+    let (proposals_tx, mut proposals) = mpsc::channel(8);
+    let agent: Arc<dyn WorkspaceAgent> = Arc::from(start_infra(Arc::new(CliClient {
+        inference,
+        proposals: proposals_tx,
+    })));
+
+    let workspace = args
+        .workspace
+        .unwrap_or_else(|| PathBuf::from("."))
+        .canonicalize()
+        .expect("Failed to resolve the workspace folder");
+    agent
+        .set_workspace_folders(vec![workspace.display().to_string()])
+        .await;
+    if !args.exposed_files.is_empty()
+        && let Err(e) = agent.set_exposed_files(args.exposed_files).await
+    {
+        eprintln!("--expose: {}", e);
+    }
+
+    let files = Arc::new(Mutex::new(OpenFiles::default()));
+    for (index, path) in args.files.iter().enumerate() {
+        match files.lock().await.open(agent.as_ref(), path).await {
+            Ok(uri) if index == 0 => agent.set_active_document(uri).await,
+            Ok(_) => {}
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    let watcher = {
+        let files = files.clone();
+        let agent = agent.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(WATCH_INTERVAL);
+            loop {
+                interval.tick().await;
+                for uri in files.lock().await.sync(agent.as_ref()).await {
+                    println!("\n{} was deleted and is no longer open.", uri);
+                }
+            }
+        })
+    };
+
+    println!("{}", commands::HELP);
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        prompt();
+        tokio::select! {
+            line = lines.next_line() => {
+                let Ok(Some(line)) = line else {
+                    break;
+                };
+                match commands::parse(&line) {
+                    Ok(Some(Input::Quit)) => break,
+                    Ok(Some(input)) => {
+                        if let Err(e) = run(input, agent.as_ref(), &files, args.model.clone()).await {
+                            eprintln!("{}", e);
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => eprintln!("{}", e),
+                }
+            }
+            Some(proposal) = proposals.recv() => review(proposal, &mut lines).await,
+        }
+    }
+
+    watcher.abort();
+    agent.shutdown().await;
+}
@@ -0,0 +1,508 @@
+use crate::page::{self, Script};
+use crate::report::Entry;
+use boa_engine::builtins::promise::{OperationType, Promise, PromiseState};
+use boa_engine::context::HostHooks;
+use boa_engine::native_function::NativeFunctionPointer;
+use boa_engine::object::builtins::JsPromise;
+use boa_engine::{
+    Context, JsArgs, JsError, JsObject, JsResult, JsValue, NativeFunction, Source, js_string,
+};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
+
+const PRELUDE: &str = include_str!("prelude.js");
+
+/// Loop iterations a single script may run, so that an app stuck in a loop
+/// is reported instead of stalling every other app.
+const LOOP_ITERATION_LIMIT: u64 = 10_000_000;
+
+/// What the rest of the runtime asks the engine thread to do.
+#[derive(Debug)]
+pub enum Command {
+    Launch {
+        app_id: String,
+        html: String,
+    },
+    /// Resolves the app's pending `fetch` call.
+    Respond {
+        app_id: String,
+        call_id: u32,
+        status: u16,
+        body: String,
+    },
+    StorageUpdated(String),
+}
+
+/// What apps ask of the agent, serviced outside the engine thread.
+#[derive(Debug, PartialEq)]
+pub enum HostCall {
+    Protocol {
+        app_id: String,
+        call_id: u32,
+        protocol: String,
+        body: String,
+    },
+    Close {
+        app_id: String,
+    },
+}
+
+/// Per-app state the native functions reach through the context.
+struct Host {
+    app_id: String,
+    next_call: Cell<u32>,
+    /// Protocol of each pending call, by call id.
+    calls: RefCell<HashMap<u32, String>>,
+    timers: RefCell<Vec<(Instant, u32)>>,
+    host_calls: UnboundedSender<HostCall>,
+    report: Sender<Entry>,
+}
+
+/// Promises rejected without a handler, reported once the job queue is empty.
+#[derive(Default)]
+struct Rejections(RefCell<Vec<JsObject<Promise>>>);
+
+impl HostHooks for Rejections {
+    fn promise_rejection_tracker(
+        &self,
+        promise: &JsObject<Promise>,
+        operation: OperationType,
+        _context: &mut Context,
+    ) {
+        let mut rejected = self.0.borrow_mut();
+        match operation {
+            OperationType::Reject => rejected.push(promise.clone()),
+            OperationType::Handle => rejected.retain(|other| other != promise),
+        }
+    }
+}
+
+struct App {
+    context: Context,
+    rejections: Rc<Rejections>,
+}
+
+fn host(context: &Context) -> &Host {
+    context
+        .get_data::<Host>()
+        .expect("Apps are created with a host")
+}
+
+fn string_arg(args: &[JsValue], index: usize, context: &mut Context) -> JsResult<String> {
+    Ok(args
+        .get_or_undefined(index)
+        .to_string(context)?
+        .to_std_string_escaped())
+}
+
+fn host_console(_: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let level = string_arg(args, 0, context)?;
+    let message = string_arg(args, 1, context)?;
+    let host = host(context);
+    let _ = host.report.send(Entry::Console {
+        app_id: host.app_id.clone(),
+        level,
+        message,
+    });
+    Ok(JsValue::undefined())
+}
+
+fn host_error(_: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let message = string_arg(args, 0, context)?;
+    let host = host(context);
+    let _ = host.report.send(Entry::Error {
+        app_id: host.app_id.clone(),
+        message,
+    });
+    Ok(JsValue::undefined())
+}
+
+fn host_request(_: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let protocol = string_arg(args, 0, context)?;
+    let body = string_arg(args, 1, context)?;
+    let host = host(context);
+    let call_id = host.next_call.get();
+    host.next_call.set(call_id + 1);
+    host.calls.borrow_mut().insert(call_id, protocol.clone());
+    let _ = host.report.send(Entry::Call {
+        app_id: host.app_id.clone(),
+        protocol: protocol.clone(),
+        body: body.clone(),
+    });
+    let _ = host.host_calls.send(HostCall::Protocol {
+        app_id: host.app_id.clone(),
+        call_id,
+        protocol,
+        body,
+    });
+    Ok(JsValue::from(call_id))
+}
+
+fn host_timer(_: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let id = args.get_or_undefined(0).to_u32(context)?;
+    let delay = args.get_or_undefined(1).to_number(context)?.max(0.0);
+    let at = Instant::now() + Duration::from_millis(delay as u64);
+    host(context).timers.borrow_mut().push((at, id));
+    Ok(JsValue::undefined())
+}
+
+fn host_close(_: &JsValue, _: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let host = host(context);
+    let _ = host.report.send(Entry::Closed {
+        app_id: host.app_id.clone(),
+    });
+    let _ = host.host_calls.send(HostCall::Close {
+        app_id: host.app_id.clone(),
+    });
+    Ok(JsValue::undefined())
+}
+
+/// A JS string literal for `text`.
+fn literal(text: &str) -> String {
+    serde_json::to_string(text).unwrap()
+}
+
+impl App {
+    fn new(
+        app_id: String,
+        host_calls: UnboundedSender<HostCall>,
+        report: Sender<Entry>,
+    ) -> JsResult<Self> {
+        let rejections = Rc::new(Rejections::default());
+        let mut context = Context::builder().host_hooks(rejections.clone()).build()?;
+        context
+            .runtime_limits_mut()
+            .set_loop_iteration_limit(LOOP_ITERATION_LIMIT);
+        context.insert_data(Host {
+            app_id,
+            next_call: Cell::new(1),
+            calls: RefCell::new(HashMap::new()),
+            timers: RefCell::new(Vec::new()),
+            host_calls,
+            report,
+        });
+        let natives: [(_, _, NativeFunctionPointer); 5] = [
+            (js_string!("__host_console"), 2, host_console),
+            (js_string!("__host_error"), 1, host_error),
+            (js_string!("__host_request"), 2, host_request),
+            (js_string!("__host_timer"), 2, host_timer),
+            (js_string!("__host_close"), 0, host_close),
+        ];
+        for (name, length, function) in natives {
+            context.register_global_callable(
+                name,
+                length,
+                NativeFunction::from_fn_ptr(function),
+            )?;
+        }
+        context.eval(Source::from_bytes(PRELUDE))?;
+        Ok(App {
+            context,
+            rejections,
+        })
+    }
+
+    fn report(&self, entry: Entry) {
+        let _ = host(&self.context).report.send(entry);
+    }
+
+    fn app_id(&self) -> String {
+        host(&self.context).app_id.clone()
+    }
+
+    fn report_error(&mut self, error: JsError) {
+        let message = match error.into_opaque(&mut self.context) {
+            Ok(value) => self.format(value),
+            Err(error) => error.to_string(),
+        };
+        self.report(Entry::Error {
+            app_id: self.app_id(),
+            message,
+        });
+    }
+
+    /// Formats `value` the way `console.log` does.
+    fn format(&mut self, value: JsValue) -> String {
+        let context = &mut self.context;
+        let format = context
+            .global_object()
+            .get(js_string!("__format"), context)
+            .ok()
+            .and_then(|format| format.as_callable());
+        format
+            .and_then(|format| {
+                format
+                    .call(&JsValue::undefined(), std::slice::from_ref(&value), context)
+                    .ok()
+            })
+            .and_then(|text| text.as_string().map(|text| text.to_std_string_escaped()))
+            .unwrap_or_else(|| value.display().to_string())
+    }
+
+    /// Runs `source` and the jobs it queued, reporting uncaught exceptions and
+    /// promises rejected without a handler.
+    fn run(&mut self, source: &str) {
+        if let Err(error) = self.context.eval(Source::from_bytes(source)) {
+            self.report_error(error);
+        }
+        if let Err(error) = self.context.run_jobs() {
+            self.report_error(error);
+        }
+        let rejected: Vec<_> = self.rejections.0.borrow_mut().drain(..).collect();
+        for promise in rejected {
+            if let PromiseState::Rejected(reason) = JsPromise::from(promise).state() {
+                let message = format!("Unhandled promise rejection: {}", self.format(reason));
+                self.report(Entry::Error {
+                    app_id: self.app_id(),
+                    message,
+                });
+            }
+        }
+    }
+
+    fn load(&mut self, html: &str) {
+        let html = agent::app_html::clean_html(html);
+        let ids = serde_json::to_string(&page::element_ids(html)).unwrap();
+        self.run(&format!("__set_element_ids({});", ids));
+        let scripts = page::scripts(html);
+        for script in &scripts {
+            match script {
+                Script::Inline(source) => self.run(source),
+                Script::External(src) => self.report(Entry::Error {
+                    app_id: self.app_id(),
+                    message: format!(
+                        "The script at {} was not loaded: the headless runtime only runs inline scripts",
+                        src
+                    ),
+                }),
+            }
+        }
+        self.run("__load();");
+        self.report(Entry::Loaded {
+            app_id: self.app_id(),
+            scripts: scripts.len(),
+        });
+    }
+
+    fn respond(&mut self, call_id: u32, status: u16, body: String) {
+        let Some(protocol) = host(&self.context).calls.borrow_mut().remove(&call_id) else {
+            return;
+        };
+        let source = format!(
+            "__wry_respond({}, {}, {});",
+            call_id,
+            status,
+            literal(&body)
+        );
+        self.report(Entry::Response {
+            app_id: self.app_id(),
+            protocol,
+            status,
+            body,
+        });
+        self.run(&source);
+    }
+
+    fn next_timer(&self) -> Option<Instant> {
+        let timers = host(&self.context).timers.borrow();
+        timers.iter().map(|(at, _)| *at).min()
+    }
+
+    fn fire_due_timers(&mut self) {
+        let now = Instant::now();
+        let due: Vec<u32> = {
+            let mut timers = host(&self.context).timers.borrow_mut();
+            let mut due: Vec<(Instant, u32)> = timers
+                .iter()
+                .copied()
+                .filter(|(at, _)| *at <= now)
+                .collect();
+            timers.retain(|(at, _)| *at > now);
+            due.sort();
+            due.into_iter().map(|(_, id)| id).collect()
+        };
+        for id in due {
+            self.run(&format!("__fire_timer({});", id));
+        }
+    }
+}
+
+/// Runs apps on the current thread until `commands` is closed. Each app gets
+/// its own JS context; protocol calls go out through `host_calls` and what the
+/// apps do is sent to `report`.
+pub fn run(
+    commands: Receiver<Command>,
+    host_calls: UnboundedSender<HostCall>,
+    report: Sender<Entry>,
+) {
+    let mut apps: HashMap<String, App> = HashMap::new();
+    loop {
+        let next_timer = apps.values().filter_map(App::next_timer).min();
+        let command = match next_timer {
+            Some(at) => commands.recv_timeout(at.saturating_duration_since(Instant::now())),
+            None => commands.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match command {
+            Ok(Command::Launch { app_id, html }) => {
+                match App::new(app_id.clone(), host_calls.clone(), report.clone()) {
+                    Ok(mut app) => {
+                        app.load(&html);
+                        apps.insert(app_id, app);
+                    }
+                    Err(error) => {
+                        let _ = report.send(Entry::Error {
+                            app_id,
+                            message: format!("Failed to create a JS context: {}", error),
+                        });
+                    }
+                }
+            }
+            Ok(Command::Respond {
+                app_id,
+                call_id,
+                status,
+                body,
+            }) => {
+                if let Some(app) = apps.get_mut(&app_id) {
+                    app.respond(call_id, status, body);
+                }
+            }
+            Ok(Command::StorageUpdated(key)) => {
+                let event = format!(
+                    "dispatchEvent(new CustomEvent('doc_changed', {{ detail: {{ key: {} }} }}));",
+                    literal(&key)
+                );
+                for app in apps.values_mut() {
+                    app.run(&event);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        for app in apps.values_mut() {
+            app.fire_due_timers();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const APP: &str = r#"<html><body><div id="out"></div>
+<script>
+window.addEventListener('DOMContentLoaded', async () => {
+    const response = await fetch('wry://inference', { method: 'POST', body: 'Say hi' });
+    document.getElementById('out').textContent = await response.text();
+    console.log('got', document.getElementById('out').textContent, { status: response.status });
+    setTimeout(() => { throw new Error('late'); }, 5);
+});
+window.addEventListener('doc_changed', (event) => console.info('changed', event.detail.key));
+Promise.reject(new TypeError('nobody listens'));
+document.getElementById('missing').textContent = 'x';
+</script>
+<script src="https://example.com/lib.js"></script>
+</body></html>"#;
+
+    fn until(
+        entries: &std::sync::mpsc::Receiver<Entry>,
+        done: impl Fn(&Entry) -> bool,
+    ) -> Vec<Entry> {
+        let mut seen = Vec::new();
+        while let Ok(entry) = entries.recv_timeout(Duration::from_secs(5)) {
+            let last = done(&entry);
+            seen.push(entry);
+            if last {
+                return seen;
+            }
+        }
+        panic!("Gave up waiting, saw {:?}", seen);
+    }
+
+    #[test]
+    fn test_app_runs_and_reports() {
+        let (commands_tx, commands) = std::sync::mpsc::channel();
+        let (host_calls_tx, mut host_calls) = tokio::sync::mpsc::unbounded_channel();
+        let (report_tx, entries) = std::sync::mpsc::channel();
+        let engine = std::thread::spawn(move || run(commands, host_calls_tx, report_tx));
+        let app_id = "app-1".to_string();
+        commands_tx
+            .send(Command::Launch {
+                app_id: app_id.clone(),
+                html: APP.to_string(),
+            })
+            .unwrap();
+
+        let seen = until(&entries, |entry| matches!(entry, Entry::Loaded { .. }));
+        let errors: Vec<&str> = seen
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::Error { message, .. } => Some(message.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(errors.len(), 3, "{:?}", errors);
+        assert!(errors[0].starts_with("TypeError"));
+        assert_eq!(
+            errors[1],
+            "Unhandled promise rejection: TypeError: nobody listens"
+        );
+        assert!(errors[2].contains("https://example.com/lib.js"));
+        assert!(seen.contains(&Entry::Call {
+            app_id: app_id.clone(),
+            protocol: "inference".to_string(),
+            body: "Say hi".to_string(),
+        }));
+        assert_eq!(
+            seen.last(),
+            Some(&Entry::Loaded {
+                app_id: app_id.clone(),
+                scripts: 2
+            })
+        );
+
+        let Ok(HostCall::Protocol { call_id, .. }) = host_calls.try_recv() else {
+            panic!("The inference call did not reach the host");
+        };
+        commands_tx
+            .send(Command::Respond {
+                app_id: app_id.clone(),
+                call_id,
+                status: 200,
+                body: "hi".to_string(),
+            })
+            .unwrap();
+        let seen = until(&entries, |entry| matches!(entry, Entry::Error { .. }));
+        assert!(seen.contains(&Entry::Console {
+            app_id: app_id.clone(),
+            level: "log".to_string(),
+            message: "got hi {\"status\":200}".to_string(),
+        }));
+        assert_eq!(
+            seen.last(),
+            Some(&Entry::Error {
+                app_id: app_id.clone(),
+                message: "Error: late".to_string()
+            })
+        );
+
+        commands_tx
+            .send(Command::StorageUpdated("score".to_string()))
+            .unwrap();
+        assert_eq!(
+            until(&entries, |_| true),
+            vec![Entry::Console {
+                app_id,
+                level: "info".to_string(),
+                message: "changed score".to_string(),
+            }]
+        );
+
+        drop(commands_tx);
+        engine.join().unwrap();
+    }
+}
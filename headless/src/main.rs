@@ -0,0 +1,60 @@
+mod engine;
+mod page;
+mod report;
+mod runtime;
+
+use agent::start_web_backend;
+use runtime::HeadlessRuntime;
+use std::io::Write;
+use std::sync::Arc;
+
+/// Runs apps in an embedded JS engine without a display, so CI can check that
+/// generated apps load and use the `wry://` protocols correctly. The server
+/// spawns it when `LSP_AGENT_WEB_RUNTIME=headless` is set.
+///
+/// Everything the apps do is reported as JSON lines (see `report::Entry`) to
+/// the file named by `LSP_AGENT_HEADLESS_REPORT`, or to stderr. Errors are
+/// also logged with the agent, as the other runtimes' error reporter does.
+#[tokio::main]
+async fn main() {
+    let mut sink: Box<dyn Write + Send> = match std::env::var("LSP_AGENT_HEADLESS_REPORT") {
+        Ok(path) => match std::fs::File::create(&path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                eprintln!("[Headless] Failed to create report {}: {}", path, e);
+                return;
+            }
+        },
+        Err(_) => Box::new(std::io::stderr()),
+    };
+
+    let (commands_tx, commands) = std::sync::mpsc::channel();
+    let (host_calls_tx, mut host_calls) = tokio::sync::mpsc::unbounded_channel();
+    let (report_tx, entries) = std::sync::mpsc::channel();
+    let (errors_tx, mut errors) = tokio::sync::mpsc::unbounded_channel();
+    std::thread::spawn(move || engine::run(commands, host_calls_tx, report_tx));
+    std::thread::spawn(move || {
+        for entry in entries {
+            if let Some(error) = entry.app_error() {
+                let _ = errors_tx.send(error);
+            }
+            let line = serde_json::to_string(&entry).unwrap();
+            if writeln!(sink, "{}", line)
+                .and_then(|()| sink.flush())
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    let runtime = Arc::new(HeadlessRuntime::new(commands_tx));
+    let (agent, mut exit_rx) = start_web_backend(runtime.clone()).await;
+    loop {
+        tokio::select! {
+            Some(call) = host_calls.recv() => runtime.serve(agent.as_ref(), call).await,
+            Some((app_id, message)) = errors.recv() => agent.log_error(app_id, message).await,
+            _ = exit_rx.recv() => break,
+        }
+    }
+}
@@ -0,0 +1,88 @@
+/// A `<script>` element of an app, in document order.
+#[derive(Debug, PartialEq)]
+pub enum Script {
+    Inline(String),
+    /// Scripts loaded from a `src` URL, which the headless runtime does not fetch.
+    External(String),
+}
+
+/// Value of `name="..."` (or single-quoted) among a tag's attributes.
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let lower = attributes.to_ascii_lowercase();
+    let mut from = 0;
+    while let Some(found) = lower[from..].find(name) {
+        let start = from + found;
+        from = start + name.len();
+        let preceded = start == 0 || lower.as_bytes()[start - 1].is_ascii_whitespace();
+        let rest = attributes[from..].trim_start();
+        let (true, Some(rest)) = (preceded, rest.strip_prefix('=')) else {
+            continue;
+        };
+        let rest = rest.trim_start();
+        let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+        let value = &rest[1..];
+        return value.find(quote).map(|end| value[..end].to_string());
+    }
+    None
+}
+
+/// The app's scripts, in the order a browser runs them.
+pub fn scripts(html: &str) -> Vec<Script> {
+    let lower = html.to_ascii_lowercase();
+    let mut scripts = Vec::new();
+    let mut from = 0;
+    while let Some(found) = lower[from..].find("<script") {
+        let start = from + found;
+        let Some(tag_end) = lower[start..].find('>').map(|end| start + end) else {
+            break;
+        };
+        let body_start = tag_end + 1;
+        let body_end = lower[body_start..]
+            .find("</script")
+            .map_or(html.len(), |end| body_start + end);
+        let attributes = &html[start + "<script".len()..tag_end];
+        scripts.push(match attribute(attributes, "src") {
+            Some(src) => Script::External(src),
+            None => Script::Inline(html[body_start..body_end].to_string()),
+        });
+        from = body_end;
+    }
+    scripts
+}
+
+/// Ids of the elements in the HTML, which `document.getElementById` finds.
+pub fn element_ids(html: &str) -> Vec<String> {
+    let mut ids = Vec::new();
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        let tag = &rest[start + 1..];
+        let end = tag.find('>').unwrap_or(tag.len());
+        if let Some(id) = attribute(&tag[..end], "id") {
+            ids.push(id);
+        }
+        rest = &tag[end..];
+    }
+    ids
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scripts_and_ids() {
+        let html = agent::app_html::clean_html(
+            "```html\n<div id=\"out\" data-id='x'></div><SCRIPT src=\"lib.js\"></SCRIPT>\
+             <script type=\"text/javascript\">let a = '<b id=\"no\">';</script><p id='p1'>\n```",
+        );
+        assert_eq!(
+            scripts(html),
+            vec![
+                Script::External("lib.js".to_string()),
+                Script::Inline("let a = '<b id=\"no\">';".to_string()),
+            ]
+        );
+        assert!(element_ids(html).starts_with(&["out".to_string()]));
+        assert!(element_ids(html).contains(&"p1".to_string()));
+    }
+}
@@ -0,0 +1,356 @@
+// Just enough of a browser for generated apps to load: a DOM that records what
+// scripts do to it, timers, and a `fetch` that only reaches `wry://` protocols.
+// The `__host_*` functions are provided by the engine.
+(() => {
+    const global = globalThis;
+
+    const format = (value) => {
+        if (typeof value === 'string') {
+            return value;
+        }
+        if (value instanceof Error) {
+            return `${value.name}: ${value.message}`;
+        }
+        if (typeof value === 'object' && value !== null) {
+            try {
+                return JSON.stringify(value);
+            } catch (e) {
+                return String(value);
+            }
+        }
+        return String(value);
+    };
+    global.__format = format;
+
+    const log = (level) => (...args) => __host_console(level, args.map(format).join(' '));
+    global.console = {
+        log: log('log'),
+        info: log('info'),
+        debug: log('debug'),
+        warn: log('warn'),
+        error: log('error'),
+    };
+    global.alert = (message) => __host_console('alert', format(message));
+
+    class Event {
+        constructor(type, init = {}) {
+            this.type = type;
+            this.detail = init.detail;
+            this.defaultPrevented = false;
+            this.target = null;
+        }
+        preventDefault() {
+            this.defaultPrevented = true;
+        }
+        stopPropagation() {}
+    }
+    class CustomEvent extends Event {}
+    global.Event = Event;
+    global.CustomEvent = CustomEvent;
+
+    class EventTarget {
+        constructor() {
+            this.listeners = {};
+        }
+        addEventListener(type, listener) {
+            (this.listeners[type] = this.listeners[type] || []).push(listener);
+        }
+        removeEventListener(type, listener) {
+            this.listeners[type] = (this.listeners[type] || []).filter((l) => l !== listener);
+        }
+        dispatchEvent(event) {
+            event.target = event.target || this;
+            const handler = (this.owner || this)['on' + event.type];
+            const listeners = (this.listeners[event.type] || []).slice();
+            if (typeof handler === 'function') {
+                listeners.unshift(handler);
+            }
+            for (const listener of listeners) {
+                try {
+                    typeof listener === 'function' ? listener.call(this, event) : listener.handleEvent(event);
+                } catch (e) {
+                    __host_error(format(e));
+                }
+            }
+            return !event.defaultPrevented;
+        }
+    }
+
+    // Canvas contexts and other APIs the headless runtime does not implement
+    // accept any call and property.
+    const inert = () => new Proxy(function () {}, {
+        get: (target, property) => (property in target ? target[property] : inert()),
+        apply: () => inert(),
+    });
+
+    class Element extends EventTarget {
+        constructor(tagName, id = '') {
+            super();
+            this.tagName = tagName.toUpperCase();
+            this.id = id;
+            this.children = [];
+            this.parentNode = null;
+            this.attributes = {};
+            this.style = {};
+            this.dataset = {};
+            this.textContent = '';
+            this.html = '';
+            this.value = '';
+            this.checked = false;
+            this.disabled = false;
+            const classes = new Set();
+            this.classList = {
+                add: (...names) => names.forEach((name) => classes.add(name)),
+                remove: (...names) => names.forEach((name) => classes.delete(name)),
+                toggle: (name, force) => {
+                    const on = force === undefined ? !classes.has(name) : force;
+                    on ? classes.add(name) : classes.delete(name);
+                    return on;
+                },
+                contains: (name) => classes.has(name),
+            };
+        }
+        get firstChild() {
+            return this.children[0] || null;
+        }
+        get innerHTML() {
+            return this.html;
+        }
+        set innerHTML(html) {
+            this.html = String(html);
+            this.children = [];
+            for (const match of this.html.matchAll(/\bid\s*=\s*["']([^"']+)["']/g)) {
+                ids.add(match[1]);
+            }
+        }
+        insertAdjacentHTML(position, html) {
+            this.innerHTML = this.html + html;
+        }
+        appendChild(child) {
+            child.parentNode = this;
+            this.children.push(child);
+            if (child.id) {
+                byId.set(child.id, child);
+            }
+            return child;
+        }
+        append(...children) {
+            children.forEach((child) => typeof child === 'object' && this.appendChild(child));
+        }
+        prepend(...children) {
+            this.children.unshift(...children.filter((child) => typeof child === 'object'));
+        }
+        insertBefore(child) {
+            return this.appendChild(child);
+        }
+        removeChild(child) {
+            this.children = this.children.filter((c) => c !== child);
+            return child;
+        }
+        replaceChildren(...children) {
+            this.children = [];
+            this.append(...children);
+        }
+        remove() {
+            if (this.parentNode) {
+                this.parentNode.removeChild(this);
+            }
+        }
+        setAttribute(name, value) {
+            this.attributes[name] = String(value);
+        }
+        getAttribute(name) {
+            return name in this.attributes ? this.attributes[name] : null;
+        }
+        hasAttribute(name) {
+            return name in this.attributes;
+        }
+        removeAttribute(name) {
+            delete this.attributes[name];
+        }
+        querySelector(selector) {
+            return document.querySelector(selector);
+        }
+        querySelectorAll() {
+            return [];
+        }
+        closest() {
+            return null;
+        }
+        focus() {}
+        blur() {}
+        scrollIntoView() {}
+        click() {
+            this.dispatchEvent(new Event('click'));
+        }
+        getBoundingClientRect() {
+            return { x: 0, y: 0, top: 0, left: 0, right: 0, bottom: 0, width: 0, height: 0 };
+        }
+        getContext() {
+            return inert();
+        }
+    }
+
+    let ids = new Set();
+    const byId = new Map();
+    const document = new EventTarget();
+    Object.assign(document, {
+        readyState: 'loading',
+        title: '',
+        documentElement: new Element('html'),
+        head: new Element('head'),
+        body: new Element('body'),
+        getElementById(id) {
+            if (!byId.has(id) && ids.has(id)) {
+                byId.set(id, new Element('div', id));
+            }
+            return byId.get(id) || null;
+        },
+        querySelector(selector) {
+            const id = /^#([\w-]+)$/.exec(selector.trim());
+            if (id) {
+                return document.getElementById(id[1]);
+            }
+            return new Element('div');
+        },
+        querySelectorAll() {
+            return [];
+        },
+        getElementsByClassName() {
+            return [];
+        },
+        getElementsByTagName() {
+            return [];
+        },
+        createElement(tagName) {
+            return new Element(tagName);
+        },
+        createTextNode(text) {
+            const node = new Element('#text');
+            node.textContent = String(text);
+            return node;
+        },
+        createDocumentFragment() {
+            return new Element('#fragment');
+        },
+    });
+    global.document = document;
+    global.__set_element_ids = (list) => {
+        ids = new Set(list);
+    };
+
+    const windowTarget = new EventTarget();
+    windowTarget.owner = global;
+    global.window = global;
+    global.self = global;
+    global.addEventListener = windowTarget.addEventListener.bind(windowTarget);
+    global.removeEventListener = windowTarget.removeEventListener.bind(windowTarget);
+    global.dispatchEvent = windowTarget.dispatchEvent.bind(windowTarget);
+    global.location = { href: 'about:blank', protocol: 'about:', reload() {} };
+    global.navigator = { userAgent: 'lsp-agent-headless', language: 'en-US', clipboard: inert() };
+    global.innerWidth = 1024;
+    global.innerHeight = 768;
+    global.getComputedStyle = () => ({ getPropertyValue: () => '' });
+    global.matchMedia = (query) => ({ matches: false, media: query, addEventListener() {}, removeEventListener() {} });
+    global.close = () => __host_close();
+
+    const storage = () => {
+        const items = new Map();
+        return {
+            getItem: (key) => (items.has(key) ? items.get(key) : null),
+            setItem: (key, value) => items.set(key, String(value)),
+            removeItem: (key) => items.delete(key),
+            clear: () => items.clear(),
+        };
+    };
+    global.localStorage = storage();
+    global.sessionStorage = storage();
+
+    global.crypto = {
+        randomUUID: () =>
+            'xxxxxxxx-xxxx-4xxx-yxxx-xxxxxxxxxxxx'.replace(/[xy]/g, (c) => {
+                const r = (Math.random() * 16) | 0;
+                return (c === 'x' ? r : (r & 0x3) | 0x8).toString(16);
+            }),
+        getRandomValues: (array) => {
+            for (let i = 0; i < array.length; i++) {
+                array[i] = (Math.random() * 256) | 0;
+            }
+            return array;
+        },
+    };
+    global.performance = { now: () => Date.now() };
+
+    const timers = new Map();
+    let nextTimer = 1;
+    const schedule = (callback, delay, args, repeat) => {
+        const id = nextTimer++;
+        timers.set(id, { callback, delay: Math.max(0, Number(delay) || 0), args, repeat });
+        __host_timer(id, timers.get(id).delay);
+        return id;
+    };
+    global.setTimeout = (callback, delay, ...args) => schedule(callback, delay, args, false);
+    global.setInterval = (callback, delay, ...args) => schedule(callback, delay, args, true);
+    global.clearTimeout = (id) => timers.delete(id);
+    global.clearInterval = global.clearTimeout;
+    global.requestAnimationFrame = (callback) => schedule(() => callback(Date.now()), 16, [], false);
+    global.cancelAnimationFrame = global.clearTimeout;
+    global.queueMicrotask = (callback) => Promise.resolve().then(callback);
+    global.__fire_timer = (id) => {
+        const timer = timers.get(id);
+        if (!timer) {
+            return;
+        }
+        if (timer.repeat) {
+            __host_timer(id, Math.max(timer.delay, 1));
+        } else {
+            timers.delete(id);
+        }
+        if (typeof timer.callback === 'function') {
+            timer.callback(...timer.args);
+        }
+    };
+
+    class Response {
+        constructor(status, body) {
+            this.status = status;
+            this.ok = status >= 200 && status < 300;
+            this.body = body;
+        }
+        async text() {
+            return this.body;
+        }
+        async json() {
+            return JSON.parse(this.body);
+        }
+    }
+    global.Response = Response;
+
+    const pending = new Map();
+    global.fetch = (input, init = {}) => {
+        const url = String(input && input.url ? input.url : input);
+        if (!url.startsWith('wry://')) {
+            return Promise.reject(new TypeError(`Failed to fetch ${url}: the headless runtime only serves wry:// protocols`));
+        }
+        const protocol = url.slice('wry://'.length).split(/[/?#]/)[0];
+        const body = init.body === undefined || init.body === null ? '' : String(init.body);
+        return new Promise((resolve, reject) => {
+            pending.set(__host_request(protocol, body), { resolve, reject });
+        });
+    };
+    global.__wry_respond = (callId, status, body) => {
+        const call = pending.get(callId);
+        pending.delete(callId);
+        if (call) {
+            call.resolve(new Response(status, body));
+        }
+    };
+
+    global.__load = () => {
+        document.readyState = 'interactive';
+        document.dispatchEvent(new Event('DOMContentLoaded'));
+        global.dispatchEvent(new Event('DOMContentLoaded'));
+        document.readyState = 'complete';
+        global.dispatchEvent(new Event('load'));
+    };
+})();
@@ -0,0 +1,48 @@
+use serde::Serialize;
+
+/// What the headless runtime observed while running an app, written as one
+/// JSON object per line for CI to check.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Entry {
+    /// The app's scripts ran and its `load` event was dispatched.
+    Loaded { app_id: String, scripts: usize },
+    Console {
+        app_id: String,
+        level: String,
+        message: String,
+    },
+    /// An uncaught exception or unhandled promise rejection.
+    Error { app_id: String, message: String },
+    /// A `fetch('wry://<protocol>')` call.
+    Call {
+        app_id: String,
+        protocol: String,
+        body: String,
+    },
+    /// The response the call resolved with.
+    Response {
+        app_id: String,
+        protocol: String,
+        status: u16,
+        body: String,
+    },
+    /// The app called `window.close()`.
+    Closed { app_id: String },
+}
+
+impl Entry {
+    /// The app and message to log with the agent if this is an error, worded
+    /// as the error reporter the other runtimes inject does.
+    pub fn app_error(&self) -> Option<(String, String)> {
+        match self {
+            Entry::Error { app_id, message } => Some((app_id.clone(), message.clone())),
+            Entry::Console {
+                app_id,
+                level,
+                message,
+            } if level == "error" => Some((app_id.clone(), format!("console.error: {}", message))),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::engine::{Command, HostCall};
+use agent::StoreValueBody;
+use agent::pending_replies::PendingReplies;
+use async_trait::async_trait;
+use std::sync::mpsc::Sender;
+use traits::{Web, WebAgent};
+
+/// Hands launched apps to the engine thread and routes the agent's responses
+/// back to the `fetch` calls waiting for them.
+pub struct HeadlessRuntime {
+    commands: Sender<Command>,
+    /// App and call id waiting for each reply, by request id.
+    pending_replies: PendingReplies<(String, u32)>,
+}
+
+impl HeadlessRuntime {
+    pub fn new(commands: Sender<Command>) -> Self {
+        Self {
+            commands,
+            pending_replies: PendingReplies::default(),
+        }
+    }
+
+    fn respond(&self, app_id: String, call_id: u32, status: u16, body: String) {
+        let _ = self.commands.send(Command::Respond {
+            app_id,
+            call_id,
+            status,
+            body,
+        });
+    }
+
+    /// A request id for a call whose reply arrives later, answering the call
+    /// with a timeout if it never does.
+    fn enqueue_reply(&self, app_id: String, call_id: u32) -> String {
+        let commands = self.commands.clone();
+        self.pending_replies
+            .register((app_id, call_id), move |(app_id, call_id)| {
+                let _ = commands.send(Command::Respond {
+                    app_id,
+                    call_id,
+                    status: 504,
+                    body: "No reply arrived in time.".to_string(),
+                });
+            })
+    }
+
+    /// Services an app's call the way the wry runtime's custom protocol handler does.
+    pub async fn serve(&self, agent: &dyn WebAgent, call: HostCall) {
+        let (app_id, call_id, protocol, body) = match call {
+            HostCall::Protocol {
+                app_id,
+                call_id,
+                protocol,
+                body,
+            } => (app_id, call_id, protocol, body),
+            HostCall::Close { app_id } => {
+                agent.close_app(app_id).await;
+                return;
+            }
+        };
+        let respond =
+            |status: u16, body: String| self.respond(app_id.clone(), call_id, status, body);
+        match protocol.as_str() {
+            "inference" => {
+                let request_id = self.enqueue_reply(app_id.clone(), call_id);
+                agent.app_inference_request(request_id, body, app_id).await;
+            }
+            "document" => respond(200, agent.read_document(app_id.clone(), body).await),
+            "selection" => match agent.read_selection(app_id.clone(), body).await {
+                Some(json) => respond(200, json),
+                None => respond(404, String::new()),
+            },
+            "launch_context" => match agent.read_launch_context(app_id.clone()).await {
+                Some(json) => respond(200, json),
+                None => respond(404, String::new()),
+            },
+            "workspace_file" => {
+                let request_id = self.enqueue_reply(app_id.clone(), call_id);
+                agent.read_workspace_file(request_id, app_id, body).await;
+            }
+            "list_directory" => {
+                let request_id = self.enqueue_reply(app_id.clone(), call_id);
+                agent.list_directory(request_id, app_id, body).await;
+            }
+            "propose_edit" => {
+                let request_id = self.enqueue_reply(app_id.clone(), call_id);
+                let proposed = agent
+                    .propose_edit(request_id.clone(), app_id.clone(), body)
+                    .await;
+                if let Err(message) = proposed {
+                    self.pending_replies.take(&request_id);
+                    respond(400, message);
+                }
+            }
+            "diagnostics" => match agent.publish_diagnostics(app_id.clone(), body).await {
+                Ok(()) => respond(200, String::new()),
+                Err(message) => respond(400, message),
+            },
+            "contributions" => match agent.publish_contributions(app_id.clone(), body).await {
+                Ok(()) => respond(200, String::new()),
+                Err(message) => respond(400, message),
+            },
+            "store_value" => match serde_json::from_str::<StoreValueBody>(&body) {
+                Ok(parsed) => {
+                    agent
+                        .store_value(
+                            app_id.clone(),
+                            parsed.key.clone(),
+                            parsed.value,
+                            parsed.description,
+                        )
+                        .await;
+                    respond(200, String::new());
+                    let _ = self.commands.send(Command::StorageUpdated(parsed.key));
+                }
+                Err(e) => respond(400, e.to_string()),
+            },
+            "read_value" => respond(
+                200,
+                agent
+                    .read_value(app_id.clone(), body)
+                    .await
+                    .unwrap_or_default(),
+            ),
+            "log" => {
+                agent.log_error(app_id.clone(), body).await;
+                respond(200, String::new());
+            }
+            _ => respond(404, String::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl Web for HeadlessRuntime {
+    async fn launch_app(&self, id: String, content: String) {
+        let _ = self.commands.send(Command::Launch {
+            app_id: id,
+            html: content,
+        });
+    }
+
+    async fn handle_reply(&self, request_id: String, status: u16, content: String) {
+        match self.pending_replies.take(&request_id) {
+            Some((app_id, call_id)) => self.respond(app_id, call_id, status, content),
+            None => eprintln!("[Headless] Reply for unknown request id: {}", request_id),
+        }
+    }
+}
@@ -5,6 +5,140 @@ use async_trait::async_trait;
 pub trait InferenceClient: Send + Sync {
     async fn inference(&self, request: String, model: Option<String>) -> Result<String, String>;
     async fn notify_shutdown(&self);
+    /// Surfaces a short notification to the user in the editor.
+    async fn show_message(&self, message: String);
+    /// Shows `proposal` to the user for review and applies it if they accept.
+    /// Returns whether the edit was applied.
+    async fn apply_edit(&self, proposal: EditProposal) -> Result<bool, String>;
+    /// Replaces the diagnostics shown for `uri`; an empty list clears them.
+    async fn publish_diagnostics(&self, uri: String, diagnostics: Vec<Diagnostic>);
+    /// Asks the editor to request code lenses again, after apps changed them.
+    async fn refresh_code_lenses(&self);
+}
+
+/// Summary of a named chat session, as listed to the editor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionInfo {
+    pub name: String,
+    pub active: bool,
+    pub history_len: usize,
+    pub apps: usize,
+}
+
+/// Result of importing a session bundle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportedSession {
+    /// Name the session was imported under.
+    pub name: String,
+    /// Keys of bundled stored values that were skipped because a value with the
+    /// same key already exists.
+    pub skipped_values: Vec<String>,
+}
+
+/// A position in a text document as sent by LSP clients: zero-based line and
+/// UTF-16 code unit offset within that line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextPosition {
+    pub line: u32,
+    pub character: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextRange {
+    pub start: TextPosition,
+    pub end: TextPosition,
+}
+
+/// A selection in an editor; `active` is the cursor position.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextSelection {
+    pub anchor: TextPosition,
+    pub active: TextPosition,
+}
+
+/// A range of a document picked by the user, e.g. for a code action.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectedRange {
+    pub uri: String,
+    pub range: TextRange,
+}
+
+/// One content change of a `didChange` notification. A change without a range
+/// replaces the whole document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextChange {
+    pub range: Option<TextRange>,
+    pub text: String,
+}
+
+/// Replaces `range` with `text`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub range: TextRange,
+    pub text: String,
+}
+
+/// Edits to one document proposed by an app, applied only once the user approves.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EditProposal {
+    pub uri: String,
+    /// Shown to the user next to the preview, naming the app that proposed it.
+    pub label: String,
+    pub edits: Vec<TextEdit>,
+    /// Version of the open document the edits were made against, so the editor
+    /// can refuse them once it has changed; `None` when it is not open.
+    pub version: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+/// A problem reported by an app about a range of a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub range: TextRange,
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+    /// Names the app that reported it.
+    pub source: String,
+}
+
+/// A line of text an app shows above `range`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeLens {
+    pub range: TextRange,
+    pub title: String,
+}
+
+/// One turn of the conversation as shown by the editor's chat UI.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatTurn {
+    User(String),
+    Assistant(String),
+}
+
+/// A running app, identified by its id and labelled by its HTML `<title>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppInfo {
+    pub id: String,
+    pub title: String,
+    /// Errors the app reported through `wry://log`, oldest first.
+    pub errors: Vec<String>,
+}
+
+/// An app in the app library, which keeps every launched app after it is closed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryAppInfo {
+    pub id: String,
+    pub title: String,
+    pub prompt: String,
+    pub last_used_ms: u64,
+    pub running: bool,
 }
 
 /// Editor-facing agent API used to synchronize documents and chat requests
@@ -12,27 +146,131 @@ pub trait InferenceClient: Send + Sync {
 #[async_trait]
 pub trait WorkspaceAgent: Send + Sync {
     async fn shutdown(&self);
-    async fn did_open(&self, uri: String, text: String);
-    async fn did_change(&self, uri: String, text: String);
+    async fn did_open(&self, uri: String, language_id: String, version: i32, text: String);
+    async fn did_change(&self, uri: String, version: i32, changes: Vec<TextChange>);
+    async fn did_save(&self, uri: String);
     async fn did_close(&self, uri: String);
     async fn set_active_document(&self, uri: String);
-    async fn chat_request(&self, content: String, model: Option<String>) -> Option<String>;
+    /// Replaces the selections of the editor showing `uri`, primary selection first.
+    async fn set_selections(&self, uri: String, selections: Vec<TextSelection>);
+    /// Markdown that running apps show for `position` in `uri`, if any.
+    async fn hover(&self, uri: String, position: TextPosition) -> Option<String>;
+    /// Code lenses that running apps placed in `uri`.
+    async fn code_lenses(&self, uri: String) -> Vec<CodeLens>;
+    /// Runs a chat turn in `session`, or in the active session when `None`.
+    /// A named session that does not exist yet is created; only `create_session`
+    /// and `switch_session` change which session is active.
+    ///
+    /// When the editor passes the turns it currently displays in `editor_history`,
+    /// the session history is first reconciled with them (e.g. after the user
+    /// deleted a turn). An empty list, from a new thread, leaves it as it is.
+    async fn chat_request(
+        &self,
+        session: Option<String>,
+        content: String,
+        model: Option<String>,
+        editor_history: Option<Vec<ChatTurn>>,
+    ) -> Option<String>;
+    /// Runs a chat turn in the active session asking about the selected text,
+    /// which also becomes the active document and selection apps can read.
+    async fn ask_about_selection(&self, selection: SelectedRange) -> Option<String>;
+    async fn audit_log(&self) -> String;
+    async fn create_session(&self, name: String) -> Result<(), String>;
+    async fn switch_session(&self, name: String) -> Result<(), String>;
+    async fn list_sessions(&self) -> Vec<SessionInfo>;
+    async fn delete_session(&self, name: String) -> Result<(), String>;
+
+    /// Serializes a session (or the active one) with its running apps and the
+    /// stored values they used into a portable bundle.
+    async fn export_session(&self, name: Option<String>) -> Result<Vec<u8>, String>;
+
+    /// Loads a bundle produced by `export_session` as a new active session. Its
+    /// apps go to the app library for the user to relaunch, and existing stored
+    /// values are kept over bundled ones.
+    async fn import_session(&self, bytes: Vec<u8>) -> Result<ImportedSession, String>;
+
+    async fn set_workspace_folders(&self, folders: Vec<String>);
+
+    /// Sets the glob patterns, relative to a workspace folder, of the files apps
+    /// may read. An empty list exposes nothing.
+    async fn set_exposed_files(&self, patterns: Vec<String>) -> Result<(), String>;
+    async fn list_apps(&self) -> Vec<AppInfo>;
+
+    /// Writes a running app's HTML to `.lsp_agent/apps/<name>.html` in the
+    /// workspace and returns the written path.
+    async fn save_app(&self, app_id: String, name: String) -> Result<String, String>;
+    async fn list_saved_apps(&self) -> Vec<String>;
+
+    /// Launches a saved app in the active session and returns its app id. When
+    /// launched on a `selection`, the app can read it through `wry://launch_context`.
+    async fn launch_saved_app(
+        &self,
+        name: String,
+        selection: Option<SelectedRange>,
+    ) -> Result<String, String>;
+
+    async fn app_library(&self) -> Vec<LibraryAppInfo>;
+
+    /// Reopens a closed library app under its original id in the active session.
+    async fn relaunch_app(&self, app_id: String) -> Result<(), String>;
 }
 
 /// Web client-facing agent API used to enqueue requests into the shared document
 /// for server-side handling.
+///
+/// Calls answered later through `Web::handle_reply` take the `request_id` the
+/// reply will carry. Callers register for the reply before making the call, as
+/// it may arrive before the call returns.
 #[async_trait]
 pub trait WebAgent: Send + Sync {
-    async fn app_inference_request(&self, content: String, app_id: String);
-    async fn read_document(&self, uri: String) -> String;
+    /// Queues an inference request; concurrent requests of an app are answered
+    /// independently of the order they complete in.
+    async fn app_inference_request(&self, request_id: String, content: String, app_id: String);
+    async fn read_document(&self, app_id: String, uri: String) -> String;
+    /// JSON description of the cursor and selected text in the editor showing
+    /// `uri` (the active document when empty), or `None` if it is not open.
+    async fn read_selection(&self, app_id: String, uri: String) -> Option<String>;
+    /// Like `read_selection`, for the selection the app was launched on, or
+    /// `None` if it was not launched on one or the document was closed.
+    async fn read_launch_context(&self, app_id: String) -> Option<String>;
     async fn close_app(&self, app_id: String);
-    async fn store_value(&self, key: String, value: String, description: String);
-    async fn read_value(&self, key: String) -> Option<String>;
+    async fn store_value(&self, app_id: String, key: String, value: String, description: String);
+    async fn read_value(&self, app_id: String, key: String) -> Option<String>;
+
+    /// Asks the server for an exposed workspace file.
+    async fn read_workspace_file(&self, request_id: String, app_id: String, path: String);
+
+    /// Like `read_workspace_file`, for a JSON listing of an exposed directory.
+    async fn list_directory(&self, request_id: String, app_id: String, path: String);
+
+    /// Queues the edits in `body` (JSON, see the `wry://propose_edit` protocol) for
+    /// the user to review. Fails, with no reply to follow, if the body is malformed.
+    async fn propose_edit(
+        &self,
+        request_id: String,
+        app_id: String,
+        body: String,
+    ) -> Result<(), String>;
+
+    /// Replaces the app's diagnostics for the document in `body` (JSON, see the
+    /// `wry://diagnostics` protocol). Fails if the body is malformed or the
+    /// document is not open.
+    async fn publish_diagnostics(&self, app_id: String, body: String) -> Result<(), String>;
+
+    /// Replaces the app's hovers and code lenses for the document in `body` (JSON,
+    /// see the `wry://contributions` protocol). Fails if the body is malformed or
+    /// the document is not open.
+    async fn publish_contributions(&self, app_id: String, body: String) -> Result<(), String>;
+
+    /// Records an error the app hit (a `console.error` call, an uncaught
+    /// exception or an unhandled rejection, see the `wry://log` protocol), so
+    /// the agent can see why it failed.
+    async fn log_error(&self, app_id: String, message: String);
 }
 
 /// Web UI bridge used to apply responses from the shared document to the webview.
 #[async_trait]
 pub trait Web: Send + Sync {
     async fn launch_app(&self, id: String, content: String);
-    async fn handle_inference_response(&self, app_id: String, content: String);
+    async fn handle_reply(&self, request_id: String, status: u16, content: String);
 }
@@ -1,5 +1,5 @@
 import * as path from 'path';
-import { workspace, ExtensionContext, window, lm, LanguageModelChatMessage, CancellationTokenSource, chat } from 'vscode';
+import { workspace, ExtensionContext, window, lm, LanguageModelChatMessage, CancellationTokenSource, chat, commands, ChatContext, ChatRequestTurn, ChatResponseTurn, ChatResponseMarkdownPart } from 'vscode';
 import {
   LanguageClient,
   LanguageClientOptions,
@@ -11,6 +11,74 @@ import * as fs from 'fs';
 
 let client: LanguageClient;
 
+const CHAT_PARTICIPANT_ID = "lsp-agent.chat";
+
+interface SessionInfo {
+  name: string;
+  active: boolean;
+  historyLength: number;
+  apps: number;
+}
+
+interface ImportedSession {
+  name: string;
+  skippedValues: string[];
+}
+
+// Each chat thread has its own server-side session, as the thread's turns are the
+// session history. The session name is kept in the result metadata of our
+// responses, so a thread with no previous response is new and gets a new session.
+function sessionForThread(context: ChatContext): string {
+  for (let i = context.history.length - 1; i >= 0; i--) {
+    const turn = context.history[i];
+    if (turn instanceof ChatResponseTurn && turn.participant === CHAT_PARTICIPANT_ID) {
+      const session = turn.result.metadata?.session;
+      if (typeof session === 'string' && session.length > 0) {
+        return session;
+      }
+    }
+  }
+  return `chat-${Date.now().toString(36)}`;
+}
+
+interface AppInfo {
+  id: string;
+  title: string;
+  errors: string[];
+}
+
+interface LibraryApp {
+  id: string;
+  title: string;
+  prompt: string;
+  lastUsed: number;
+  running: boolean;
+}
+
+interface ChatTurnPayload {
+  role: 'user' | 'assistant';
+  content: string;
+}
+
+// The turns of this thread as the user currently sees them, so the server can
+// drop turns that were deleted and pick up ones it never recorded.
+function historyForServer(context: ChatContext): ChatTurnPayload[] {
+  const turns: ChatTurnPayload[] = [];
+  for (const turn of context.history) {
+    if (turn instanceof ChatRequestTurn) {
+      turns.push({ role: 'user', content: turn.prompt });
+    } else if (turn instanceof ChatResponseTurn) {
+      const content = turn.response
+        .filter((part): part is ChatResponseMarkdownPart => part instanceof ChatResponseMarkdownPart)
+        .map(part => part.value.value)
+        .join('')
+        .trim();
+      turns.push({ role: 'assistant', content });
+    }
+  }
+  return turns;
+}
+
 export async function activate(context: ExtensionContext) {
   const serverPathCandidates = [
     path.join(context.extensionPath, '../../target/debug/server'),
@@ -48,7 +116,14 @@ export async function activate(context: ExtensionContext) {
     outputChannel: outputChannel, // <--- Use the same output channel for the server logs
     synchronize: {
       // Notify the server about file changes to '.clientrc files contained in the workspace
-      fileEvents: workspace.createFileSystemWatcher('**/.clientrc')
+      fileEvents: workspace.createFileSystemWatcher('**/.clientrc'),
+      // Sends lspAgent.* settings changes, e.g. exposedFiles, as didChangeConfiguration
+      configurationSection: 'lspAgent'
+    },
+    initializationOptions: {
+      exposedFiles: workspace.getConfiguration('lspAgent').get<string[]>('exposedFiles', []),
+      // Inference runs through Copilot here, see the custom/inference handler below.
+      clientInference: true
     }
   };
 
@@ -91,6 +166,28 @@ export async function activate(context: ExtensionContext) {
       }
     });
     
+    // Selections change on every cursor move, so only the latest one after a
+    // short pause is sent.
+    let selectionTimer: NodeJS.Timeout | undefined;
+    window.onDidChangeTextEditorSelection(event => {
+      if (selectionTimer) {
+        clearTimeout(selectionTimer);
+      }
+      selectionTimer = setTimeout(() => {
+        const uri = event.textEditor.document.uri.toString();
+        const selections = event.selections.map(selection => ({
+          anchor: { line: selection.anchor.line, character: selection.anchor.character },
+          active: { line: selection.active.line, character: selection.active.character }
+        }));
+        client.sendRequest("workspace/executeCommand", {
+            command: "lsp-agent.selection",
+            arguments: [uri, selections]
+        }).catch(err => {
+            outputChannel.appendLine(`[LSP Agent] Failed to update selection: ${err}`);
+        });
+      }, 150);
+    });
+
     if (window.activeTextEditor && window.activeTextEditor.document) {
         const uri = window.activeTextEditor.document.uri.toString();
         client.sendRequest("workspace/executeCommand", {
@@ -157,7 +254,28 @@ export async function activate(context: ExtensionContext) {
     return null;
   }
 
-  const chatParticipant = chat.createChatParticipant("lsp-agent.chat", async (request, context, response, token) => {
+  async function runServerCommand(command: string, args: any[]): Promise<any> {
+    const initError = await ensureClient();
+    if (initError) {
+      throw new Error(initError);
+    }
+    return client.sendRequest("workspace/executeCommand", { command, arguments: args });
+  }
+
+  async function pickSession(placeHolder: string): Promise<string | undefined> {
+    const sessions: SessionInfo[] = (await runServerCommand("lsp-agent.session-list", [])) || [];
+    const picked = await window.showQuickPick(
+      sessions.map(s => ({
+        label: s.name,
+        description: s.active ? 'active' : undefined,
+        detail: `${s.historyLength} history entries, ${s.apps} running apps`
+      })),
+      { placeHolder }
+    );
+    return picked?.label;
+  }
+
+  const chatParticipant = chat.createChatParticipant(CHAT_PARTICIPANT_ID, async (request, context, response, token) => {
     const initError = await ensureClient();
     if (initError) {
       response.markdown(`\n\n${initError}`);
@@ -165,17 +283,20 @@ export async function activate(context: ExtensionContext) {
     }
     const userPrompt = request.prompt;
     const modelId = request.model.id;
+    const history = historyForServer(context);
     
     try {
+      const session = sessionForThread(context);
       const result = await client.sendRequest("workspace/executeCommand", { 
             command: "lsp-agent.log-chat", 
-            arguments: [userPrompt, modelId] 
+            arguments: [userPrompt, modelId, session, history] 
         });
       if (typeof result === 'string' && result.length > 0) {
         response.markdown(`\n\n${result}`);
       } else {
         response.markdown(`\n\nRequest processed by server.`);
       }
+      return { metadata: { session } };
     } catch (err) {
         response.markdown(`\n\nFailed to send request: ${err}`);
     }
@@ -183,6 +304,166 @@ export async function activate(context: ExtensionContext) {
 
   context.subscriptions.push(chatParticipant);
 
+  context.subscriptions.push(commands.registerCommand("lspAgent.showAudit", async () => {
+    try {
+      const log = await runServerCommand("lsp-agent.show-audit", []);
+      const doc = await workspace.openTextDocument({
+        content: typeof log === 'string' ? log : '',
+        language: 'markdown'
+      });
+      await window.showTextDocument(doc, { preview: true });
+    } catch (err) {
+      window.showErrorMessage(`Failed to load audit log: ${err}`);
+    }
+  }));
+
+  context.subscriptions.push(commands.registerCommand("lspAgent.newSession", async () => {
+    const name = await window.showInputBox({ prompt: "Name of the new agent session" });
+    if (!name) {
+      return;
+    }
+    try {
+      await runServerCommand("lsp-agent.session-create", [name]);
+      window.showInformationMessage(`Agent session '${name}' created and activated.`);
+    } catch (err) {
+      window.showErrorMessage(`Failed to create session: ${err}`);
+    }
+  }));
+
+  context.subscriptions.push(commands.registerCommand("lspAgent.switchSession", async () => {
+    try {
+      const name = await pickSession("Select the agent session to activate");
+      if (name) {
+        await runServerCommand("lsp-agent.session-switch", [name]);
+      }
+    } catch (err) {
+      window.showErrorMessage(`Failed to switch session: ${err}`);
+    }
+  }));
+
+  context.subscriptions.push(commands.registerCommand("lspAgent.deleteSession", async () => {
+    try {
+      const name = await pickSession("Select the agent session to delete");
+      if (name) {
+        await runServerCommand("lsp-agent.session-delete", [name]);
+      }
+    } catch (err) {
+      window.showErrorMessage(`Failed to delete session: ${err}`);
+    }
+  }));
+
+  context.subscriptions.push(commands.registerCommand("lspAgent.exportSession", async () => {
+    try {
+      const name = await pickSession("Select the agent session to export");
+      if (!name) {
+        return;
+      }
+      const target = await window.showSaveDialog({
+        defaultUri: workspace.workspaceFolders?.[0]?.uri.with({
+          path: path.posix.join(workspace.workspaceFolders[0].uri.path, `${name}.lsp-agent`)
+        }),
+        filters: { 'LSP Agent session': ['lsp-agent'] }
+      });
+      if (target) {
+        await runServerCommand("lsp-agent.export-session", [target.fsPath, name]);
+        window.showInformationMessage(`Agent session '${name}' exported to ${target.fsPath}.`);
+      }
+    } catch (err) {
+      window.showErrorMessage(`Failed to export session: ${err}`);
+    }
+  }));
+
+  context.subscriptions.push(commands.registerCommand("lspAgent.importSession", async () => {
+    try {
+      const picked = await window.showOpenDialog({
+        canSelectMany: false,
+        filters: { 'LSP Agent session': ['lsp-agent'] }
+      });
+      if (picked && picked.length > 0) {
+        const imported: ImportedSession = await runServerCommand("lsp-agent.import-session", [picked[0].fsPath]);
+        window.showInformationMessage(
+          `Agent session imported as '${imported.name}'. Reopen its apps with 'LSP Agent: Reopen App from Library'.`
+        );
+        if (imported.skippedValues.length > 0) {
+          window.showWarningMessage(
+            `Kept existing stored values instead of the bundled ones for: ${imported.skippedValues.join(', ')}.`
+          );
+        }
+      }
+    } catch (err) {
+      window.showErrorMessage(`Failed to import session: ${err}`);
+    }
+  }));
+
+  context.subscriptions.push(commands.registerCommand("lspAgent.saveApp", async () => {
+    try {
+      const apps: AppInfo[] = (await runServerCommand("lsp-agent.list-apps", [])) || [];
+      if (apps.length === 0) {
+        window.showInformationMessage("No apps are running.");
+        return;
+      }
+      const picked = await window.showQuickPick(
+        apps.map(app => ({ label: app.title, description: app.id, id: app.id })),
+        { placeHolder: "Select the app to save" }
+      );
+      if (!picked) {
+        return;
+      }
+      const name = await window.showInputBox({
+        prompt: "File name for the app in .lsp_agent/apps",
+        value: picked.label
+      });
+      if (!name) {
+        return;
+      }
+      const savedPath = await runServerCommand("lsp-agent.save-app", [picked.id, name]);
+      window.showInformationMessage(`App saved to ${savedPath}.`);
+    } catch (err) {
+      window.showErrorMessage(`Failed to save app: ${err}`);
+    }
+  }));
+
+  context.subscriptions.push(commands.registerCommand("lspAgent.launchSavedApp", async () => {
+    try {
+      const names: string[] = (await runServerCommand("lsp-agent.list-saved-apps", [])) || [];
+      if (names.length === 0) {
+        window.showInformationMessage("No saved apps found in .lsp_agent/apps.");
+        return;
+      }
+      const name = await window.showQuickPick(names, { placeHolder: "Select the saved app to launch" });
+      if (name) {
+        await runServerCommand("lsp-agent.launch-saved-app", [name]);
+      }
+    } catch (err) {
+      window.showErrorMessage(`Failed to launch app: ${err}`);
+    }
+  }));
+
+  context.subscriptions.push(commands.registerCommand("lspAgent.reopenApp", async () => {
+    try {
+      const library: LibraryApp[] = (await runServerCommand("lsp-agent.list-app-library", [])) || [];
+      const closed = library.filter(app => !app.running);
+      if (closed.length === 0) {
+        window.showInformationMessage("The app library has no closed apps.");
+        return;
+      }
+      const picked = await window.showQuickPick(
+        closed.map(app => ({
+          label: app.title,
+          description: new Date(app.lastUsed).toLocaleString(),
+          detail: app.prompt,
+          id: app.id
+        })),
+        { placeHolder: "Select the app to reopen", matchOnDetail: true }
+      );
+      if (picked) {
+        await runServerCommand("lsp-agent.relaunch-app", [picked.id]);
+      }
+    } catch (err) {
+      window.showErrorMessage(`Failed to reopen app: ${err}`);
+    }
+  }));
+
   if (serverAvailable) {
     await ensureClient();
   }
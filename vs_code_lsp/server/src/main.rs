@@ -1,10 +1,18 @@
+use agent::http_inference::HttpInference;
 use agent::start_infra;
 use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use tower_lsp::jsonrpc::Result as LspResult;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tower_lsp::jsonrpc::{Error, Result as LspResult};
 use tower_lsp::lsp_types::*;
 use tower_lsp::{Client, LanguageServer, LspService, Server};
-use traits::{InferenceClient, WorkspaceAgent};
+use traits::{
+    ChatTurn, Diagnostic as AppDiagnostic, DiagnosticSeverity as AppSeverity, EditProposal,
+    InferenceClient, SelectedRange, TextChange, TextPosition, TextRange, TextSelection,
+    WorkspaceAgent,
+};
 
 struct InferenceLspRequest;
 
@@ -32,8 +40,23 @@ impl tower_lsp::lsp_types::notification::Notification for ShutdownExtension {
     const METHOD: &'static str = "lsp-agent/shutdown";
 }
 
+/// One selection as sent with `lsp-agent.selection`.
+#[derive(Deserialize)]
+struct SelectionArg {
+    anchor: Position,
+    active: Position,
+}
+
 struct LspAgentClient {
     client: Client,
+    /// Set when the editor answers `custom/inference` itself, as the VS Code
+    /// extension does.
+    client_inference: Arc<AtomicBool>,
+    /// Used by editors that cannot run inference.
+    builtin_inference: Option<HttpInference>,
+    /// Set when the editor takes versioned document changes with change
+    /// annotations, which app edits need so the user confirms them.
+    confirmed_edits: Arc<AtomicBool>,
 }
 
 #[async_trait::async_trait]
@@ -43,6 +66,16 @@ impl InferenceClient for LspAgentClient {
         request: String,
         model: Option<String>,
     ) -> std::result::Result<String, String> {
+        if !self.client_inference.load(Ordering::Relaxed) {
+            return match &self.builtin_inference {
+                Some(inference) => inference.complete(request, model).await,
+                None => Err(
+                    "This editor cannot run inference; set LSP_AGENT_INFERENCE_URL to use \
+                    an OpenAI-compatible API instead."
+                        .to_string(),
+                ),
+            };
+        }
         let params = InferenceParams { request, model };
         match self
             .client
@@ -57,31 +90,165 @@ impl InferenceClient for LspAgentClient {
     async fn notify_shutdown(&self) {
         let _ = self.client.send_notification::<ShutdownExtension>(()).await;
     }
+
+    async fn show_message(&self, message: String) {
+        self.client
+            .show_message(MessageType::WARNING, message)
+            .await;
+    }
+
+    async fn apply_edit(&self, proposal: EditProposal) -> std::result::Result<bool, String> {
+        if !self.confirmed_edits.load(Ordering::Relaxed) {
+            return Err(
+                "This editor cannot ask the user to confirm edits, so apps may not edit documents."
+                    .to_string(),
+            );
+        }
+        let edit = workspace_edit(proposal)?;
+        match self.client.apply_edit(edit).await {
+            Ok(response) => Ok(response.applied),
+            Err(e) => Err(format!("{:?}", e)),
+        }
+    }
+
+    async fn publish_diagnostics(&self, uri: String, diagnostics: Vec<AppDiagnostic>) {
+        let Ok(uri) = Url::parse(&uri) else {
+            return;
+        };
+        let diagnostics = diagnostics
+            .into_iter()
+            .map(|diagnostic| Diagnostic {
+                range: Range {
+                    start: lsp_position(diagnostic.range.start),
+                    end: lsp_position(diagnostic.range.end),
+                },
+                severity: Some(match diagnostic.severity {
+                    AppSeverity::Error => DiagnosticSeverity::ERROR,
+                    AppSeverity::Warning => DiagnosticSeverity::WARNING,
+                    AppSeverity::Information => DiagnosticSeverity::INFORMATION,
+                    AppSeverity::Hint => DiagnosticSeverity::HINT,
+                }),
+                source: Some(diagnostic.source),
+                message: diagnostic.message,
+                ..Default::default()
+            })
+            .collect();
+        self.client
+            .publish_diagnostics(uri, diagnostics, None)
+            .await;
+    }
+
+    async fn refresh_code_lenses(&self) {
+        // Clients without refresh support pick up changes on their next request.
+        let _ = self.client.code_lens_refresh().await;
+    }
 }
 
 struct Backend {
     client: Client,
     agent: Box<dyn WorkspaceAgent>,
+    client_inference: Arc<AtomicBool>,
+    confirmed_edits: Arc<AtomicBool>,
+    /// Whether the editor can open the chat transcript through `window/showDocument`.
+    show_document: AtomicBool,
+    /// Set once the editor reports its active document through `lsp-agent.active-doc`;
+    /// until then the last opened or changed document is taken as active.
+    explicit_active_document: AtomicBool,
+    inferred_active_document: std::sync::Mutex<Option<String>>,
+    /// Markdown file that `lsp-agent.chat` appends each turn to, in the user's
+    /// cache directory; `None` when the platform has none.
+    chat_transcript: Option<PathBuf>,
+    /// Set once the transcript has been created by this server.
+    chat_transcript_created: AtomicBool,
 }
 
 #[tower_lsp::async_trait]
 impl LanguageServer for Backend {
-    async fn initialize(&self, _: InitializeParams) -> LspResult<InitializeResult> {
+    async fn initialize(&self, params: InitializeParams) -> LspResult<InitializeResult> {
         self.client
             .log_message(MessageType::INFO, "Server initializing...")
             .await;
+        self.agent
+            .set_workspace_folders(workspace_folder_paths(&params))
+            .await;
+        if let Some(patterns) = params
+            .initialization_options
+            .as_ref()
+            .and_then(exposed_file_patterns)
+        {
+            self.set_exposed_files(patterns).await;
+        }
+        let client_inference = params
+            .initialization_options
+            .as_ref()
+            .and_then(|options| options.get("clientInference"))
+            .and_then(|value| value.as_bool())
+            .unwrap_or(false);
+        self.client_inference
+            .store(client_inference, Ordering::Relaxed);
+        let show_document = params
+            .capabilities
+            .window
+            .as_ref()
+            .and_then(|window| window.show_document.as_ref())
+            .is_some_and(|capability| capability.support);
+        self.show_document.store(show_document, Ordering::Relaxed);
+        let workspace_edit = params
+            .capabilities
+            .workspace
+            .as_ref()
+            .and_then(|workspace| workspace.workspace_edit.as_ref());
+        let confirmed_edits = workspace_edit.is_some_and(|capability| {
+            capability.document_changes == Some(true)
+                && capability.change_annotation_support.is_some()
+        });
+        self.confirmed_edits
+            .store(confirmed_edits, Ordering::Relaxed);
         Ok(InitializeResult {
             capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Kind(
-                    TextDocumentSyncKind::FULL,
+                text_document_sync: Some(TextDocumentSyncCapability::Options(
+                    TextDocumentSyncOptions {
+                        open_close: Some(true),
+                        change: Some(TextDocumentSyncKind::INCREMENTAL),
+                        save: Some(TextDocumentSyncSaveOptions::Supported(true)),
+                        ..Default::default()
+                    },
                 )),
+                code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+                hover_provider: Some(HoverProviderCapability::Simple(true)),
+                code_lens_provider: Some(CodeLensOptions {
+                    resolve_provider: Some(false),
+                }),
                 execute_command_provider: Some(ExecuteCommandOptions {
                     commands: vec![
                         "lsp-agent.log-chat".to_string(),
+                        "lsp-agent.chat".to_string(),
                         "lsp-agent.active-doc".to_string(),
+                        "lsp-agent.selection".to_string(),
+                        "lsp-agent.ask-selection".to_string(),
+                        "lsp-agent.show-audit".to_string(),
+                        "lsp-agent.session-create".to_string(),
+                        "lsp-agent.session-switch".to_string(),
+                        "lsp-agent.session-list".to_string(),
+                        "lsp-agent.session-delete".to_string(),
+                        "lsp-agent.export-session".to_string(),
+                        "lsp-agent.import-session".to_string(),
+                        "lsp-agent.list-apps".to_string(),
+                        "lsp-agent.save-app".to_string(),
+                        "lsp-agent.list-saved-apps".to_string(),
+                        "lsp-agent.launch-saved-app".to_string(),
+                        "lsp-agent.list-app-library".to_string(),
+                        "lsp-agent.relaunch-app".to_string(),
                     ],
                     ..Default::default()
                 }),
+                workspace: Some(WorkspaceServerCapabilities {
+                    workspace_folders: Some(WorkspaceFoldersServerCapabilities {
+                        supported: Some(true),
+                        change_notifications: Some(OneOf::Left(true)),
+                    }),
+                    file_operations: None,
+                }),
                 ..Default::default()
             },
             ..Default::default()
@@ -100,16 +267,41 @@ impl LanguageServer for Backend {
     }
 
     async fn did_open(&self, params: DidOpenTextDocumentParams) {
-        let uri = params.text_document.uri.to_string();
-        let text = params.text_document.text;
-        self.agent.did_open(uri, text).await;
+        let document = params.text_document;
+        let uri = document.uri.to_string();
+        self.agent
+            .did_open(
+                uri.clone(),
+                document.language_id,
+                document.version,
+                document.text,
+            )
+            .await;
+        self.infer_active_document(uri).await;
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
         let uri = params.text_document.uri.to_string();
-        if let Some(change) = params.content_changes.last() {
-            self.agent.did_change(uri, change.text.clone()).await;
-        }
+        let changes = params
+            .content_changes
+            .into_iter()
+            .map(|change| TextChange {
+                range: change.range.map(|range| TextRange {
+                    start: text_position(range.start),
+                    end: text_position(range.end),
+                }),
+                text: change.text,
+            })
+            .collect();
+        self.agent
+            .did_change(uri.clone(), params.text_document.version, changes)
+            .await;
+        self.infer_active_document(uri).await;
+    }
+
+    async fn did_save(&self, params: DidSaveTextDocumentParams) {
+        let uri = params.text_document.uri.to_string();
+        self.agent.did_save(uri).await;
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
@@ -117,6 +309,90 @@ impl LanguageServer for Backend {
         self.agent.did_close(uri).await;
     }
 
+    async fn did_change_workspace_folders(&self, _: DidChangeWorkspaceFoldersParams) {
+        if let Ok(Some(folders)) = self.client.workspace_folders().await {
+            let paths = folders
+                .iter()
+                .filter_map(|folder| folder.uri.to_file_path().ok())
+                .map(|path| path.display().to_string())
+                .collect();
+            self.agent.set_workspace_folders(paths).await;
+        }
+    }
+
+    async fn did_change_configuration(&self, params: DidChangeConfigurationParams) {
+        let settings = params.settings.get("lspAgent").unwrap_or(&params.settings);
+        if let Some(patterns) = exposed_file_patterns(settings) {
+            self.set_exposed_files(patterns).await;
+        }
+    }
+
+    async fn hover(&self, params: HoverParams) -> LspResult<Option<Hover>> {
+        let position = params.text_document_position_params;
+        let contents = self
+            .agent
+            .hover(
+                position.text_document.uri.to_string(),
+                text_position(position.position),
+            )
+            .await;
+        Ok(contents.map(|value| Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: None,
+        }))
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> LspResult<Option<Vec<CodeLens>>> {
+        let lenses = self
+            .agent
+            .code_lenses(params.text_document.uri.to_string())
+            .await
+            .into_iter()
+            .map(|lens| CodeLens {
+                range: Range {
+                    start: lsp_position(lens.range.start),
+                    end: lsp_position(lens.range.end),
+                },
+                // Lenses from apps are labels; a command without an id is not clickable.
+                command: Some(Command {
+                    title: lens.title,
+                    command: String::new(),
+                    arguments: None,
+                }),
+                data: None,
+            })
+            .collect();
+        Ok(Some(lenses))
+    }
+
+    async fn code_action(&self, params: CodeActionParams) -> LspResult<Option<CodeActionResponse>> {
+        if params.range.start == params.range.end {
+            return Ok(None);
+        }
+        let arguments = vec![
+            serde_json::json!(params.text_document.uri),
+            serde_json::json!(params.range),
+        ];
+        let mut actions = vec![CodeActionOrCommand::Command(Command {
+            title: "Ask web-agent about this selection".to_string(),
+            command: "lsp-agent.ask-selection".to_string(),
+            arguments: Some(arguments.clone()),
+        })];
+        for name in self.agent.list_saved_apps().await {
+            let mut arguments = arguments.clone();
+            arguments.insert(0, serde_json::Value::String(name.clone()));
+            actions.push(CodeActionOrCommand::Command(Command {
+                title: format!("Open saved app '{}' on this selection", name),
+                command: "lsp-agent.launch-saved-app".to_string(),
+                arguments: Some(arguments),
+            }));
+        }
+        Ok(Some(actions))
+    }
+
     async fn execute_command(
         &self,
         params: ExecuteCommandParams,
@@ -130,7 +406,20 @@ impl LanguageServer for Backend {
                         .get(1)
                         .and_then(|v| v.as_str())
                         .map(|s| s.to_string());
-                    let response = self.agent.chat_request(user_input, model).await;
+                    let session = params
+                        .arguments
+                        .get(2)
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string());
+                    let editor_history = params
+                        .arguments
+                        .get(3)
+                        .and_then(|v| v.as_array())
+                        .map(|turns| turns.iter().filter_map(parse_chat_turn).collect());
+                    let response = self
+                        .agent
+                        .chat_request(session, user_input, model, editor_history)
+                        .await;
                     if let Some(message) = response {
                         return Ok(Some(serde_json::Value::String(message)));
                     }
@@ -138,28 +427,439 @@ impl LanguageServer for Backend {
                 }
                 Ok(None)
             }
+            "lsp-agent.chat" => {
+                let Some(message) = params.arguments.first().and_then(|v| v.as_str()) else {
+                    return Err(Error::invalid_params("Expected a message."));
+                };
+                let response = self
+                    .agent
+                    .chat_request(None, message.to_string(), None, None)
+                    .await;
+                let reply = response.as_deref().unwrap_or("The agent did not reply.");
+                self.show_chat_turn(message, reply).await;
+                Ok(response.map(serde_json::Value::String))
+            }
             "lsp-agent.active-doc" => {
                 if let Some(uri) = params.arguments.first().and_then(|v| v.as_str()) {
+                    self.explicit_active_document.store(true, Ordering::Relaxed);
                     self.agent.set_active_document(uri.to_string()).await;
                 }
                 Ok(None)
             }
+            "lsp-agent.selection" => {
+                let uri = params.arguments.first().and_then(|v| v.as_str());
+                let selections = params
+                    .arguments
+                    .get(1)
+                    .cloned()
+                    .and_then(|v| serde_json::from_value::<Vec<SelectionArg>>(v).ok());
+                if let (Some(uri), Some(selections)) = (uri, selections) {
+                    let selections = selections
+                        .into_iter()
+                        .map(|selection| TextSelection {
+                            anchor: text_position(selection.anchor),
+                            active: text_position(selection.active),
+                        })
+                        .collect();
+                    self.agent.set_selections(uri.to_string(), selections).await;
+                }
+                Ok(None)
+            }
+            "lsp-agent.ask-selection" => {
+                let Some(selection) = selected_range(&params.arguments) else {
+                    return Err(Error::invalid_params("Expected a uri and a range."));
+                };
+                let response = self.agent.ask_about_selection(selection).await;
+                if let Some(message) = &response {
+                    self.client
+                        .show_message(MessageType::INFO, message.clone())
+                        .await;
+                }
+                Ok(response.map(serde_json::Value::String))
+            }
+            "lsp-agent.show-audit" => {
+                let log = self.agent.audit_log().await;
+                Ok(Some(serde_json::Value::String(log)))
+            }
+            "lsp-agent.session-create"
+            | "lsp-agent.session-switch"
+            | "lsp-agent.session-delete" => {
+                let Some(name) = params.arguments.first().and_then(|v| v.as_str()) else {
+                    return Err(Error::invalid_params("Expected a session name."));
+                };
+                let name = name.to_string();
+                let result = match params.command.as_str() {
+                    "lsp-agent.session-create" => self.agent.create_session(name).await,
+                    "lsp-agent.session-switch" => self.agent.switch_session(name).await,
+                    _ => self.agent.delete_session(name).await,
+                };
+                result.map_err(Error::invalid_params)?;
+                Ok(None)
+            }
+            "lsp-agent.session-list" => {
+                let sessions: Vec<serde_json::Value> = self
+                    .agent
+                    .list_sessions()
+                    .await
+                    .into_iter()
+                    .map(|session| {
+                        serde_json::json!({
+                            "name": session.name,
+                            "active": session.active,
+                            "historyLength": session.history_len,
+                            "apps": session.apps,
+                        })
+                    })
+                    .collect();
+                Ok(Some(serde_json::Value::Array(sessions)))
+            }
+            "lsp-agent.export-session" => {
+                let Some(path) = params.arguments.first().and_then(|v| v.as_str()) else {
+                    return Err(Error::invalid_params("Expected a file path."));
+                };
+                let session = params
+                    .arguments
+                    .get(1)
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string());
+                let bytes = self
+                    .agent
+                    .export_session(session)
+                    .await
+                    .map_err(Error::invalid_params)?;
+                tokio::fs::write(path, bytes).await.map_err(|e| {
+                    Error::invalid_params(format!("Failed to write {}: {}", path, e))
+                })?;
+                Ok(None)
+            }
+            "lsp-agent.import-session" => {
+                let Some(path) = params.arguments.first().and_then(|v| v.as_str()) else {
+                    return Err(Error::invalid_params("Expected a file path."));
+                };
+                let bytes = tokio::fs::read(path).await.map_err(|e| {
+                    Error::invalid_params(format!("Failed to read {}: {}", path, e))
+                })?;
+                let imported = self
+                    .agent
+                    .import_session(bytes)
+                    .await
+                    .map_err(Error::invalid_params)?;
+                Ok(Some(serde_json::json!({
+                    "name": imported.name,
+                    "skippedValues": imported.skipped_values,
+                })))
+            }
+            "lsp-agent.list-apps" => {
+                let apps: Vec<serde_json::Value> = self
+                    .agent
+                    .list_apps()
+                    .await
+                    .into_iter()
+                    .map(|app| {
+                        serde_json::json!({ "id": app.id, "title": app.title, "errors": app.errors })
+                    })
+                    .collect();
+                Ok(Some(serde_json::Value::Array(apps)))
+            }
+            "lsp-agent.save-app" => {
+                let (Some(app_id), Some(name)) = (
+                    params.arguments.first().and_then(|v| v.as_str()),
+                    params.arguments.get(1).and_then(|v| v.as_str()),
+                ) else {
+                    return Err(Error::invalid_params("Expected an app id and a name."));
+                };
+                let path = self
+                    .agent
+                    .save_app(app_id.to_string(), name.to_string())
+                    .await
+                    .map_err(Error::invalid_params)?;
+                Ok(Some(serde_json::Value::String(path)))
+            }
+            "lsp-agent.list-saved-apps" => {
+                let names = self.agent.list_saved_apps().await;
+                Ok(Some(serde_json::json!(names)))
+            }
+            "lsp-agent.launch-saved-app" => {
+                let Some(name) = params.arguments.first().and_then(|v| v.as_str()) else {
+                    return Err(Error::invalid_params("Expected a saved app name."));
+                };
+                let selection = selected_range(&params.arguments[1..]);
+                let app_id = self
+                    .agent
+                    .launch_saved_app(name.to_string(), selection)
+                    .await
+                    .map_err(Error::invalid_params)?;
+                Ok(Some(serde_json::Value::String(app_id)))
+            }
+            "lsp-agent.list-app-library" => {
+                let apps: Vec<serde_json::Value> = self
+                    .agent
+                    .app_library()
+                    .await
+                    .into_iter()
+                    .map(|app| {
+                        serde_json::json!({
+                            "id": app.id,
+                            "title": app.title,
+                            "prompt": app.prompt,
+                            "lastUsed": app.last_used_ms,
+                            "running": app.running,
+                        })
+                    })
+                    .collect();
+                Ok(Some(serde_json::Value::Array(apps)))
+            }
+            "lsp-agent.relaunch-app" => {
+                let Some(app_id) = params.arguments.first().and_then(|v| v.as_str()) else {
+                    return Err(Error::invalid_params("Expected an app id."));
+                };
+                self.agent
+                    .relaunch_app(app_id.to_string())
+                    .await
+                    .map_err(Error::invalid_params)?;
+                Ok(None)
+            }
             _ => Ok(None),
         }
     }
 }
 
+impl Backend {
+    /// For editors that do not report their active document, treats the document
+    /// the user last opened or typed in as active.
+    async fn infer_active_document(&self, uri: String) {
+        if self.explicit_active_document.load(Ordering::Relaxed) {
+            return;
+        }
+        {
+            let mut inferred = self.inferred_active_document.lock().unwrap();
+            if inferred.as_deref() == Some(uri.as_str()) {
+                return;
+            }
+            *inferred = Some(uri.clone());
+        }
+        self.agent.set_active_document(uri).await;
+    }
+
+    /// Appends a chat turn to the transcript and opens it in the editor, falling
+    /// back to a message for editors that cannot show documents.
+    async fn show_chat_turn(&self, message: &str, reply: &str) {
+        let written = match &self.chat_transcript {
+            Some(path) => {
+                let create = !self.chat_transcript_created.swap(true, Ordering::Relaxed);
+                let written = append_chat_turn(path, create, message, reply);
+                if written.is_err() && create {
+                    self.chat_transcript_created.store(false, Ordering::Relaxed);
+                }
+                written
+            }
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "no cache directory",
+            )),
+        };
+        if let Err(e) = &written {
+            self.client
+                .log_message(
+                    MessageType::WARNING,
+                    format!("Failed to write the chat transcript: {}", e),
+                )
+                .await;
+        }
+        let uri = self
+            .chat_transcript
+            .as_ref()
+            .and_then(|path| Url::from_file_path(path).ok());
+        if written.is_ok()
+            && self.show_document.load(Ordering::Relaxed)
+            && let Some(uri) = uri
+        {
+            let params = ShowDocumentParams {
+                uri,
+                external: Some(false),
+                take_focus: Some(true),
+                selection: None,
+            };
+            if let Ok(true) = self.client.show_document(params).await {
+                return;
+            }
+        }
+        self.client
+            .show_message(MessageType::INFO, reply.to_string())
+            .await;
+    }
+
+    async fn set_exposed_files(&self, patterns: Vec<String>) {
+        if let Err(e) = self.agent.set_exposed_files(patterns).await {
+            self.client
+                .show_message(MessageType::ERROR, format!("lspAgent.exposedFiles: {}", e))
+                .await;
+        }
+    }
+}
+
+/// Wraps an app's proposal in a workspace edit whose change annotation requires
+/// confirmation, so the editor previews the diff and lets the user decline it.
+fn workspace_edit(proposal: EditProposal) -> std::result::Result<WorkspaceEdit, String> {
+    let uri = Url::parse(&proposal.uri).map_err(|e| format!("Invalid uri: {}", e))?;
+    let annotation: ChangeAnnotationIdentifier = "lsp-agent-app-edit".to_string();
+    let edits = proposal
+        .edits
+        .into_iter()
+        .map(|edit| {
+            OneOf::Right(AnnotatedTextEdit {
+                text_edit: tower_lsp::lsp_types::TextEdit {
+                    range: Range {
+                        start: lsp_position(edit.range.start),
+                        end: lsp_position(edit.range.end),
+                    },
+                    new_text: edit.text,
+                },
+                annotation_id: annotation.clone(),
+            })
+        })
+        .collect();
+    Ok(WorkspaceEdit {
+        document_changes: Some(DocumentChanges::Edits(vec![TextDocumentEdit {
+            text_document: OptionalVersionedTextDocumentIdentifier {
+                uri,
+                version: proposal.version,
+            },
+            edits,
+        }])),
+        change_annotations: Some(
+            [(
+                annotation,
+                ChangeAnnotation {
+                    label: proposal.label,
+                    needs_confirmation: Some(true),
+                    description: Some("Review the edit before it is applied.".to_string()),
+                },
+            )]
+            .into_iter()
+            .collect(),
+        ),
+        ..Default::default()
+    })
+}
+
+fn lsp_position(position: TextPosition) -> Position {
+    Position {
+        line: position.line,
+        character: position.character,
+    }
+}
+
+/// Reads the `exposedFiles` glob list from the extension settings.
+fn exposed_file_patterns(settings: &serde_json::Value) -> Option<Vec<String>> {
+    let patterns = settings.get("exposedFiles")?.as_array()?;
+    Some(
+        patterns
+            .iter()
+            .filter_map(|pattern| pattern.as_str())
+            .map(|pattern| pattern.to_string())
+            .collect(),
+    )
+}
+
+fn text_position(position: Position) -> TextPosition {
+    TextPosition {
+        line: position.line,
+        character: position.character,
+    }
+}
+
+/// Appends a turn to the transcript. The first turn creates the file and its
+/// directory, readable only by the user; an existing file is never taken over.
+fn append_chat_turn(path: &Path, create: bool, message: &str, reply: &str) -> std::io::Result<()> {
+    if create && let Some(dir) = path.parent() {
+        let mut builder = std::fs::DirBuilder::new();
+        builder.recursive(true);
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+        builder.create(dir)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create_new(create)
+        .append(true)
+        .open(path)?;
+    write!(file, "## You\n\n{}\n\n## Agent\n\n{}\n\n", message, reply)
+}
+
+/// A transcript file of its own for this server, under the user's cache directory.
+fn chat_transcript_path() -> Option<PathBuf> {
+    let started = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis())
+        .unwrap_or_default();
+    let name = format!("chat-{}-{}.md", std::process::id(), started);
+    Some(dirs::cache_dir()?.join("lsp-agent").join(name))
+}
+
+/// Reads the `[uri, range]` arguments that code actions pass to their commands.
+fn selected_range(arguments: &[serde_json::Value]) -> Option<SelectedRange> {
+    let uri = arguments.first()?.as_str()?.to_string();
+    let range: Range = serde_json::from_value(arguments.get(1)?.clone()).ok()?;
+    Some(SelectedRange {
+        uri,
+        range: TextRange {
+            start: text_position(range.start),
+            end: text_position(range.end),
+        },
+    })
+}
+
+/// File system paths of the workspace folders, falling back to the root URI
+/// for clients that do not support multiple folders.
+#[allow(deprecated)]
+fn workspace_folder_paths(params: &InitializeParams) -> Vec<String> {
+    let uris: Vec<&Url> = match &params.workspace_folders {
+        Some(folders) if !folders.is_empty() => folders.iter().map(|folder| &folder.uri).collect(),
+        _ => params.root_uri.iter().collect(),
+    };
+    uris.into_iter()
+        .filter_map(|uri| uri.to_file_path().ok())
+        .map(|path| path.display().to_string())
+        .collect()
+}
+
+/// Parses one `{ "role": "user" | "assistant", "content": ... }` entry of the
+/// chat history sent along with `lsp-agent.log-chat`.
+fn parse_chat_turn(value: &serde_json::Value) -> Option<ChatTurn> {
+    let content = value.get("content")?.as_str()?.to_string();
+    match value.get("role")?.as_str()? {
+        "user" => Some(ChatTurn::User(content)),
+        "assistant" => Some(ChatTurn::Assistant(content)),
+        _ => None,
+    }
+}
+
 #[tokio::main]
 async fn main() {
     let stdin = tokio::io::stdin();
     let stdout = tokio::io::stdout();
 
     let (service, socket) = LspService::new(|client| {
+        let client_inference = Arc::new(AtomicBool::new(false));
+        let confirmed_edits = Arc::new(AtomicBool::new(false));
         let agent_client = Arc::new(LspAgentClient {
             client: client.clone(),
+            client_inference: client_inference.clone(),
+            builtin_inference: HttpInference::from_env(),
+            confirmed_edits: confirmed_edits.clone(),
         });
         let agent = start_infra(agent_client);
-        Backend { client, agent }
+        Backend {
+            client,
+            agent,
+            client_inference,
+            confirmed_edits,
+            show_document: AtomicBool::new(false),
+            explicit_active_document: AtomicBool::new(false),
+            inferred_active_document: std::sync::Mutex::new(None),
+            chat_transcript: chat_transcript_path(),
+            chat_transcript_created: AtomicBool::new(false),
+        }
     });
     Server::new(stdin, stdout, socket).serve(service).await;
 }
@@ -1,18 +1,18 @@
-use agent::start_web_backend;
+use agent::app_html::clean_html;
+use agent::pending_replies::PendingReplies;
+use agent::{start_web_backend, StoreValueBody};
 use async_trait::async_trait;
-use std::collections::{HashMap, VecDeque};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::thread;
 use tao::event::{Event, WindowEvent};
 use tao::event_loop::{ControlFlow, EventLoopBuilder};
 use tao::window::{Window, WindowId};
 use tokio::runtime::Runtime;
-use tokio::sync::{mpsc, Mutex};
+use tokio::sync::mpsc;
 use traits::{Web, WebAgent};
 use wry::{http, RequestAsyncResponder, WebView};
 
-use serde::Deserialize;
-
 #[derive(Debug)]
 enum AgentEvent {
     WebApp { id: String, content: String },
@@ -24,13 +24,6 @@ enum BackendCommand {
     CloseApp(String),
 }
 
-#[derive(Deserialize)]
-struct StoreValueBody {
-    key: String,
-    value: String,
-    description: String,
-}
-
 enum ApiRequest {
     Inference {
         content: String,
@@ -39,36 +32,87 @@ enum ApiRequest {
     },
     ReadDocument {
         uri: String,
+        app_id: String,
+        responder: RequestAsyncResponder,
+    },
+    ReadSelection {
+        uri: String,
+        app_id: String,
+        responder: RequestAsyncResponder,
+    },
+    ReadLaunchContext {
+        app_id: String,
         responder: RequestAsyncResponder,
     },
     StoreValue {
+        app_id: String,
         key: String,
         value: String,
         description: String,
         responder: RequestAsyncResponder,
     },
     ReadValue {
+        app_id: String,
         key: String,
         responder: RequestAsyncResponder,
     },
+    ReadWorkspaceFile {
+        app_id: String,
+        path: String,
+        responder: RequestAsyncResponder,
+    },
+    ListDirectory {
+        app_id: String,
+        path: String,
+        responder: RequestAsyncResponder,
+    },
+    ProposeEdit {
+        app_id: String,
+        body: String,
+        responder: RequestAsyncResponder,
+    },
+    PublishDiagnostics {
+        app_id: String,
+        body: String,
+        responder: RequestAsyncResponder,
+    },
+    PublishContributions {
+        app_id: String,
+        body: String,
+        responder: RequestAsyncResponder,
+    },
+    LogError {
+        app_id: String,
+        message: String,
+        responder: RequestAsyncResponder,
+    },
 }
 
 struct WebRuntime {
     proxy: tao::event_loop::EventLoopProxy<AgentEvent>,
-    pending_inference_requests: Mutex<HashMap<String, VecDeque<RequestAsyncResponder>>>,
+    pending_replies: PendingReplies<RequestAsyncResponder>,
 }
 
 impl WebRuntime {
     fn new(proxy: tao::event_loop::EventLoopProxy<AgentEvent>) -> Self {
         Self {
             proxy,
-            pending_inference_requests: Mutex::new(HashMap::new()),
+            pending_replies: PendingReplies::default(),
         }
     }
 
-    async fn enqueue_inference_request(&self, app_id: String, responder: RequestAsyncResponder) {
-        let mut pending = self.pending_inference_requests.lock().await;
-        pending.entry(app_id).or_default().push_back(responder);
+    /// A request id for a call whose reply arrives later. A reply that never
+    /// comes is answered with a timeout.
+    fn enqueue_reply(&self, responder: RequestAsyncResponder) -> String {
+        self.pending_replies.register(responder, |responder| {
+            responder.respond(
+                http::Response::builder()
+                    .header("Access-Control-Allow-Origin", "*")
+                    .status(504)
+                    .body(Vec::from("No reply arrived in time."))
+                    .unwrap(),
+            );
+        })
     }
 
     async fn notify_storage_update(&self, key: String) {
@@ -82,24 +126,17 @@ impl Web for WebRuntime {
         let _ = self.proxy.send_event(AgentEvent::WebApp { id, content });
     }
 
-    async fn handle_inference_response(&self, app_id: String, content: String) {
-        let mut pending = self.pending_inference_requests.lock().await;
-        if let Some(queue) = pending.get_mut(&app_id) {
-            if let Some(responder) = queue.pop_front() {
-                responder.respond(
-                    http::Response::builder()
-                        .header("Access-Control-Allow-Origin", "*")
-                        .body(Vec::from(content))
-                        .unwrap(),
-                );
-                if queue.is_empty() {
-                    pending.remove(&app_id);
-                }
-            } else {
-                eprintln!("Received Inference response but no pending responder!");
-            }
+    async fn handle_reply(&self, request_id: String, status: u16, content: String) {
+        if let Some(responder) = self.pending_replies.take(&request_id) {
+            responder.respond(
+                http::Response::builder()
+                    .header("Access-Control-Allow-Origin", "*")
+                    .status(status)
+                    .body(Vec::from(content))
+                    .unwrap(),
+            );
         } else {
-            eprintln!("Received Inference response for unknown app id: {}", app_id);
+            eprintln!("Received reply for unknown request id: {}", request_id);
         }
     }
 }
@@ -152,14 +189,17 @@ async fn handle_api_request(agent: &dyn WebAgent, req: ApiRequest, web_runtime:
             app_id,
             responder,
         } => {
-            let app_id_for_queue = app_id.clone();
-            agent.app_inference_request(content, app_id).await;
-            web_runtime
-                .enqueue_inference_request(app_id_for_queue, responder)
+            let request_id = web_runtime.enqueue_reply(responder);
+            agent
+                .app_inference_request(request_id, content, app_id)
                 .await;
         }
-        ApiRequest::ReadDocument { uri, responder } => {
-            let content = agent.read_document(uri).await;
+        ApiRequest::ReadDocument {
+            uri,
+            app_id,
+            responder,
+        } => {
+            let content = agent.read_document(app_id, uri).await;
             responder.respond(
                 http::Response::builder()
                     .header("Access-Control-Allow-Origin", "*")
@@ -167,13 +207,46 @@ async fn handle_api_request(agent: &dyn WebAgent, req: ApiRequest, web_runtime:
                     .unwrap(),
             );
         }
+        ApiRequest::ReadSelection {
+            uri,
+            app_id,
+            responder,
+        } => {
+            let response = match agent.read_selection(app_id, uri).await {
+                Some(json) => http::Response::builder()
+                    .header("Access-Control-Allow-Origin", "*")
+                    .header("Content-Type", "application/json")
+                    .body(Vec::from(json)),
+                None => http::Response::builder()
+                    .header("Access-Control-Allow-Origin", "*")
+                    .status(404)
+                    .body(Vec::new()),
+            };
+            responder.respond(response.unwrap());
+        }
+        ApiRequest::ReadLaunchContext { app_id, responder } => {
+            let response = match agent.read_launch_context(app_id).await {
+                Some(json) => http::Response::builder()
+                    .header("Access-Control-Allow-Origin", "*")
+                    .header("Content-Type", "application/json")
+                    .body(Vec::from(json)),
+                None => http::Response::builder()
+                    .header("Access-Control-Allow-Origin", "*")
+                    .status(404)
+                    .body(Vec::new()),
+            };
+            responder.respond(response.unwrap());
+        }
         ApiRequest::StoreValue {
+            app_id,
             key,
             value,
             description,
             responder,
         } => {
-            agent.store_value(key.clone(), value, description).await;
+            agent
+                .store_value(app_id, key.clone(), value, description)
+                .await;
             web_runtime.notify_storage_update(key).await;
             responder.respond(
                 http::Response::builder()
@@ -183,8 +256,12 @@ async fn handle_api_request(agent: &dyn WebAgent, req: ApiRequest, web_runtime:
                     .unwrap(),
             );
         }
-        ApiRequest::ReadValue { key, responder } => {
-            let value = agent.read_value(key).await;
+        ApiRequest::ReadValue {
+            app_id,
+            key,
+            responder,
+        } => {
+            let value = agent.read_value(app_id, key).await;
             let body = value.unwrap_or_default();
             responder.respond(
                 http::Response::builder()
@@ -193,6 +270,127 @@ async fn handle_api_request(agent: &dyn WebAgent, req: ApiRequest, web_runtime:
                     .unwrap(),
             );
         }
+        ApiRequest::ReadWorkspaceFile {
+            app_id,
+            path,
+            responder,
+        } => {
+            let request_id = web_runtime.enqueue_reply(responder);
+            agent.read_workspace_file(request_id, app_id, path).await;
+        }
+        ApiRequest::ListDirectory {
+            app_id,
+            path,
+            responder,
+        } => {
+            let request_id = web_runtime.enqueue_reply(responder);
+            agent.list_directory(request_id, app_id, path).await;
+        }
+        ApiRequest::ProposeEdit {
+            app_id,
+            body,
+            responder,
+        } => {
+            let request_id = web_runtime.enqueue_reply(responder);
+            let proposed = agent.propose_edit(request_id.clone(), app_id, body).await;
+            if let Err(message) = proposed {
+                if let Some(responder) = web_runtime.pending_replies.take(&request_id) {
+                    responder.respond(
+                        http::Response::builder()
+                            .header("Access-Control-Allow-Origin", "*")
+                            .status(400)
+                            .body(Vec::from(message))
+                            .unwrap(),
+                    );
+                }
+            }
+        }
+        ApiRequest::PublishDiagnostics {
+            app_id,
+            body,
+            responder,
+        } => {
+            let response = match agent.publish_diagnostics(app_id, body).await {
+                Ok(()) => http::Response::builder()
+                    .header("Access-Control-Allow-Origin", "*")
+                    .status(200)
+                    .body(Vec::new()),
+                Err(message) => http::Response::builder()
+                    .header("Access-Control-Allow-Origin", "*")
+                    .status(400)
+                    .body(Vec::from(message)),
+            };
+            responder.respond(response.unwrap());
+        }
+        ApiRequest::PublishContributions {
+            app_id,
+            body,
+            responder,
+        } => {
+            let response = match agent.publish_contributions(app_id, body).await {
+                Ok(()) => http::Response::builder()
+                    .header("Access-Control-Allow-Origin", "*")
+                    .status(200)
+                    .body(Vec::new()),
+                Err(message) => http::Response::builder()
+                    .header("Access-Control-Allow-Origin", "*")
+                    .status(400)
+                    .body(Vec::from(message)),
+            };
+            responder.respond(response.unwrap());
+        }
+        ApiRequest::LogError {
+            app_id,
+            message,
+            responder,
+        } => {
+            agent.log_error(app_id, message).await;
+            responder.respond(
+                http::Response::builder()
+                    .header("Access-Control-Allow-Origin", "*")
+                    .status(200)
+                    .body(Vec::new())
+                    .unwrap(),
+            );
+        }
+    }
+}
+
+impl ApiRequest {
+    fn into_responder(self) -> RequestAsyncResponder {
+        match self {
+            ApiRequest::Inference { responder, .. }
+            | ApiRequest::ReadDocument { responder, .. }
+            | ApiRequest::ReadSelection { responder, .. }
+            | ApiRequest::ReadLaunchContext { responder, .. }
+            | ApiRequest::StoreValue { responder, .. }
+            | ApiRequest::ReadValue { responder, .. }
+            | ApiRequest::ReadWorkspaceFile { responder, .. }
+            | ApiRequest::ListDirectory { responder, .. }
+            | ApiRequest::ProposeEdit { responder, .. }
+            | ApiRequest::PublishDiagnostics { responder, .. }
+            | ApiRequest::PublishContributions { responder, .. }
+            | ApiRequest::LogError { responder, .. } => responder,
+        }
+    }
+}
+
+fn respond_empty(responder: RequestAsyncResponder, status: u16) {
+    responder.respond(
+        http::Response::builder()
+            .header("Access-Control-Allow-Origin", "*")
+            .status(status)
+            .body(Vec::new())
+            .unwrap(),
+    );
+}
+
+/// Hands `request` to the backend thread, answering it with a 500 if the
+/// backend is gone.
+fn send_api_request(api_tx: &mpsc::Sender<ApiRequest>, request: ApiRequest) {
+    if let Err(e) = api_tx.blocking_send(request) {
+        eprintln!("[Web] Failed to send API request: {}", e);
+        respond_empty(e.0.into_responder(), 500);
     }
 }
 
@@ -226,18 +424,7 @@ fn main() {
                     .unwrap();
                 let id = window.id();
 
-                let clean_content = content.trim();
-                let clean_content = if let Some(stripped) = clean_content.strip_prefix("```html") {
-                    stripped
-                } else if let Some(stripped) = clean_content.strip_prefix("```") {
-                    stripped
-                } else {
-                    clean_content
-                };
-                let clean_content = clean_content
-                    .strip_suffix("```")
-                    .unwrap_or(clean_content)
-                    .trim();
+                let clean_content = clean_html(&content);
 
                 let api_tx = api_tx.clone();
                 let app_id_for_requests = app_id.clone();
@@ -245,111 +432,97 @@ fn main() {
                     .with_asynchronous_custom_protocol(
                         "wry".into(),
                         move |_webview_id, request, responder| {
-                            let api_tx = api_tx.clone();
-                            let app_id_for_requests = app_id_for_requests.clone();
+                            let app_id = app_id_for_requests.clone();
                             let uri = request.uri().clone();
-                            let body = request.body().clone();
+                            let body = String::from_utf8_lossy(request.body()).to_string();
                             eprintln!("[Web] Received custom protocol request: {}", uri);
-                            if uri.to_string().contains("inference") {
-                                let body_str = String::from_utf8_lossy(&body).to_string();
-                                eprintln!(
-                                    "[Web] Forwarding inference request: {} chars",
-                                    body_str.len()
-                                );
-                                if let Err(e) = api_tx.blocking_send(ApiRequest::Inference {
-                                    content: body_str,
-                                    app_id: app_id_for_requests,
-                                    responder,
-                                }) {
-                                    eprintln!("[Web] Failed to send API request: {}", e);
-                                    if let ApiRequest::Inference { responder, .. } = e.0 {
-                                        responder.respond(
-                                            http::Response::builder()
-                                                .status(500)
-                                                .body(Vec::new())
-                                                .unwrap(),
-                                        );
+                            // Windows serves custom protocols as `http://wry.<host>`.
+                            let host = uri.host().unwrap_or_default();
+                            let api_request = match host.strip_prefix("wry.").unwrap_or(host) {
+                                "inference" => {
+                                    eprintln!(
+                                        "[Web] Forwarding inference request: {} chars",
+                                        body.len()
+                                    );
+                                    ApiRequest::Inference {
+                                        content: body,
+                                        app_id,
+                                        responder,
                                     }
                                 }
-                            } else if uri.to_string().contains("document") {
-                                let body_str = String::from_utf8_lossy(&body).to_string();
-                                if let Err(e) = api_tx.blocking_send(ApiRequest::ReadDocument {
-                                    uri: body_str,
+                                "document" => ApiRequest::ReadDocument {
+                                    uri: body,
+                                    app_id,
                                     responder,
-                                }) {
-                                    eprintln!("[Web] Failed to send document request: {}", e);
-                                    if let ApiRequest::ReadDocument { responder, .. } = e.0 {
-                                        responder.respond(
-                                            http::Response::builder()
-                                                .status(500)
-                                                .body(Vec::new())
-                                                .unwrap(),
-                                        );
-                                    }
+                                },
+                                "selection" => ApiRequest::ReadSelection {
+                                    uri: body,
+                                    app_id,
+                                    responder,
+                                },
+                                "launch_context" => {
+                                    ApiRequest::ReadLaunchContext { app_id, responder }
                                 }
-                            } else if uri.to_string().contains("store_value") {
-                                let body_str = String::from_utf8_lossy(&body).to_string();
-                                match serde_json::from_str::<StoreValueBody>(&body_str) {
-                                    Ok(parsed) => {
-                                        if let Err(e) =
-                                            api_tx.blocking_send(ApiRequest::StoreValue {
-                                                key: parsed.key,
-                                                value: parsed.value,
-                                                description: parsed.description,
-                                                responder,
-                                            })
-                                        {
-                                            eprintln!(
-                                                "[Web] Failed to send store_value request: {}",
-                                                e
-                                            );
-                                            if let ApiRequest::StoreValue { responder, .. } = e.0 {
-                                                responder.respond(
-                                                    http::Response::builder()
-                                                        .status(500)
-                                                        .body(Vec::new())
-                                                        .unwrap(),
-                                                );
-                                            }
-                                        }
-                                    }
+                                "workspace_file" => ApiRequest::ReadWorkspaceFile {
+                                    app_id,
+                                    path: body,
+                                    responder,
+                                },
+                                "list_directory" => ApiRequest::ListDirectory {
+                                    app_id,
+                                    path: body,
+                                    responder,
+                                },
+                                "propose_edit" => ApiRequest::ProposeEdit {
+                                    app_id,
+                                    body,
+                                    responder,
+                                },
+                                "diagnostics" => ApiRequest::PublishDiagnostics {
+                                    app_id,
+                                    body,
+                                    responder,
+                                },
+                                "contributions" => ApiRequest::PublishContributions {
+                                    app_id,
+                                    body,
+                                    responder,
+                                },
+                                "store_value" => match serde_json::from_str::<StoreValueBody>(&body)
+                                {
+                                    Ok(parsed) => ApiRequest::StoreValue {
+                                        app_id,
+                                        key: parsed.key,
+                                        value: parsed.value,
+                                        description: parsed.description,
+                                        responder,
+                                    },
                                     Err(e) => {
                                         eprintln!("[Web] Failed to parse store_value body: {}", e);
-                                        responder.respond(
-                                            http::Response::builder()
-                                                .status(400)
-                                                .body(Vec::new())
-                                                .unwrap(),
-                                        );
-                                    }
-                                }
-                            } else if uri.to_string().contains("read_value") {
-                                let key = String::from_utf8_lossy(&body).to_string();
-                                if let Err(e) =
-                                    api_tx.blocking_send(ApiRequest::ReadValue { key, responder })
-                                {
-                                    eprintln!("[Web] Failed to send read_value request: {}", e);
-                                    if let ApiRequest::ReadValue { responder, .. } = e.0 {
-                                        responder.respond(
-                                            http::Response::builder()
-                                                .status(500)
-                                                .body(Vec::new())
-                                                .unwrap(),
-                                        );
+                                        respond_empty(responder, 400);
+                                        return;
                                     }
+                                },
+                                "read_value" => ApiRequest::ReadValue {
+                                    app_id,
+                                    key: body,
+                                    responder,
+                                },
+                                "log" => ApiRequest::LogError {
+                                    app_id,
+                                    message: body,
+                                    responder,
+                                },
+                                _ => {
+                                    eprintln!("[Web] Unknown URI: {}", uri);
+                                    respond_empty(responder, 404);
+                                    return;
                                 }
-                            } else {
-                                eprintln!("[Web] Unknown URI: {}", uri);
-                                responder.respond(
-                                    http::Response::builder()
-                                        .header("Access-Control-Allow-Origin", "*")
-                                        .status(404)
-                                        .body(Vec::new())
-                                        .unwrap(),
-                                );
-                            }
+                            };
+                            send_api_request(&api_tx, api_request);
                         },
                     )
+                    .with_initialization_script(agent::app_errors::REPORTER_SCRIPT)
                     .with_html(clean_content)
                     .build(&window)
                     .unwrap();